@@ -0,0 +1,188 @@
+//! Import of ani-cli's watch history.
+//!
+//! ani-cli keeps one line per show in its `ani-hsts` file: the last watched
+//! episode, the AllAnime ID and the title, separated by tabs. The IDs are the
+//! same ones anime-watcher uses, so shows need no matching by title.
+
+use crate::history::{WatchHistory, WatchRecord, UNKNOWN_TIME};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+
+/// Highest episode number taken from a line; anything above it is treated
+/// as a corrupt line rather than marking that many episodes watched.
+const MAX_EPISODE: i64 = 10_000;
+
+/// A show in ani-cli's history.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AniCliEntry {
+    /// Last watched episode.
+    pub episode: i64,
+    /// AllAnime show ID.
+    pub show_id: String,
+    /// Title of the show, without ani-cli's episode count.
+    pub title: String,
+}
+
+/// The contents of an `ani-hsts` file.
+#[derive(Debug, Default)]
+pub struct AniCliHistory {
+    /// Shows that could be read, in file order.
+    pub entries: Vec<AniCliEntry>,
+    /// Line numbers (from 1) and contents of lines that couldn't be read.
+    pub invalid: Vec<(usize, String)>,
+}
+
+/// Location of ani-cli's history file, as ani-cli itself resolves it.
+pub fn default_path() -> Option<PathBuf> {
+    let dir = match env::var_os("ANI_CLI_HIST_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| dirs::home_dir().map(|home| home.join(".local").join("state")))?
+            .join("ani-cli"),
+    };
+    Some(dir.join("ani-hsts"))
+}
+
+/// Remove the ` (N episodes)` suffix ani-cli adds to titles.
+fn strip_episode_count(title: &str) -> &str {
+    title
+        .strip_suffix(" episodes)")
+        .and_then(|rest| rest.rsplit_once(" ("))
+        .filter(|(_, count)| !count.is_empty() && count.bytes().all(|b| b.is_ascii_digit()))
+        .map_or(title, |(name, _)| name)
+}
+
+/// Parse an `ani-hsts` file.
+///
+/// Episodes that aren't whole numbers (recaps like `12.5`) can't be
+/// recorded and are reported as invalid along with malformed lines and
+/// implausibly high episode numbers.
+pub fn parse(content: &str) -> AniCliHistory {
+    let mut history = AniCliHistory::default();
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = line.splitn(3, '\t');
+        let (Some(episode), Some(show_id), Some(title)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            history.invalid.push((index + 1, line.to_string()));
+            continue;
+        };
+        let (show_id, title) = (show_id.trim(), strip_episode_count(title.trim()));
+        match episode.trim().parse::<i64>() {
+            Ok(episode) if (1..=MAX_EPISODE).contains(&episode) && !show_id.is_empty() => {
+                history.entries.push(AniCliEntry {
+                    episode,
+                    show_id: show_id.to_string(),
+                    title: title.to_string(),
+                })
+            }
+            _ => history.invalid.push((index + 1, line.to_string())),
+        }
+    }
+    history
+}
+
+/// Build watch history records from ani-cli entries.
+///
+/// Every episode up to the last watched one is marked watched in `mode`,
+/// stamped [`UNKNOWN_TIME`] since ani-cli doesn't record when each was
+/// watched. The records are dated `timestamp`, such as the file's
+/// modification time, to order them in the "continue" list.
+pub fn to_history(entries: &[AniCliEntry], mode: &str, timestamp: u64) -> WatchHistory {
+    let mut history = WatchHistory::new();
+    for entry in entries {
+        let record = WatchRecord {
+            show_id: entry.show_id.clone(),
+            show_name: entry.title.clone(),
+            episode: entry.episode,
+            mode: mode.to_string(),
+            timestamp,
+            watched: (1..=entry.episode).map(|ep| (ep, UNKNOWN_TIME)).collect(),
+            positions: BTreeMap::new(),
+            durations: BTreeMap::new(),
+            edited: 0,
+        };
+        let key = record.key();
+        // A show listed twice keeps its further episode
+        if history
+            .records
+            .get(&key)
+            .is_none_or(|existing| existing.episode < record.episode)
+        {
+            history.records.insert(key, record);
+        }
+    }
+    history
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HISTORY: &str = "5\tReooPAxPMsHM4KPMY\tSousou no Frieren (28 episodes)\n\
+                           12\tcstcbG4EquLyDnAwN\tBocchi the Rock! (12 episodes)\n\
+                           12.5\tSJms742bSTrcyJZay\tSome Show (13 episodes)\n\
+                           not a history line\n\
+                           99999999999\tReooPAxPMsHM4KPMY\tCorrupt\n";
+
+    #[test]
+    fn test_parse() {
+        let history = parse(HISTORY);
+        assert_eq!(history.entries.len(), 2);
+        assert_eq!(
+            history.entries[0],
+            AniCliEntry {
+                episode: 5,
+                show_id: "ReooPAxPMsHM4KPMY".to_string(),
+                title: "Sousou no Frieren".to_string(),
+            }
+        );
+        assert_eq!(history.entries[1].title, "Bocchi the Rock!");
+        let lines: Vec<usize> = history.invalid.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn test_strip_episode_count() {
+        assert_eq!(strip_episode_count("Frieren (28 episodes)"), "Frieren");
+        assert_eq!(strip_episode_count("86 (Part 2)"), "86 (Part 2)");
+        assert_eq!(strip_episode_count("Odd ( episodes)"), "Odd ( episodes)");
+    }
+
+    #[test]
+    fn test_to_history() {
+        let entries = parse(HISTORY).entries;
+        let history = to_history(&entries, "sub", 1_700_000_000);
+        let record = history.get_record("ReooPAxPMsHM4KPMY", "sub").unwrap();
+        assert_eq!(record.episode, 5);
+        assert_eq!(record.timestamp, 1_700_000_000);
+        assert_eq!(record.furthest_watched(), Some(5));
+        assert!((1..=5).all(|ep| record.is_watched(ep)));
+        assert_eq!(record.watched.get(&1), Some(&UNKNOWN_TIME));
+        assert!(history.get_record("ReooPAxPMsHM4KPMY", "dub").is_none());
+    }
+
+    #[test]
+    fn test_merge_keeps_newer_progress() {
+        let entries = parse(HISTORY).entries;
+        let mut history = WatchHistory::new();
+        history.update("ReooPAxPMsHM4KPMY", "Frieren", 8, "sub");
+        let ours = history
+            .get_record("ReooPAxPMsHM4KPMY", "sub")
+            .unwrap()
+            .timestamp;
+
+        history.merge(&to_history(&entries, "sub", ours - 100));
+        let record = history.get_record("ReooPAxPMsHM4KPMY", "sub").unwrap();
+        // Our later play stays the last played episode, with ani-cli's
+        // episodes filled in before it
+        assert_eq!(record.episode, 8);
+        assert!((1..=5).all(|ep| record.is_watched(ep)));
+        assert!(history.get_record("cstcbG4EquLyDnAwN", "sub").is_some());
+    }
+}
@@ -0,0 +1,931 @@
+//! AniList progress sync.
+//!
+//! AllAnime shows are mapped to AniList media IDs by title and start year,
+//! or by hand with `anime-watcher anilist map`. Progress is pushed to the
+//! user's list as episodes are watched, and `anime-watcher anilist pull`
+//! seeds the watch history from it. The GraphQL endpoint is configurable
+//! (`anilist_api_url`) so the sync can be tried against a local mock server.
+
+use crate::api::fetch_show_details;
+use crate::paths;
+use crate::persist;
+use log::debug;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc;
+
+/// The public AniList GraphQL endpoint.
+pub const DEFAULT_API_URL: &str = "https://graphql.anilist.co";
+
+/// Page that asks the user to authorize an API client.
+const AUTHORIZE_URL: &str = "https://anilist.co/api/v2/oauth/authorize";
+
+/// How often a rate-limited request is retried.
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+
+/// Link that lets the user authorize `client_id` and shows them a token.
+///
+/// # Examples
+///
+/// ```
+/// use anime_watcher::anilist::authorize_url;
+///
+/// assert_eq!(
+///     authorize_url("1234"),
+///     "https://anilist.co/api/v2/oauth/authorize?client_id=1234&response_type=token"
+/// );
+/// ```
+pub fn authorize_url(client_id: &str) -> String {
+    format!(
+        "{}?client_id={}&response_type=token",
+        AUTHORIZE_URL, client_id
+    )
+}
+
+/// Page of a media entry on the AniList website.
+pub fn media_url(media_id: i64) -> String {
+    format!("https://anilist.co/anime/{}", media_id)
+}
+
+/// Titles of an AniList media entry.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct MediaTitle {
+    /// Romanized title.
+    #[serde(default)]
+    pub romaji: Option<String>,
+    /// English title.
+    #[serde(default)]
+    pub english: Option<String>,
+    /// Title in the original language.
+    #[serde(default)]
+    pub native: Option<String>,
+}
+
+impl MediaTitle {
+    /// Every known title, romanized first.
+    pub fn all(&self) -> impl Iterator<Item = &str> {
+        [&self.romaji, &self.english, &self.native]
+            .into_iter()
+            .filter_map(|t| t.as_deref())
+    }
+
+    /// The title to show the user.
+    pub fn preferred(&self) -> String {
+        self.english
+            .as_deref()
+            .or(self.romaji.as_deref())
+            .or(self.native.as_deref())
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+/// A possibly partial date.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct FuzzyDate {
+    /// Year, if known.
+    #[serde(default)]
+    pub year: Option<i32>,
+}
+
+/// The user's list entry for a media.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ListProgress {
+    /// Episodes watched.
+    #[serde(default)]
+    pub progress: i64,
+    /// List status, such as `CURRENT` or `COMPLETED`.
+    pub status: String,
+}
+
+/// An anime on AniList.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Media {
+    /// AniList media ID.
+    pub id: i64,
+    /// MyAnimeList ID, if known.
+    #[serde(default, rename = "idMal")]
+    pub id_mal: Option<i64>,
+    /// Titles of the anime.
+    #[serde(default)]
+    pub title: MediaTitle,
+    /// Number of episodes, if known.
+    #[serde(default)]
+    pub episodes: Option<i64>,
+    /// Date the anime started airing.
+    #[serde(default, rename = "startDate")]
+    pub start_date: Option<FuzzyDate>,
+    /// The user's list entry, when requested with a token.
+    #[serde(default, rename = "mediaListEntry")]
+    pub list_entry: Option<ListProgress>,
+}
+
+impl Media {
+    /// Year the anime started airing.
+    pub fn year(&self) -> Option<i32> {
+        self.start_date.as_ref().and_then(|d| d.year)
+    }
+}
+
+/// An entry of the user's anime list.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ListEntry {
+    /// Episodes watched.
+    #[serde(default)]
+    pub progress: i64,
+    /// List status, such as `CURRENT` or `PLANNING`.
+    pub status: String,
+    /// The anime.
+    pub media: Media,
+}
+
+/// The logged-in user.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Viewer {
+    /// AniList user ID.
+    pub id: i64,
+    /// User name.
+    pub name: String,
+}
+
+/// Result of pushing progress for one show.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PushOutcome {
+    /// The list entry was updated.
+    Updated {
+        /// Episodes watched.
+        progress: i64,
+        /// New list status.
+        status: String,
+    },
+    /// AniList already had the same or more progress.
+    UpToDate,
+}
+
+/// Lowercase a title and drop everything but letters and digits, so
+/// punctuation and spacing differences don't matter.
+fn normalize(title: &str) -> String {
+    title
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Whether two titles are the same apart from case and punctuation.
+pub fn same_title(a: &str, b: &str) -> bool {
+    let a = normalize(a);
+    !a.is_empty() && a == normalize(b)
+}
+
+/// Pick the search result matching one of `titles`.
+///
+/// Only exact title matches count, and when both years are known they must
+/// agree; sequels and remakes often share a title. Returns `None` rather
+/// than guessing, so the show can be mapped by hand.
+pub fn best_media<'a>(
+    candidates: &'a [Media],
+    titles: &[&str],
+    year: Option<i32>,
+) -> Option<&'a Media> {
+    candidates
+        .iter()
+        .filter(|m| match (year, m.year()) {
+            (Some(ours), Some(theirs)) => ours == theirs,
+            _ => true,
+        })
+        .find(|m| {
+            m.title
+                .all()
+                .any(|t| titles.iter().any(|ours| same_title(ours, t)))
+        })
+}
+
+/// List status to set after watching `progress` episodes.
+///
+/// # Examples
+///
+/// ```
+/// use anime_watcher::anilist::list_status;
+///
+/// assert_eq!(list_status(3, Some(12), None), "CURRENT");
+/// assert_eq!(list_status(12, Some(12), Some("CURRENT")), "COMPLETED");
+/// assert_eq!(list_status(3, None, Some("REPEATING")), "REPEATING");
+/// ```
+pub fn list_status(progress: i64, episodes: Option<i64>, current: Option<&str>) -> &'static str {
+    if episodes.is_some_and(|total| total > 0 && progress >= total) {
+        "COMPLETED"
+    } else if current == Some("REPEATING") {
+        "REPEATING"
+    } else {
+        "CURRENT"
+    }
+}
+
+/// A GraphQL response.
+#[derive(Debug, Deserialize)]
+struct GraphQlResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphQlError {
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ViewerData {
+    #[serde(rename = "Viewer")]
+    viewer: Viewer,
+}
+
+#[derive(Debug, Deserialize)]
+struct MediaData {
+    #[serde(rename = "Media")]
+    media: Media,
+}
+
+#[derive(Debug, Deserialize)]
+struct PageData {
+    #[serde(rename = "Page")]
+    page: MediaPage,
+}
+
+#[derive(Debug, Deserialize)]
+struct MediaPage {
+    media: Vec<Media>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CollectionData {
+    #[serde(rename = "MediaListCollection")]
+    collection: Collection,
+}
+
+#[derive(Debug, Deserialize)]
+struct Collection {
+    lists: Vec<CollectionList>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CollectionList {
+    entries: Vec<ListEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SaveData {
+    #[serde(rename = "SaveMediaListEntry")]
+    entry: ListProgress,
+}
+
+/// Fields requested for every media.
+const MEDIA_FIELDS: &str = "id idMal episodes startDate { year } title { romaji english native }";
+
+/// Client for the AniList GraphQL API.
+#[derive(Debug, Clone)]
+pub struct AniList {
+    api_url: String,
+    token: Option<String>,
+    client: reqwest::Client,
+}
+
+impl AniList {
+    /// Create a client for `api_url`, authenticated with `token` if given.
+    pub fn new(api_url: &str, token: Option<String>) -> Result<Self, Box<dyn Error>> {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("anime-watcher/", env!("CARGO_PKG_VERSION")))
+            .timeout(Duration::from_secs(30))
+            .build()?;
+        Ok(Self {
+            api_url: api_url.to_string(),
+            token,
+            client,
+        })
+    }
+
+    /// Run a GraphQL query.
+    ///
+    /// Rate-limited requests are retried after the delay AniList asks for.
+    async fn request<T: DeserializeOwned>(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> Result<T, Box<dyn Error>> {
+        let body = json!({ "query": query, "variables": variables });
+        let mut attempt = 0;
+        let resp = loop {
+            let mut request = self.client.post(&self.api_url).json(&body);
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+            let resp = request.send().await?;
+            if resp.status() != reqwest::StatusCode::TOO_MANY_REQUESTS
+                || attempt == MAX_RATE_LIMIT_RETRIES
+            {
+                break resp;
+            }
+            attempt += 1;
+            let wait = resp
+                .headers()
+                .get("Retry-After")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(60);
+            debug!("AniList rate limit reached; waiting {}s", wait);
+            tokio::time::sleep(Duration::from_secs(wait)).await;
+        };
+
+        let status = resp.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            return Err(
+                "AniList rejected the access token; run `anime-watcher anilist login` again".into(),
+            );
+        }
+        let parsed: GraphQlResponse<T> = resp
+            .json()
+            .await
+            .map_err(|e| format!("Invalid AniList response ({}): {}", status, e))?;
+        if let Some(error) = parsed.errors.first() {
+            return Err(format!("AniList: {}", error.message).into());
+        }
+        parsed
+            .data
+            .ok_or_else(|| format!("AniList returned no data ({})", status).into())
+    }
+
+    /// Get the logged-in user.
+    pub async fn viewer(&self) -> Result<Viewer, Box<dyn Error>> {
+        let data: ViewerData = self
+            .request("query { Viewer { id name } }", json!({}))
+            .await?;
+        Ok(data.viewer)
+    }
+
+    /// Search for anime by title.
+    pub async fn search(&self, title: &str) -> Result<Vec<Media>, Box<dyn Error>> {
+        let query = format!(
+            "query ($search: String) {{ Page(perPage: 10) {{ media(search: $search, type: ANIME) {{ {} }} }} }}",
+            MEDIA_FIELDS
+        );
+        let data: PageData = self.request(&query, json!({ "search": title })).await?;
+        Ok(data.page.media)
+    }
+
+    /// Get an anime, with the user's list entry when logged in.
+    pub async fn media(&self, media_id: i64) -> Result<Media, Box<dyn Error>> {
+        let entry = if self.token.is_some() {
+            "mediaListEntry { progress status }"
+        } else {
+            ""
+        };
+        let query = format!(
+            "query ($id: Int) {{ Media(id: $id, type: ANIME) {{ {} {} }} }}",
+            MEDIA_FIELDS, entry
+        );
+        let data: MediaData = self.request(&query, json!({ "id": media_id })).await?;
+        Ok(data.media)
+    }
+
+    /// Get every entry of a user's anime list.
+    pub async fn list(&self, user_id: i64) -> Result<Vec<ListEntry>, Box<dyn Error>> {
+        let query = format!(
+            "query ($userId: Int) {{ MediaListCollection(userId: $userId, type: ANIME) {{ \
+             lists {{ entries {{ progress status media {{ {} }} }} }} }} }}",
+            MEDIA_FIELDS
+        );
+        let data: CollectionData = self.request(&query, json!({ "userId": user_id })).await?;
+        Ok(data
+            .collection
+            .lists
+            .into_iter()
+            .flat_map(|list| list.entries)
+            .collect())
+    }
+
+    /// Set the progress of an anime on the user's list.
+    ///
+    /// Progress is never moved backwards, so episodes watched elsewhere
+    /// aren't lost; the status becomes `COMPLETED` with the last episode.
+    pub async fn push_progress(
+        &self,
+        media_id: i64,
+        progress: i64,
+    ) -> Result<PushOutcome, Box<dyn Error>> {
+        let media = self.media(media_id).await?;
+        let current = media.list_entry.as_ref();
+        if current.is_some_and(|entry| entry.progress >= progress) {
+            return Ok(PushOutcome::UpToDate);
+        }
+
+        let status = list_status(
+            progress,
+            media.episodes,
+            current.map(|entry| entry.status.as_str()),
+        );
+        let query = "mutation ($mediaId: Int, $progress: Int, $status: MediaListStatus) { \
+                     SaveMediaListEntry(mediaId: $mediaId, progress: $progress, status: $status) \
+                     { progress status } }";
+        let data: SaveData = self
+            .request(
+                query,
+                json!({ "mediaId": media_id, "progress": progress, "status": status }),
+            )
+            .await?;
+        Ok(PushOutcome::Updated {
+            progress: data.entry.progress,
+            status: data.entry.status,
+        })
+    }
+
+    /// Find the AniList entry of an AllAnime show.
+    ///
+    /// Uses the saved mapping if there is one; otherwise the show's titles
+    /// and start year are looked up on AllAnime and searched for on AniList,
+    /// and a confident match is added to `mappings`.
+    pub async fn map_show(
+        &self,
+        mappings: &mut Mappings,
+        show_id: &str,
+    ) -> Result<Option<Mapping>, Box<dyn Error>> {
+        if let Some(mapping) = mappings.shows.get(show_id) {
+            return Ok(Some(mapping.clone()));
+        }
+
+        let details = fetch_show_details(show_id).await?;
+        let year = details.aired_start.as_ref().and_then(|d| d.year);
+        let titles: Vec<&str> = [Some(&details.name), details.english_name.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+
+        for title in &titles {
+            let candidates = self.search(title).await?;
+            if let Some(media) = best_media(&candidates, &titles, year) {
+                let mapping = Mapping::from_media(media, false);
+                mappings.shows.insert(show_id.to_string(), mapping.clone());
+                return Ok(Some(mapping));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// The AniList entry an AllAnime show is mapped to.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Mapping {
+    /// AniList media ID.
+    pub media_id: i64,
+    /// AniList title, for display.
+    pub title: String,
+    /// Whether the mapping was set by hand.
+    #[serde(default)]
+    pub manual: bool,
+    /// Unix timestamp of when the mapping was made.
+    #[serde(default)]
+    pub mapped_at: u64,
+}
+
+impl Mapping {
+    /// Mapping to `media`.
+    pub fn from_media(media: &Media, manual: bool) -> Self {
+        Self {
+            media_id: media.id,
+            title: media.title.preferred(),
+            manual,
+            mapped_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+        }
+    }
+}
+
+/// AllAnime show IDs mapped to AniList media.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Mappings {
+    /// Map of show_id to AniList entry.
+    pub shows: HashMap<String, Mapping>,
+    /// Shows whose mapping was dropped, with the Unix timestamp.
+    ///
+    /// Kept so other instances don't save dropped mappings back.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub removed: HashMap<String, u64>,
+}
+
+impl Mappings {
+    /// Create an empty set of mappings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the path to the mappings file, next to the watch history.
+    pub fn get_mappings_path() -> Result<PathBuf, io::Error> {
+        Ok(paths::data_dir()?.join("anilist.json"))
+    }
+
+    /// Load the mappings from disk.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        Self::load_from(&Self::get_mappings_path()?)
+    }
+
+    /// Load the mappings from a specific file.
+    pub fn load_from(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(persist::load_json(path)?.unwrap_or_else(Self::new))
+    }
+
+    /// Save the mappings to disk.
+    ///
+    /// Mappings saved by other running instances since these were loaded
+    /// are merged in rather than overwritten.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        self.save_to(&Self::get_mappings_path()?)
+    }
+
+    /// Save the mappings to a specific file, merging with its contents.
+    pub fn save_to(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        persist::save_json(path, self, Self::merge)
+    }
+
+    /// Merge other mappings into these.
+    ///
+    /// For shows in both, the more recent mapping wins, and mappings dropped
+    /// since they were made are left out.
+    pub fn merge(&mut self, other: &Mappings) {
+        for (show_id, theirs) in &other.shows {
+            match self.shows.get(show_id) {
+                Some(ours) if ours.mapped_at > theirs.mapped_at => {}
+                _ => {
+                    self.shows.insert(show_id.clone(), theirs.clone());
+                }
+            }
+        }
+
+        for (show_id, removed_at) in &other.removed {
+            let entry = self.removed.entry(show_id.clone()).or_insert(*removed_at);
+            *entry = (*entry).max(*removed_at);
+        }
+        let removed = &self.removed;
+        self.shows.retain(|show_id, mapping| {
+            removed
+                .get(show_id)
+                .is_none_or(|removed_at| mapping.mapped_at > *removed_at)
+        });
+    }
+
+    /// Drop the mapping of a show, returning it if there was one.
+    pub fn remove(&mut self, show_id: &str) -> Option<Mapping> {
+        let mapping = self.shows.remove(show_id)?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.removed.insert(show_id.to_string(), timestamp);
+        Some(mapping)
+    }
+
+    /// The show mapped to an AniList media ID.
+    pub fn show_for(&self, media_id: i64) -> Option<&str> {
+        self.shows
+            .iter()
+            .find(|(_, m)| m.media_id == media_id)
+            .map(|(show_id, _)| show_id.as_str())
+    }
+}
+
+/// Get the path to the stored access token of the profile in use.
+pub fn token_path() -> Result<PathBuf, io::Error> {
+    Ok(paths::profile_data_dir()?.join("anilist-token"))
+}
+
+/// Load the stored access token, if logged in.
+pub fn load_token() -> io::Result<Option<String>> {
+    load_token_from(&token_path()?)
+}
+
+/// Load an access token from a specific file.
+///
+/// A token file readable by other users is made private first.
+pub fn load_token_from(path: &Path) -> io::Result<Option<String>> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(metadata) = fs::metadata(path) {
+            if metadata.permissions().mode() & 0o077 != 0 {
+                fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+    }
+    Ok(persist::read(path)?
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty()))
+}
+
+/// Store the access token.
+pub fn save_token(token: &str) -> io::Result<()> {
+    save_token_to(&token_path()?, token)
+}
+
+/// Store an access token in a specific file, readable only by the user.
+///
+/// Written without [`persist::write_atomic`] so no backup copies of the
+/// token are left behind.
+pub fn save_token_to(path: &Path, token: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let temp = path.with_extension("tmp");
+    let mut file = options.open(&temp)?;
+    file.write_all(token.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&temp, path)
+}
+
+/// Forget the stored access token.
+///
+/// Returns whether there was one.
+pub fn delete_token() -> io::Result<bool> {
+    match fs::remove_file(token_path()?) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Pushes progress to AniList in the background while the TUI runs.
+pub struct ProgressSync {
+    client: AniList,
+    message_tx: mpsc::UnboundedSender<String>,
+    message_rx: mpsc::UnboundedReceiver<String>,
+}
+
+impl ProgressSync {
+    /// Create a sync that pushes with `client`.
+    pub fn new(client: AniList) -> Self {
+        let (message_tx, message_rx) = mpsc::unbounded_channel();
+        Self {
+            client,
+            message_tx,
+            message_rx,
+        }
+    }
+
+    /// Push that `progress` episodes of a show have been watched.
+    ///
+    /// Runs in the background; the outcome is reported through
+    /// [`try_next_message`](Self::try_next_message).
+    pub fn push(&self, show_id: &str, show_name: &str, progress: i64) {
+        let client = self.client.clone();
+        let tx = self.message_tx.clone();
+        let (show_id, show_name) = (show_id.to_string(), show_name.to_string());
+        tokio::spawn(async move {
+            let message = match push_show(&client, &show_id, progress).await {
+                Ok(Some(PushOutcome::Updated { progress, .. })) => Some(format!(
+                    "AniList: {} updated to episode {}",
+                    show_name, progress
+                )),
+                Ok(Some(PushOutcome::UpToDate)) => None,
+                Ok(None) => Some(format!(
+                    "AniList: no match for {}; map it with `anime-watcher anilist map`",
+                    show_name
+                )),
+                Err(e) => Some(format!("AniList sync failed: {}", e)),
+            };
+            if let Some(message) = message {
+                let _ = tx.send(message);
+            }
+        });
+    }
+
+    /// Get the next outcome message without waiting.
+    pub fn try_next_message(&mut self) -> Option<String> {
+        self.message_rx.try_recv().ok()
+    }
+}
+
+/// Map a show and push its progress, saving a new mapping.
+///
+/// Returns `None` if the show couldn't be matched.
+async fn push_show(
+    client: &AniList,
+    show_id: &str,
+    progress: i64,
+) -> Result<Option<PushOutcome>, Box<dyn Error>> {
+    let mut mappings = Mappings::load()?;
+    let known = mappings.shows.contains_key(show_id);
+    let Some(mapping) = client.map_show(&mut mappings, show_id).await? else {
+        return Ok(None);
+    };
+    if !known {
+        mappings.save()?;
+    }
+    Ok(Some(
+        client.push_progress(mapping.media_id, progress).await?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn media(id: i64, romaji: &str, english: Option<&str>, year: Option<i32>) -> Media {
+        Media {
+            id,
+            id_mal: None,
+            title: MediaTitle {
+                romaji: Some(romaji.to_string()),
+                english: english.map(|e| e.to_string()),
+                native: None,
+            },
+            episodes: Some(12),
+            start_date: Some(FuzzyDate { year }),
+            list_entry: None,
+        }
+    }
+
+    /// Answer one HTTP request per response, returning the requests received.
+    fn mock_server(responses: Vec<&'static str>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    request.push_str(&line);
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                request.push_str(&String::from_utf8(body).unwrap());
+                requests.push(request);
+
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_best_media() {
+        let candidates = [
+            media(1, "Sousou no Frieren 2nd Season", None, Some(2026)),
+            media(2, "Hunter x Hunter", None, Some(1999)),
+            media(
+                3,
+                "Hunter x Hunter (2011)",
+                Some("Hunter x Hunter"),
+                Some(2011),
+            ),
+        ];
+        assert_eq!(
+            best_media(&candidates, &["Hunter x Hunter"], Some(2011)).map(|m| m.id),
+            Some(3)
+        );
+        assert_eq!(
+            best_media(&candidates, &["hunter × hunter", "HUNTER X HUNTER"], None).map(|m| m.id),
+            Some(2)
+        );
+        assert!(best_media(&candidates, &["Sousou no Frieren"], None).is_none());
+    }
+
+    #[test]
+    fn test_merge_mappings() {
+        let mut saved = Mappings::new();
+        saved.shows.insert(
+            "a".to_string(),
+            Mapping::from_media(&media(1, "A", None, None), false),
+        );
+        saved.shows.insert(
+            "b".to_string(),
+            Mapping::from_media(&media(2, "B", None, None), false),
+        );
+
+        // Another instance remapped one show by hand and dropped another
+        let mut ours = saved.clone();
+        let mut manual = Mapping::from_media(&media(3, "A2", None, None), true);
+        manual.mapped_at += 10;
+        ours.shows.insert("a".to_string(), manual);
+        ours.remove("b");
+        // while this one matched a new show
+        saved.shows.insert(
+            "c".to_string(),
+            Mapping::from_media(&media(4, "C", None, None), false),
+        );
+
+        saved.merge(&ours);
+        assert_eq!(saved.shows["a"].media_id, 3);
+        assert!(!saved.shows.contains_key("b"));
+        assert_eq!(saved.shows["c"].media_id, 4);
+    }
+
+    #[tokio::test]
+    async fn test_push_progress_against_mock_server() {
+        let (url, server) = mock_server(vec![
+            r#"{"data":{"Media":{"id":7,"episodes":12,"title":{"romaji":"Show"},
+               "mediaListEntry":{"progress":3,"status":"CURRENT"}}}}"#,
+            r#"{"data":{"SaveMediaListEntry":{"progress":12,"status":"COMPLETED"}}}"#,
+        ]);
+        let client = AniList::new(&url, Some("secret".to_string())).unwrap();
+
+        let outcome = client.push_progress(7, 12).await.unwrap();
+        assert_eq!(
+            outcome,
+            PushOutcome::Updated {
+                progress: 12,
+                status: "COMPLETED".to_string()
+            }
+        );
+
+        let requests = server.join().unwrap();
+        assert!(requests[0]
+            .to_lowercase()
+            .contains("authorization: bearer secret"));
+        assert!(requests[1].contains(r#""progress":12"#));
+        assert!(requests[1].contains(r#""status":"COMPLETED""#));
+    }
+
+    #[tokio::test]
+    async fn test_push_progress_never_goes_back() {
+        let (url, server) = mock_server(vec![
+            r#"{"data":{"Media":{"id":7,"episodes":12,"title":{"romaji":"Show"},
+               "mediaListEntry":{"progress":8,"status":"CURRENT"}}}}"#,
+        ]);
+        let client = AniList::new(&url, Some("secret".to_string())).unwrap();
+
+        assert_eq!(
+            client.push_progress(7, 5).await.unwrap(),
+            PushOutcome::UpToDate
+        );
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_graphql_errors() {
+        let (url, server) = mock_server(vec![
+            r#"{"data":null,"errors":[{"message":"Invalid token","status":400}]}"#,
+        ]);
+        let client = AniList::new(&url, Some("bad".to_string())).unwrap();
+
+        let err = client.viewer().await.unwrap_err();
+        assert_eq!(err.to_string(), "AniList: Invalid token");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_token_file_is_private() {
+        let dir = std::env::temp_dir().join(format!("aw-anilist-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("anilist-token");
+
+        assert_eq!(load_token_from(&path).unwrap(), None);
+        save_token_to(&path, "secret\n").unwrap();
+        assert_eq!(load_token_from(&path).unwrap().as_deref(), Some("secret"));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
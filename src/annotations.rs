@@ -0,0 +1,404 @@
+//! Personal scores, notes and tags attached to shows.
+//!
+//! Annotations belong to a show rather than to a watch record, so they are
+//! shared by sub and dub and survive the show being removed from the history
+//! or the watchlist. They are kept in `annotations.json` next to the history.
+
+use crate::paths;
+use crate::persist;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Lowest score a show can be given.
+pub const MIN_SCORE: u8 = 1;
+
+/// Highest score a show can be given.
+pub const MAX_SCORE: u8 = 10;
+
+/// What the user noted about a show.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Annotation {
+    /// Unique identifier for the show.
+    pub show_id: String,
+    /// Display name of the show.
+    pub show_name: String,
+    /// Score from 1 to 10.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<u8>,
+    /// Free-form notes.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    /// Custom tags, such as "rewatch" or "with friends".
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    /// Unix timestamp of the last change.
+    pub updated_at: u64,
+}
+
+impl Annotation {
+    /// Whether nothing is noted about the show.
+    pub fn is_empty(&self) -> bool {
+        self.score.is_none() && self.notes.is_empty() && self.tags.is_empty()
+    }
+
+    /// The tags, separated by commas.
+    pub fn tags_text(&self) -> String {
+        self.tags.iter().cloned().collect::<Vec<_>>().join(", ")
+    }
+
+    /// Whether the show has a tag, or a tag with `tag` as one of its
+    /// words, ignoring case.
+    fn has_tag(&self, tag: &str) -> bool {
+        let tag = tag.to_lowercase();
+        self.tags.iter().any(|t| {
+            let t = t.to_lowercase();
+            t == tag || t.split_whitespace().any(|word| word == tag)
+        })
+    }
+}
+
+/// Current Unix timestamp.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Parse a score typed by the user. An empty input clears the score.
+pub fn parse_score(input: &str) -> Result<Option<u8>, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(None);
+    }
+    match input.parse::<u8>() {
+        Ok(score) if (MIN_SCORE..=MAX_SCORE).contains(&score) => Ok(Some(score)),
+        _ => Err(format!(
+            "Score must be a number from {} to {}",
+            MIN_SCORE, MAX_SCORE
+        )),
+    }
+}
+
+/// Parse comma-separated tags, dropping empty ones.
+pub fn parse_tags(input: &str) -> BTreeSet<String> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Annotations of all shows.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Annotations {
+    /// Map of show_id to annotation.
+    pub shows: HashMap<String, Annotation>,
+    /// Shows whose annotation was cleared, with the Unix timestamp.
+    ///
+    /// Kept so other instances don't save cleared annotations back.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub removed: HashMap<String, u64>,
+}
+
+impl Annotations {
+    /// Create an empty set of annotations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the path to the annotations, next to the watch history.
+    pub fn get_annotations_path() -> Result<PathBuf, io::Error> {
+        Ok(paths::profile_data_dir()?.join("annotations.json"))
+    }
+
+    /// Load the annotations from disk.
+    ///
+    /// Returns no annotations if the file doesn't exist.
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::load_from(&Self::get_annotations_path()?)
+    }
+
+    /// Load the annotations from a specific file.
+    pub fn load_from(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(persist::load_json(path)?.unwrap_or_else(Self::new))
+    }
+
+    /// Find the newest backup of the annotations that can be parsed.
+    pub fn newest_backup(path: &Path) -> Option<(PathBuf, Self)> {
+        persist::newest_json_backup(path)
+    }
+
+    /// Save the annotations to disk.
+    ///
+    /// Changes saved by other running instances since the annotations were
+    /// loaded are merged in rather than overwritten.
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.save_to(&Self::get_annotations_path()?)
+    }
+
+    /// Save the annotations to a specific file, merging with its contents.
+    pub fn save_to(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        persist::save_json(path, self, Self::merge)
+    }
+
+    /// Merge other annotations into these.
+    ///
+    /// For shows in both, the more recently changed annotation wins, and
+    /// annotations cleared since they were last changed are left out.
+    pub fn merge(&mut self, other: &Annotations) {
+        for (show_id, theirs) in &other.shows {
+            match self.shows.get(show_id) {
+                Some(ours) if ours.updated_at > theirs.updated_at => {}
+                _ => {
+                    self.shows.insert(show_id.clone(), theirs.clone());
+                }
+            }
+        }
+
+        for (show_id, removed_at) in &other.removed {
+            let entry = self.removed.entry(show_id.clone()).or_insert(*removed_at);
+            *entry = (*entry).max(*removed_at);
+        }
+        let removed = &self.removed;
+        self.shows.retain(|show_id, annotation| {
+            removed
+                .get(show_id)
+                .is_none_or(|removed_at| annotation.updated_at > *removed_at)
+        });
+    }
+
+    /// The annotation of a show, if anything is noted about it.
+    pub fn get(&self, show_id: &str) -> Option<&Annotation> {
+        self.shows.get(show_id)
+    }
+
+    /// Change the annotation of a show, creating it if needed.
+    ///
+    /// An annotation left empty by the change is removed.
+    pub fn update(&mut self, show_id: &str, show_name: &str, change: impl FnOnce(&mut Annotation)) {
+        let annotation = self
+            .shows
+            .entry(show_id.to_string())
+            .or_insert_with(|| Annotation {
+                show_id: show_id.to_string(),
+                show_name: show_name.to_string(),
+                score: None,
+                notes: String::new(),
+                tags: BTreeSet::new(),
+                updated_at: 0,
+            });
+        change(annotation);
+        annotation.show_name = show_name.to_string();
+        annotation.updated_at = now();
+        if annotation.is_empty() {
+            self.shows.remove(show_id);
+            self.removed.insert(show_id.to_string(), now());
+        }
+    }
+}
+
+/// Split a filter into words at whitespace outside double quotes, dropping
+/// the quotes. An unclosed quote runs to the end of the input.
+fn split_words(input: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in input.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// A filter over show names and annotations.
+///
+/// Words starting with `#` match tags, `score:N` matches shows scored N or
+/// higher, and the remaining words must appear in the show name. Matching
+/// ignores case, and a tag word also matches tags that have it as a whole
+/// word, so `#friends` finds shows tagged "with friends" but `#rewatch`
+/// doesn't find "not-rewatch". Double quotes keep spaces in a word, so
+/// `#"with friends"` matches that whole tag.
+///
+/// # Examples
+///
+/// ```
+/// use anime_watcher::annotations::{Annotations, Filter};
+///
+/// let mut annotations = Annotations::new();
+/// annotations.update("show1", "Frieren", |a| {
+///     a.score = Some(9);
+///     a.tags.insert("rewatch".to_string());
+/// });
+///
+/// let filter = Filter::parse("frie #rewatch score:8");
+/// assert!(filter.matches("Frieren", annotations.get("show1")));
+/// assert!(!filter.matches("Bocchi", annotations.get("show2")));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    /// Text the show name must contain, lowercased.
+    text: String,
+    /// Tags the show must have, lowercased.
+    tags: Vec<String>,
+    /// Lowest score the show must have.
+    min_score: Option<u8>,
+}
+
+impl Filter {
+    /// Parse a filter typed by the user.
+    pub fn parse(input: &str) -> Self {
+        let mut filter = Filter::default();
+        let mut words = Vec::new();
+        for word in split_words(input) {
+            let lower = word.to_lowercase();
+            if let Some(tag) = lower.strip_prefix('#') {
+                if !tag.is_empty() {
+                    filter.tags.push(tag.to_string());
+                }
+            } else if let Some(score) = lower
+                .strip_prefix("score:")
+                .and_then(|s| s.parse::<u8>().ok())
+            {
+                filter.min_score = Some(score);
+            } else {
+                words.push(lower);
+            }
+        }
+        filter.text = words.join(" ");
+        filter
+    }
+
+    /// Whether a show matches the filter.
+    pub fn matches(&self, name: &str, annotation: Option<&Annotation>) -> bool {
+        if !self.text.is_empty() && !name.to_lowercase().contains(&self.text) {
+            return false;
+        }
+        if !self.tags.is_empty()
+            && !annotation.is_some_and(|a| self.tags.iter().all(|tag| a.has_tag(tag)))
+        {
+            return false;
+        }
+        match self.min_score {
+            Some(min) => annotation.and_then(|a| a.score).is_some_and(|s| s >= min),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_score() {
+        assert_eq!(parse_score(" 7 "), Ok(Some(7)));
+        assert_eq!(parse_score(""), Ok(None));
+        assert!(parse_score("0").is_err());
+        assert!(parse_score("11").is_err());
+        assert!(parse_score("great").is_err());
+    }
+
+    #[test]
+    fn test_parse_tags() {
+        let tags = parse_tags("rewatch, with friends,, rewatch ");
+        assert_eq!(
+            tags.into_iter().collect::<Vec<_>>(),
+            vec!["rewatch", "with friends"]
+        );
+    }
+
+    #[test]
+    fn test_update_removes_empty() {
+        let mut annotations = Annotations::new();
+        annotations.update("a", "Frieren", |a| a.notes = "Episode 10!".to_string());
+        assert_eq!(annotations.get("a").unwrap().notes, "Episode 10!");
+
+        annotations.update("a", "Sousou no Frieren", |a| a.score = Some(10));
+        let annotation = annotations.get("a").unwrap();
+        assert_eq!(annotation.show_name, "Sousou no Frieren");
+        assert_eq!(annotation.score, Some(10));
+
+        annotations.update("a", "Frieren", |a| {
+            a.notes.clear();
+            a.score = None;
+        });
+        assert!(annotations.get("a").is_none());
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut annotations = Annotations::new();
+        annotations.update("a", "Frieren", |a| {
+            a.score = Some(9);
+            a.tags = parse_tags("rewatch, with friends");
+        });
+        let frieren = annotations.get("a");
+
+        assert!(Filter::parse("").matches("Bocchi", None));
+        assert!(Filter::parse("FRIEREN").matches("Frieren", frieren));
+        assert!(Filter::parse("#friends").matches("Frieren", frieren));
+        assert!(Filter::parse("#rewatch #with").matches("Frieren", frieren));
+        assert!(!Filter::parse("#dropped").matches("Frieren", frieren));
+        assert!(!Filter::parse("#friend").matches("Frieren", frieren));
+        assert!(Filter::parse("#\"With Friends\"").matches("Frieren", frieren));
+        assert!(!Filter::parse("#\"with friend\"").matches("Frieren", frieren));
+        assert!(
+            Filter::parse("\"sousou no\" #\"with friends").matches("Sousou no Frieren", frieren)
+        );
+        assert!(Filter::parse("score:9").matches("Frieren", frieren));
+        assert!(!Filter::parse("score:10").matches("Frieren", frieren));
+        assert!(!Filter::parse("score:1").matches("Bocchi", None));
+        // A lone # is just typing in progress
+        assert_eq!(Filter::parse("#"), Filter::default());
+    }
+
+    #[test]
+    fn test_has_tag() {
+        let mut annotations = Annotations::new();
+        annotations.update("a", "Frieren", |a| {
+            a.tags = parse_tags("Not-Rewatch, With Friends")
+        });
+        let frieren = annotations.get("a").unwrap();
+
+        assert!(frieren.has_tag("not-rewatch"));
+        assert!(frieren.has_tag("FRIENDS"));
+        assert!(frieren.has_tag("with friends"));
+        assert!(!frieren.has_tag("rewatch"));
+        assert!(!frieren.has_tag("friend"));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut saved = Annotations::new();
+        saved.update("a", "Frieren", |a| a.score = Some(8));
+        saved.update("b", "Bocchi", |a| a.notes = "funny".to_string());
+
+        // Another instance rescored one show and cleared another
+        let mut ours = saved.clone();
+        ours.update("a", "Frieren", |a| a.score = Some(10));
+        ours.shows.get_mut("a").unwrap().updated_at += 10;
+        ours.update("b", "Bocchi", |a| a.notes.clear());
+        // while this one tagged a show
+        saved.update("c", "Dandadan", |a| a.tags = parse_tags("with friends"));
+
+        saved.merge(&ours);
+        assert_eq!(saved.get("a").unwrap().score, Some(10));
+        assert!(saved.get("b").is_none());
+        assert!(saved.get("c").is_some());
+    }
+}
@@ -3,7 +3,7 @@
 //! This module provides functions for searching shows, fetching episode lists,
 //! and retrieving stream sources from the AllAnime GraphQL API.
 
-use crate::types::{Episode, RawShow, Show, StreamSource};
+use crate::types::{Episode, RawShow, Show, ShowDetails, StreamSource, SubtitleTrack};
 use log::{debug, info, warn};
 use regex::Regex;
 use serde::Deserialize;
@@ -18,7 +18,7 @@ const MAX_RETRIES: u32 = 3;
 const BASE_RETRY_DELAY_MS: u64 = 500;
 
 const API_URL: &str = "https://api.allanime.day/api";
-const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36";
+pub(crate) const USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36";
 
 /// Stream provider types from AllAnime.
 ///
@@ -186,6 +186,17 @@ pub struct EpisodeShow {
     pub available_episodes_detail: std::collections::HashMap<String, Vec<String>>,
 }
 
+// Response types for show details
+#[derive(Debug, Deserialize)]
+struct ShowDetailsResponse {
+    data: ShowDetailsData,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShowDetailsData {
+    show: ShowDetails,
+}
+
 // Response types for clock.json
 #[derive(Debug, Deserialize)]
 struct ClockResponse {
@@ -202,6 +213,19 @@ struct ClockLink {
     link: Option<String>,
     #[serde(default)]
     hls: Option<String>,
+    #[serde(default)]
+    subtitles: Vec<SubtitleTrack>,
+}
+
+/// Translation type to query the API with for a mode.
+///
+/// Dual-audio downloads browse the sub release; the manager fetches the dub
+/// separately.
+fn translation_type(mode: &str) -> &str {
+    match mode {
+        "dual" => "sub",
+        other => other,
+    }
 }
 
 /// Decode AllAnime's hex-encoded URLs.
@@ -296,6 +320,7 @@ pub async fn search_shows(
     mode: &str,
 ) -> Result<Vec<Show>, Box<dyn std::error::Error>> {
     debug!("Searching for '{}' in {} mode", query, mode);
+    let mode = translation_type(mode);
 
     let variables = serde_json::json!({
         "search": {
@@ -311,7 +336,7 @@ pub async fn search_shows(
 
     let query_str = r#"query ($search: SearchInput, $limit: Int, $page: Int, $translationType: VaildTranslationTypeEnumType, $countryOrigin: VaildCountryOriginEnumType) {
         shows(search: $search, limit: $limit, page: $page, translationType: $translationType, countryOrigin: $countryOrigin) {
-            edges { _id name availableEpisodes __typename }
+            edges { _id name englishName nativeName availableEpisodes __typename }
         }
     }"#;
 
@@ -353,6 +378,8 @@ pub async fn search_shows(
             Show {
                 id: raw.id,
                 name: raw.name,
+                english_name: raw.english_name,
+                native_name: raw.native_name,
                 available_episodes: count,
             }
         })
@@ -380,6 +407,7 @@ pub async fn fetch_episodes(
     mode: &str,
 ) -> Result<Vec<Episode>, Box<dyn std::error::Error>> {
     debug!("Fetching episodes for show {} in {} mode", show_id, mode);
+    let mode = translation_type(mode);
 
     let variables = serde_json::json!({
         "showId": show_id,
@@ -445,6 +473,90 @@ pub async fn fetch_episodes(
     Ok(episodes)
 }
 
+/// Fetch descriptive metadata for a show.
+///
+/// # Arguments
+///
+/// * `show_id` - The unique identifier of the show
+///
+/// # Returns
+///
+/// The show's titles, synopsis, poster URL, air date and genres.
+pub async fn fetch_show_details(show_id: &str) -> Result<ShowDetails, Box<dyn std::error::Error>> {
+    debug!("Fetching details for show {}", show_id);
+
+    let variables = serde_json::json!({
+        "showId": show_id,
+    });
+
+    const DETAILS_QUERY: &str = r#"
+        query ($showId: String!) {
+            show(_id: $showId) {
+                _id
+                name
+                englishName
+                nativeName
+                description
+                thumbnail
+                airedStart
+                genres
+            }
+        }
+    "#;
+
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(Duration::from_secs(30))
+        .build()?;
+
+    let variables_str = serde_json::to_string(&variables)?;
+    let query_string = DETAILS_QUERY.to_string();
+
+    let resp = retry_with_backoff("Fetch show details", || {
+        let client = client.clone();
+        let variables_str = variables_str.clone();
+        let query_string = query_string.clone();
+        async move {
+            client
+                .get(API_URL)
+                .header("Referer", "https://allmanga.to")
+                .query(&[("variables", variables_str), ("query", query_string)])
+                .send()
+                .await
+        }
+    })
+    .await?;
+
+    let parsed: ShowDetailsResponse = resp
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse show details: {}", e))?;
+
+    Ok(parsed.data.show)
+}
+
+/// Download a poster image to the given path.
+pub async fn download_image(
+    url: &str,
+    output_path: &std::path::Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(Duration::from_secs(30))
+        .build()?;
+
+    let bytes = client
+        .get(url)
+        .header("Referer", "https://allmanga.to")
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    std::fs::write(output_path, &bytes)?;
+    Ok(())
+}
+
 /// Fetch stream sources for a specific episode.
 ///
 /// Retrieves available streaming URLs for an episode from various providers.
@@ -469,6 +581,7 @@ pub async fn fetch_stream_sources(
         "Fetching stream sources for episode {} of show {}",
         episode_str, show_id
     );
+    let mode = translation_type(mode);
 
     let variables = serde_json::json!({
         "showId": show_id,
@@ -568,7 +681,12 @@ pub async fn fetch_stream_sources(
             } else {
                 source.source_url.clone()
             };
-            result.push(StreamSource { quality: 0, url });
+            result.push(StreamSource {
+                quality: 0,
+                url,
+                provider: source.source_name.clone(),
+                subtitles: Vec::new(),
+            });
             continue;
         }
 
@@ -584,6 +702,8 @@ pub async fn fetch_stream_sources(
             result.push(StreamSource {
                 quality: 0,
                 url: decoded_url,
+                provider: source.source_name.clone(),
+                subtitles: Vec::new(),
             });
             continue;
         }
@@ -608,13 +728,20 @@ pub async fn fetch_stream_sources(
                                     .unwrap_or("0")
                                     .parse()
                                     .unwrap_or(0);
-                                result.push(StreamSource { quality, url });
+                                result.push(StreamSource {
+                                    quality,
+                                    url,
+                                    provider: source.source_name.clone(),
+                                    subtitles: link.subtitles.clone(),
+                                });
                             }
 
                             if let Some(hls_url) = link.hls {
                                 result.push(StreamSource {
                                     quality: 0,
                                     url: hls_url,
+                                    provider: source.source_name.clone(),
+                                    subtitles: link.subtitles.clone(),
                                 });
                             }
                         }
@@ -637,10 +764,79 @@ pub async fn fetch_stream_sources(
     Ok(result)
 }
 
+/// Select a stream source based on quality preference.
+///
+/// `quality` is "best", "worst", or a target resolution such as "720".
+/// Numeric targets fall back to the closest known quality.
+pub fn choose_stream(
+    sources: &[StreamSource],
+    quality: &str,
+) -> Result<StreamSource, Box<dyn std::error::Error>> {
+    if sources.is_empty() {
+        return Err("No sources available".into());
+    }
+
+    if sources.len() == 1 {
+        return Ok(sources[0].clone());
+    }
+
+    let mut known_quality: Vec<&StreamSource> = sources.iter().filter(|s| s.quality > 0).collect();
+    let unknown_quality: Vec<&StreamSource> = sources.iter().filter(|s| s.quality == 0).collect();
+
+    known_quality.sort_by_key(|s| std::cmp::Reverse(s.quality));
+
+    match quality.to_lowercase().as_str() {
+        "best" => {
+            if let Some(source) = known_quality.first() {
+                Ok((*source).clone())
+            } else if let Some(source) = unknown_quality.first() {
+                Ok((*source).clone())
+            } else {
+                Ok(sources[0].clone())
+            }
+        }
+        "worst" => {
+            if let Some(source) = known_quality.last() {
+                Ok((*source).clone())
+            } else if let Some(source) = unknown_quality.first() {
+                Ok((*source).clone())
+            } else {
+                Ok(sources[0].clone())
+            }
+        }
+        q => {
+            if let Ok(target_quality) = q.parse::<i32>() {
+                if let Some(source) = known_quality.iter().find(|s| s.quality == target_quality) {
+                    return Ok((*source).clone());
+                }
+
+                if !known_quality.is_empty() {
+                    let closest = known_quality
+                        .iter()
+                        .min_by_key(|s| (s.quality - target_quality).abs())
+                        .unwrap();
+                    return Ok((*closest).clone());
+                }
+
+                Ok(sources[0].clone())
+            } else {
+                // Return first source if quality string is invalid
+                Ok(sources[0].clone())
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_translation_type_browses_sub_for_dual() {
+        assert_eq!(translation_type("dual"), "sub");
+        assert_eq!(translation_type("dub"), "dub");
+    }
+
     #[test]
     fn test_decode_allanime_url_empty() {
         assert_eq!(decode_allanime_url(""), "");
@@ -729,4 +925,35 @@ mod tests {
         let result = extract_clock_id(encoded);
         assert_eq!(result, Some("abc".to_string()));
     }
+
+    fn sources(qualities: &[i32]) -> Vec<StreamSource> {
+        qualities
+            .iter()
+            .map(|&quality| StreamSource {
+                quality,
+                url: format!("{}p", quality),
+                provider: String::new(),
+                subtitles: Vec::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_choose_stream_best_and_worst() {
+        let sources = sources(&[480, 1080, 720]);
+        assert_eq!(choose_stream(&sources, "best").unwrap().quality, 1080);
+        assert_eq!(choose_stream(&sources, "worst").unwrap().quality, 480);
+    }
+
+    #[test]
+    fn test_choose_stream_closest_quality() {
+        let sources = sources(&[480, 1080]);
+        assert_eq!(choose_stream(&sources, "720").unwrap().quality, 480);
+        assert_eq!(choose_stream(&sources, "1000").unwrap().quality, 1080);
+    }
+
+    #[test]
+    fn test_choose_stream_empty() {
+        assert!(choose_stream(&[], "best").is_err());
+    }
 }
@@ -0,0 +1,1351 @@
+//! Subcommands that run without the TUI, for scripts and cron jobs.
+//!
+//! Each command prints plain progress lines (or JSON lines with `--json`)
+//! and returns an exit code from [`exit_code`].
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde_json::json;
+
+use chrono::Local;
+
+use crate::anicli;
+use crate::anilist::{self, AniList, Mapping, Mappings, PushOutcome};
+use crate::annotations::Annotations;
+use crate::api::{fetch_episodes, fetch_show_details, search_shows};
+use crate::download::{
+    check_space, estimate_batch, format_size, verify_queue, DownloadEvent, DownloadJob,
+    DownloadManager, DownloadOptions, DownloadOutcome, DownloadQueue, QueueStatus, Schedule,
+};
+use crate::follow::FollowList;
+use crate::history::WatchHistory;
+use crate::mal::{self, MalIds, MalShow, TitleMatch};
+use crate::stats::{self, Stats};
+use crate::types::Show;
+use crate::watchlist::{WatchStatus, Watchlist};
+
+/// Process exit codes used by the headless commands.
+pub mod exit_code {
+    /// Everything succeeded.
+    pub const SUCCESS: i32 = 0;
+    /// Some episodes failed to download.
+    pub const PARTIAL: i32 = 1;
+    /// Invalid arguments, such as a malformed episode list.
+    pub const USAGE: i32 = 2;
+    /// The show or the requested episodes could not be found.
+    pub const NOT_FOUND: i32 = 3;
+    /// The AllAnime API could not be reached.
+    pub const NETWORK: i32 = 4;
+    /// The episodes don't fit on disk or within the download quota.
+    pub const NO_SPACE: i32 = 5;
+}
+
+/// Most episodes an episode list can select.
+const MAX_SELECTED_EPISODES: usize = 10_000;
+
+/// Parse an episode list such as `"1-10,12"`.
+///
+/// `"all"` selects every available episode. Returns the selected numbers in
+/// ascending order without duplicates. Lists selecting more than 10000
+/// episodes are rejected.
+///
+/// # Examples
+///
+/// ```
+/// use anime_watcher::commands::parse_episode_spec;
+///
+/// assert_eq!(parse_episode_spec("1-3,5", &[1, 2, 3, 4, 5]), Ok(vec![1, 2, 3, 5]));
+/// assert_eq!(parse_episode_spec("all", &[2, 1]), Ok(vec![1, 2]));
+/// assert!(parse_episode_spec("3-1", &[1, 2, 3]).is_err());
+/// ```
+pub fn parse_episode_spec(spec: &str, available: &[i64]) -> Result<Vec<i64>, String> {
+    if spec.trim().eq_ignore_ascii_case("all") {
+        let all: BTreeSet<i64> = available.iter().copied().collect();
+        return Ok(all.into_iter().collect());
+    }
+
+    let parse_number = |s: &str| {
+        s.trim()
+            .parse::<i64>()
+            .map_err(|_| format!("invalid episode number '{}'", s.trim()))
+    };
+
+    let mut selected = BTreeSet::new();
+    for part in spec.split(',').filter(|p| !p.trim().is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse_number(start)?, parse_number(end)?);
+                if start > end {
+                    return Err(format!("invalid episode range '{}'", part.trim()));
+                }
+                let len = end
+                    .checked_sub(start)
+                    .and_then(|n| usize::try_from(n).ok())
+                    .and_then(|n| n.checked_add(1));
+                if len.is_none_or(|len| selected.len() + len > MAX_SELECTED_EPISODES) {
+                    return Err(format!(
+                        "episode range '{}' is too long (at most {} episodes)",
+                        part.trim(),
+                        MAX_SELECTED_EPISODES
+                    ));
+                }
+                selected.extend(start..=end);
+            }
+            None => {
+                selected.insert(parse_number(part)?);
+            }
+        }
+    }
+
+    if selected.is_empty() {
+        return Err("no episodes selected".to_string());
+    }
+    Ok(selected.into_iter().collect())
+}
+
+/// Pick the search result that best matches `query`.
+///
+/// Prefers an exact match (see [`exact_match`]), then the first result.
+pub fn best_match<'a>(shows: &'a [Show], query: &str) -> Option<&'a Show> {
+    exact_match(shows, query).or_else(|| shows.first())
+}
+
+/// Find the search result whose ID or (case-insensitive) title is exactly
+/// `query`, preferring an ID match.
+pub fn exact_match<'a>(shows: &'a [Show], query: &str) -> Option<&'a Show> {
+    let query = query.trim();
+    shows.iter().find(|s| s.id == query).or_else(|| {
+        shows.iter().find(|s| {
+            s.name.eq_ignore_ascii_case(query)
+                || s.english_name
+                    .as_deref()
+                    .is_some_and(|e| e.eq_ignore_ascii_case(query))
+        })
+    })
+}
+
+/// Options for the `download` subcommand.
+#[derive(Debug, Clone)]
+pub struct DownloadRequest {
+    /// Show ID or search query.
+    pub query: String,
+    /// Episode list (see [`parse_episode_spec`]).
+    pub episodes: String,
+    /// Translation mode (sub/dub).
+    pub mode: String,
+    /// Preferred quality.
+    pub quality: String,
+    /// Directory to save episodes to.
+    pub download_dir: PathBuf,
+    /// Only print what would be downloaded.
+    pub dry_run: bool,
+    /// Print progress as JSON lines.
+    pub json: bool,
+}
+
+/// Prints progress as plain text or JSON lines.
+struct Reporter {
+    json: bool,
+}
+
+impl Reporter {
+    fn info(&self, message: &str, value: serde_json::Value) {
+        if self.json {
+            println!("{}", value);
+        } else {
+            println!("{}", message);
+        }
+    }
+
+    fn warn(&self, message: &str) {
+        if self.json {
+            println!("{}", json!({ "event": "warning", "message": message }));
+        } else {
+            eprintln!("Warning: {}", message);
+        }
+    }
+
+    fn error(&self, message: &str) {
+        if self.json {
+            println!("{}", json!({ "event": "error", "message": message }));
+        } else {
+            eprintln!("Error: {}", message);
+        }
+    }
+}
+
+/// Find the show for a download request.
+async fn resolve_show(query: &str, mode: &str) -> Result<Option<Show>, Box<dyn std::error::Error>> {
+    let shows = search_shows(query, mode).await?;
+    if let Some(show) = exact_match(&shows, query) {
+        return Ok(Some(show.clone()));
+    }
+
+    // No title matched exactly; a single word may be a show ID, which the
+    // fuzzy search wouldn't return.
+    if !query.trim().contains(char::is_whitespace) {
+        if let Ok(details) = fetch_show_details(query.trim()).await {
+            return Ok(Some(Show {
+                id: details.id,
+                name: details.name,
+                english_name: details.english_name,
+                native_name: details.native_name,
+                available_episodes: 0,
+            }));
+        }
+    }
+    Ok(best_match(&shows, query).cloned())
+}
+
+/// Run the `download` subcommand and return the process exit code.
+pub async fn run_download(request: &DownloadRequest, options: &DownloadOptions) -> i32 {
+    let out = Reporter { json: request.json };
+
+    // Catch malformed episode lists before touching the network.
+    if let Err(e) = parse_episode_spec(&request.episodes, &[]) {
+        out.error(&e);
+        return exit_code::USAGE;
+    }
+
+    let show = match resolve_show(&request.query, &request.mode).await {
+        Ok(Some(show)) => show,
+        Ok(None) => {
+            out.error(&format!("no show found for '{}'", request.query));
+            return exit_code::NOT_FOUND;
+        }
+        Err(e) => {
+            out.error(&e.to_string());
+            return exit_code::NETWORK;
+        }
+    };
+
+    let available: Vec<i64> = match fetch_episodes(&show.id, &request.mode).await {
+        Ok(episodes) => episodes.iter().map(|e| e.number).collect(),
+        Err(e) => {
+            out.error(&e.to_string());
+            return exit_code::NETWORK;
+        }
+    };
+
+    let requested = match parse_episode_spec(&request.episodes, &available) {
+        Ok(requested) => requested,
+        Err(e) => {
+            out.error(&e);
+            return exit_code::USAGE;
+        }
+    };
+    let (episodes, missing): (Vec<i64>, Vec<i64>) =
+        requested.into_iter().partition(|ep| available.contains(ep));
+
+    for ep in &missing {
+        out.info(
+            &format!("Episode {} is not available in {} mode", ep, request.mode),
+            json!({ "event": "missing", "episode": ep }),
+        );
+    }
+    if episodes.is_empty() {
+        out.error("none of the requested episodes are available");
+        return exit_code::NOT_FOUND;
+    }
+
+    let estimate = estimate_batch(&show.id, &request.mode, &request.quality, &episodes).await;
+    let size = estimate
+        .map(|bytes| format!(", about {}", format_size(bytes)))
+        .unwrap_or_default();
+    out.info(
+        &format!(
+            "{} [{}] ({}): {} episode(s){} to {}",
+            show.name,
+            show.id,
+            request.mode,
+            episodes.len(),
+            size,
+            request.download_dir.display()
+        ),
+        json!({
+            "event": "plan",
+            "show_id": show.id,
+            "show": show.name,
+            "mode": request.mode,
+            "quality": request.quality,
+            "dir": request.download_dir,
+            "episodes": episodes,
+            "estimated_bytes": estimate,
+            "dry_run": request.dry_run,
+        }),
+    );
+    if request.dry_run {
+        if !request.json {
+            let list: Vec<String> = episodes.iter().map(|e| e.to_string()).collect();
+            println!("Episodes: {}", list.join(", "));
+        }
+        return exit_code::SUCCESS;
+    }
+
+    let mut queue = DownloadQueue::load().unwrap_or_default();
+    if let Err(code) = preflight(&request.download_dir, estimate, options, &mut queue, &out) {
+        return code;
+    }
+
+    let mut manager = DownloadManager::new(options);
+    for (i, &episode) in episodes.iter().enumerate() {
+        manager.submit(DownloadJob {
+            id: i as u64,
+            show_id: show.id.clone(),
+            show_name: show.name.clone(),
+            english_name: show.english_name.clone(),
+            native_name: show.native_name.clone(),
+            episode,
+            mode: request.mode.clone(),
+            quality: request.quality.clone(),
+            download_dir: request.download_dir.clone(),
+        });
+    }
+
+    let total = episodes.len();
+    let (mut finished, mut failed) = (0, 0);
+    while finished < total {
+        let Some(event) = manager.try_next_event() else {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            continue;
+        };
+
+        match event {
+            DownloadEvent::WorkerStatus { worker, message } => {
+                if message != "idle" {
+                    out.info(
+                        &format!("  worker {}: {}", worker + 1, message),
+                        json!({ "event": "status", "worker": worker, "message": message }),
+                    );
+                }
+            }
+            DownloadEvent::Finished { job, outcome } => {
+                finished += 1;
+                let progress = format!("[{}/{}]", finished, total);
+                let (status, detail) = match &outcome {
+                    DownloadOutcome::Completed(path) => ("completed", path.display().to_string()),
+                    DownloadOutcome::Skipped(path) => ("skipped", path.display().to_string()),
+                    DownloadOutcome::NoSources => ("failed", "no sources".to_string()),
+                    DownloadOutcome::Failed(e) => ("failed", e.clone()),
+                };
+                if status == "failed" {
+                    failed += 1;
+                }
+                let mark = if status == "failed" { "✗" } else { "✓" };
+                out.info(
+                    &format!(
+                        "{} {} Episode {} {}: {}",
+                        progress, mark, job.episode, status, detail
+                    ),
+                    json!({
+                        "event": "finished",
+                        "episode": job.episode,
+                        "status": status,
+                        "detail": detail,
+                    }),
+                );
+            }
+        }
+    }
+
+    out.info(
+        &format!("Done: {} succeeded, {} failed", total - failed, failed),
+        json!({ "event": "summary", "succeeded": total - failed, "failed": failed }),
+    );
+
+    if failed > 0 {
+        exit_code::PARTIAL
+    } else {
+        exit_code::SUCCESS
+    }
+}
+
+/// Check that about `estimate` bytes fit in `dir`, applying the quota's
+/// cleanup policy to episodes in `queue`. Returns the exit code to stop with
+/// if they don't fit.
+///
+/// Without an estimate only the space already used is checked, with a
+/// warning that the batch itself wasn't.
+fn preflight(
+    dir: &Path,
+    estimate: Option<u64>,
+    options: &DownloadOptions,
+    queue: &mut DownloadQueue,
+    out: &Reporter,
+) -> Result<(), i32> {
+    let history = WatchHistory::load().unwrap_or_default();
+    let others = WatchHistory::load_other_profiles();
+    let histories: Vec<&WatchHistory> = std::iter::once(&history).chain(&others).collect();
+    if estimate.is_none() {
+        out.warn("couldn't estimate the download size; free space and quota aren't checked for it");
+    }
+
+    match check_space(
+        dir,
+        &options.filename_template,
+        estimate.unwrap_or(0),
+        options.quota,
+        queue,
+        &histories,
+    ) {
+        Ok(report) => {
+            if !report.deleted.is_empty() {
+                let _ = queue.save();
+            }
+            for path in &report.deleted {
+                out.info(
+                    &format!("Deleted watched episode {}", path.display()),
+                    json!({ "event": "deleted", "path": path }),
+                );
+            }
+            Ok(())
+        }
+        Err(e) => {
+            out.error(&e);
+            Err(exit_code::NO_SPACE)
+        }
+    }
+}
+
+/// Run the `sync` subcommand and return the process exit code.
+///
+/// Queues new episodes of every followed show, then downloads the queue
+/// unless `queue_only` is set. Downloads honour the queue's pause state and
+/// the configured download windows; anything left over stays queued.
+pub async fn run_sync(
+    download_dir: &Path,
+    options: &DownloadOptions,
+    schedule: &Schedule,
+    queue_only: bool,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let mut follows = FollowList::load()?;
+    let mut queue = DownloadQueue::load()?;
+
+    if follows.shows.is_empty() {
+        println!("Not following any shows. Press + on a show's episode list to follow it.");
+        return Ok(exit_code::SUCCESS);
+    }
+
+    let mut code = exit_code::SUCCESS;
+    let mut queued = 0;
+    let mut estimate = 0;
+    let mut unsized_shows = Vec::new();
+    let ids: Vec<String> = follows.sorted().iter().map(|f| f.show_id.clone()).collect();
+    for id in ids {
+        let Some(followed) = follows.shows.get_mut(&id) else {
+            continue;
+        };
+
+        let available: Vec<i64> = match fetch_episodes(&followed.show_id, &followed.mode).await {
+            Ok(episodes) => episodes.iter().map(|e| e.number).collect(),
+            Err(e) => {
+                println!("✗ {}: {}", followed.show_name, e);
+                code = exit_code::NETWORK;
+                continue;
+            }
+        };
+
+        let new = followed.new_episodes(&available);
+        if new.is_empty() {
+            println!("  {}: up to date", followed.show_name);
+            continue;
+        }
+
+        match estimate_batch(&followed.show_id, &followed.mode, &followed.quality, &new).await {
+            Some(size) => estimate += size,
+            None => unsized_shows.push(followed.show_name.clone()),
+        }
+
+        let show = followed.to_show();
+        for &episode in &new {
+            queue.enqueue(
+                &show,
+                episode,
+                &followed.mode,
+                &followed.quality,
+                download_dir,
+            );
+        }
+        followed.mark_known(&new);
+        queued += new.len();
+
+        let list: Vec<String> = new.iter().map(|e| e.to_string()).collect();
+        println!(
+            "+ {}: {} new episode(s) ({})",
+            followed.show_name,
+            new.len(),
+            list.join(", ")
+        );
+    }
+
+    queue.save()?;
+    follows.save()?;
+    println!("Queued {} new episode(s).", queued);
+
+    if queue_only || queue.remaining() == 0 {
+        return Ok(code);
+    }
+
+    // Only the new episodes are sized; older queue items were checked before.
+    let out = Reporter { json: false };
+    if !unsized_shows.is_empty() {
+        out.warn(&format!(
+            "couldn't estimate the size of new episodes of {}; they aren't counted \
+             in the space check",
+            unsized_shows.join(", ")
+        ));
+    }
+    if let Err(no_space) = preflight(download_dir, Some(estimate), options, &mut queue, &out) {
+        println!("New episodes stay queued until there is room.");
+        return Ok(no_space);
+    }
+
+    let failed = drain_queue(&mut queue, options, schedule).await?;
+    if failed > 0 && code == exit_code::SUCCESS {
+        code = exit_code::PARTIAL;
+    }
+    Ok(code)
+}
+
+/// Download pending queue items until none are left that may start.
+///
+/// Returns the number of failed downloads.
+async fn drain_queue(
+    queue: &mut DownloadQueue,
+    options: &DownloadOptions,
+    schedule: &Schedule,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut manager = DownloadManager::new(options);
+    let mut failed = 0;
+
+    loop {
+        if schedule.is_open(Local::now().time()) {
+            let mut started = false;
+            while manager.has_capacity() {
+                let Some(job) = queue.start_next() else {
+                    break;
+                };
+                manager.submit(job);
+                started = true;
+            }
+            if started {
+                queue.save()?;
+            }
+        }
+
+        let active = queue.items.iter().any(|i| i.status == QueueStatus::Active);
+        if !active {
+            break;
+        }
+
+        match manager.try_next_event() {
+            Some(DownloadEvent::Finished { job, outcome }) => {
+                queue.finish(job.id, &outcome);
+                queue.save()?;
+                match &outcome {
+                    DownloadOutcome::Completed(path) => {
+                        println!("✓ {} Ep {}: {}", job.show_name, job.episode, path.display())
+                    }
+                    DownloadOutcome::Skipped(path) => println!(
+                        "✓ {} Ep {}: already downloaded ({})",
+                        job.show_name,
+                        job.episode,
+                        path.display()
+                    ),
+                    DownloadOutcome::NoSources => {
+                        failed += 1;
+                        println!("✗ {} Ep {}: no sources", job.show_name, job.episode)
+                    }
+                    DownloadOutcome::Failed(e) => {
+                        failed += 1;
+                        println!("✗ {} Ep {}: {}", job.show_name, job.episode, e)
+                    }
+                }
+            }
+            Some(DownloadEvent::WorkerStatus { .. }) => {}
+            None => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+
+    if queue.paused && queue.remaining() > 0 {
+        println!(
+            "The download queue is paused; {} episode(s) left.",
+            queue.remaining()
+        );
+    } else if let Some(opens) = schedule.next_opening(Local::now().time()) {
+        if queue.remaining() > 0 {
+            println!(
+                "{} episode(s) wait for the download window at {}.",
+                queue.remaining(),
+                opens.format("%H:%M")
+            );
+        }
+    }
+
+    Ok(failed)
+}
+
+/// Run the `verify` subcommand.
+///
+/// Checks every completed item in the download queue, re-queues broken
+/// episodes and removes leftover `.part` files from the download directory.
+pub async fn run_verify(download_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut queue = DownloadQueue::load()?;
+    let report = verify_queue(&mut queue, download_dir).await;
+    queue.save()?;
+
+    for broken in &report.requeued {
+        println!("✗ {}: {}", broken.description, broken.reason);
+    }
+    for part in &report.removed_parts {
+        println!("Removed unfinished download {}", part.display());
+    }
+    println!(
+        "Checked {} downloads, re-queued {}.",
+        report.checked,
+        report.requeued.len()
+    );
+    if !report.requeued.is_empty() {
+        println!("Re-queued episodes download the next time anime-watcher starts.");
+    }
+    Ok(())
+}
+
+/// Client for the logged-in AniList user.
+fn anilist_client(api_url: &str) -> Result<AniList, Box<dyn std::error::Error>> {
+    let token = anilist::load_token()?
+        .ok_or("Not logged in to AniList; run `anime-watcher anilist login` first")?;
+    AniList::new(api_url, Some(token))
+}
+
+/// Run `anilist login`: ask for an access token and store it.
+pub async fn run_anilist_login(
+    api_url: &str,
+    client_id: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match client_id {
+        Some(id) => println!(
+            "Open this page, allow access and copy the token:\n  {}",
+            anilist::authorize_url(id)
+        ),
+        None => println!(
+            "To get a login link, create an API client at https://anilist.co/settings/developer\n\
+             with the redirect URL https://anilist.co/api/v2/oauth/pin and set\n\
+             anilist_client_id in the config. A token you already have can be pasted now."
+        ),
+    }
+    print!("Access token: ");
+    io::stdout().flush()?;
+    let mut token = String::new();
+    io::stdin().read_line(&mut token)?;
+    let token = token.trim();
+    if token.is_empty() {
+        return Err("No token entered".into());
+    }
+
+    let viewer = AniList::new(api_url, Some(token.to_string()))?
+        .viewer()
+        .await?;
+    anilist::save_token(token)?;
+    println!("Logged in to AniList as {}", viewer.name);
+    Ok(())
+}
+
+/// Run `anilist logout`: forget the stored access token.
+pub fn run_anilist_logout() -> Result<(), Box<dyn std::error::Error>> {
+    if anilist::delete_token()? {
+        println!("Logged out of AniList");
+    } else {
+        println!("Not logged in to AniList");
+    }
+    Ok(())
+}
+
+/// Run `anilist map`: map a show to an AniList entry by hand.
+pub async fn run_anilist_map(
+    api_url: &str,
+    show_id: &str,
+    media_id: i64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let media = AniList::new(api_url, None)?.media(media_id).await?;
+    let mapping = Mapping::from_media(&media, true);
+    let mut mappings = Mappings::load()?;
+    mappings.shows.insert(show_id.to_string(), mapping.clone());
+    mappings.save()?;
+    println!(
+        "Mapped {} to {} ({})",
+        show_id,
+        mapping.title,
+        anilist::media_url(media_id)
+    );
+    Ok(())
+}
+
+/// Run `anilist unmap`: drop a show's mapping so it is matched again.
+pub fn run_anilist_unmap(show_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut mappings = Mappings::load()?;
+    match mappings.remove(show_id) {
+        Some(mapping) => {
+            mappings.save()?;
+            println!("Removed the mapping of {} to {}", show_id, mapping.title);
+        }
+        None => println!("{} is not mapped", show_id),
+    }
+    Ok(())
+}
+
+/// Run `anilist push`: push the progress of every show in the history.
+///
+/// A show watched both subbed and dubbed counts the further track.
+pub async fn run_anilist_push(api_url: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let client = anilist_client(api_url)?;
+    let history = WatchHistory::load()?;
+    let mut mappings = Mappings::load()?;
+
+    let mut shows: BTreeMap<&str, (&str, i64)> = BTreeMap::new();
+    for record in history.records.values() {
+        let progress = record.furthest_watched().unwrap_or(0);
+        let entry = shows
+            .entry(record.show_id.as_str())
+            .or_insert((record.show_name.as_str(), 0));
+        entry.1 = entry.1.max(progress);
+    }
+
+    let (mut updated, mut unmatched, mut failed) = (0, 0, 0);
+    for (show_id, (name, progress)) in shows {
+        if progress <= 0 {
+            continue;
+        }
+        let mapping = match client.map_show(&mut mappings, show_id).await {
+            Ok(Some(mapping)) => mapping,
+            Ok(None) => {
+                unmatched += 1;
+                println!(
+                    "? {}: no AniList match; map it with `anime-watcher anilist map {} <media id>`",
+                    name, show_id
+                );
+                continue;
+            }
+            Err(e) => {
+                failed += 1;
+                println!("✗ {}: {}", name, e);
+                continue;
+            }
+        };
+        match client.push_progress(mapping.media_id, progress).await {
+            Ok(PushOutcome::Updated { progress, status }) => {
+                updated += 1;
+                println!("✓ {}: episode {} ({})", mapping.title, progress, status);
+            }
+            Ok(PushOutcome::UpToDate) => println!("  {}: up to date", mapping.title),
+            Err(e) => {
+                failed += 1;
+                println!("✗ {}: {}", mapping.title, e);
+            }
+        }
+    }
+    mappings.save()?;
+
+    println!(
+        "Updated {}, unmatched {}, failed {}.",
+        updated, unmatched, failed
+    );
+    Ok(())
+}
+
+/// Find the AllAnime show for an AniList entry by exact title.
+async fn find_show(
+    media: &anilist::Media,
+    mode: &str,
+) -> Result<Option<Show>, Box<dyn std::error::Error>> {
+    for title in media.title.all() {
+        let shows = search_shows(title, mode).await?;
+        let found = shows.into_iter().find(|show| {
+            media.title.all().any(|t| {
+                anilist::same_title(&show.name, t)
+                    || show
+                        .english_name
+                        .as_deref()
+                        .is_some_and(|e| anilist::same_title(e, t))
+            })
+        });
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+    Ok(None)
+}
+
+/// Run `anilist pull`: seed the watch history and watchlist from the
+/// user's list.
+///
+/// Entries are matched to AllAnime shows by saved mapping or exact title,
+/// and their progress is recorded in the `mode` track. Progress behind what
+/// the history already has is left alone. Shows not yet on the watchlist
+/// are added with their AniList status.
+pub async fn run_anilist_pull(api_url: &str, mode: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let client = anilist_client(api_url)?;
+    let viewer = client.viewer().await?;
+    let entries = client.list(viewer.id).await?;
+    let mut history = WatchHistory::load()?;
+    let mut watchlist = Watchlist::load()?;
+    let mut mappings = Mappings::load()?;
+
+    let (mut seeded, mut listed, mut unmatched, mut skipped) = (0, 0, 0, 0);
+    for entry in &entries {
+        let title = entry.media.title.preferred();
+        let status = WatchStatus::from_anilist(&entry.status);
+        if entry.progress <= 0 && status.is_none() {
+            skipped += 1;
+            continue;
+        }
+
+        let show = match mappings.show_for(entry.media.id) {
+            Some(show_id) => Show {
+                id: show_id.to_string(),
+                name: title.clone(),
+                english_name: entry.media.title.english.clone(),
+                native_name: entry.media.title.native.clone(),
+                available_episodes: 0,
+            },
+            None => match find_show(&entry.media, mode).await {
+                Ok(Some(show)) => {
+                    mappings
+                        .shows
+                        .entry(show.id.clone())
+                        .or_insert_with(|| Mapping::from_media(&entry.media, false));
+                    show
+                }
+                Ok(None) => {
+                    unmatched += 1;
+                    println!("? {}: not found on AllAnime", title);
+                    continue;
+                }
+                Err(e) => {
+                    unmatched += 1;
+                    println!("✗ {}: {}", title, e);
+                    continue;
+                }
+            },
+        };
+
+        // Shows already on the watchlist keep their local status
+        if let Some(status) = status {
+            if watchlist.add(&show, status) {
+                listed += 1;
+                println!("+ {}: {} on the watchlist", title, status.label());
+            }
+        }
+        if entry.progress <= 0 {
+            continue;
+        }
+        if history.import_progress(&show.id, &title, mode, entry.progress, None) {
+            seeded += 1;
+            println!("+ {}: episode {}", title, entry.progress);
+        } else {
+            println!("  {}: up to date", title);
+        }
+    }
+    history.save()?;
+    watchlist.save()?;
+    mappings.save()?;
+
+    println!(
+        "Pulled {} entries for {}: seeded {} into the history and {} onto the watchlist, unmatched {}, {} skipped.",
+        entries.len(),
+        viewer.name,
+        seeded,
+        listed,
+        unmatched,
+        skipped
+    );
+    Ok(())
+}
+
+/// Look up a show's MAL ID and length through its AniList entry.
+async fn lookup_mal_show(
+    client: &AniList,
+    mappings: &mut Mappings,
+    show_id: &str,
+) -> Result<Option<MalShow>, Box<dyn std::error::Error>> {
+    let Some(mapping) = client.map_show(mappings, show_id).await? else {
+        return Ok(None);
+    };
+    let media = client.media(mapping.media_id).await?;
+    Ok(media.id_mal.map(|mal_id| MalShow {
+        mal_id,
+        episodes: media.episodes,
+    }))
+}
+
+/// Run `history export`: write the watch history as a MAL XML file.
+///
+/// Shows without a known MAL ID are looked up through AniList, which needs
+/// no account. The XML goes to `output`, or to stdout with messages on
+/// stderr.
+pub async fn run_history_export(
+    output: Option<&Path>,
+    api_url: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let history = WatchHistory::load()?;
+    let annotations = Annotations::load()?;
+    let mut ids = MalIds::load()?;
+    let mut mappings = Mappings::load()?;
+    let client = AniList::new(api_url, None)?;
+
+    let shows = mal::export_shows(&history);
+    let mut missing = Vec::new();
+    for show in &shows {
+        if ids.shows.contains_key(&show.show_id) {
+            continue;
+        }
+        match lookup_mal_show(&client, &mut mappings, &show.show_id).await {
+            Ok(Some(found)) => {
+                ids.shows.insert(show.show_id.clone(), found);
+            }
+            Ok(None) => missing.push(show.name.as_str()),
+            Err(e) => {
+                eprintln!("Warning: Failed to look up {}: {}", show.name, e);
+                missing.push(show.name.as_str());
+            }
+        }
+    }
+    ids.save()?;
+    mappings.save()?;
+
+    let entries: Vec<mal::MalEntry> = shows
+        .iter()
+        .map(|show| {
+            mal::to_entry(
+                show,
+                ids.shows.get(&show.show_id),
+                annotations.get(&show.show_id),
+            )
+        })
+        .collect();
+    let xml = mal::export(&entries);
+    match output {
+        Some(path) => {
+            fs::write(path, xml)?;
+            eprintln!("Exported {} shows to {}", entries.len(), path.display());
+        }
+        None => print!("{}", xml),
+    }
+    if !missing.is_empty() {
+        eprintln!(
+            "Warning: No MAL ID found for {} show(s), which MAL will skip: {}",
+            missing.len(),
+            missing.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Ask which search result an imported title refers to.
+///
+/// Returns `None` if the user skips it.
+fn choose_show(title: &str, candidates: &[Show]) -> io::Result<Option<Show>> {
+    let candidates = &candidates[..candidates.len().min(9)];
+    println!("\n\"{}\" could be:", title);
+    for (i, show) in candidates.iter().enumerate() {
+        println!(
+            "  {}) {} ({} eps) [{}]",
+            i + 1,
+            show.name,
+            show.available_episodes,
+            show.id
+        );
+    }
+    loop {
+        print!("Choose 1-{}, or s to skip: ", candidates.len());
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0 {
+            return Ok(None);
+        }
+        match answer.trim() {
+            "s" | "S" => return Ok(None),
+            n => {
+                if let Some(show) = n
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| candidates.get(n.wrapping_sub(1)))
+                {
+                    return Ok(Some(show.clone()));
+                }
+            }
+        }
+    }
+}
+
+/// Find the AllAnime show for an imported MAL entry.
+///
+/// Ambiguous titles are asked about when running in a terminal and skipped
+/// otherwise.
+async fn resolve_mal_entry(
+    entry: &mal::MalEntry,
+    mode: &str,
+    interactive: bool,
+) -> Result<Option<Show>, Box<dyn std::error::Error>> {
+    let results = search_shows(&entry.title, mode).await?;
+    Ok(match mal::match_title(&entry.title, results) {
+        TitleMatch::Exact(show) => Some(show),
+        TitleMatch::Ambiguous(candidates) if interactive => choose_show(&entry.title, &candidates)?,
+        TitleMatch::Ambiguous(_) | TitleMatch::NotFound => None,
+    })
+}
+
+/// Run `history import`: add another tracker's history to the watch history.
+///
+/// Reads ani-cli's history when no file is given. Without a `format`, MAL
+/// XML is told apart from ani-cli's history by its content. Progress is
+/// recorded in the `mode` track.
+pub async fn run_history_import(
+    file: Option<&Path>,
+    format: Option<&str>,
+    mode: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file = match file {
+        Some(file) => file.to_path_buf(),
+        None => anicli::default_path().ok_or("Could not find ani-cli's history")?,
+    };
+    let content = fs::read_to_string(&file)
+        .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+    let format = format.unwrap_or(if content.trim_start().starts_with('<') {
+        "mal-xml"
+    } else {
+        "ani-cli"
+    });
+
+    match format {
+        "ani-cli" => {
+            // ani-cli doesn't record when shows were watched, so the
+            // file's modification time stands in for all of them
+            let timestamp = fs::metadata(&file)
+                .and_then(|meta| meta.modified())
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or_else(|| Local::now().timestamp() as u64, |d| d.as_secs());
+            import_anicli(&content, mode, timestamp)
+        }
+        _ => import_mal(&file, &content, mode).await,
+    }
+}
+
+/// Add ani-cli's history to the watch history, keeping whichever progress
+/// of a show was watched later.
+fn import_anicli(
+    content: &str,
+    mode: &str,
+    timestamp: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let parsed = anicli::parse(content);
+    for (line, text) in &parsed.invalid {
+        println!("✗ line {}: can't read \"{}\"", line, text);
+    }
+
+    let mut history = WatchHistory::load()?;
+    let before = history.clone();
+    history.merge(&anicli::to_history(&parsed.entries, mode, timestamp));
+
+    let (mut imported, mut up_to_date, mut skipped) = (0, 0, parsed.invalid.len());
+    let mut seen = BTreeSet::new();
+    for entry in &parsed.entries {
+        if !seen.insert(&entry.show_id) {
+            continue;
+        }
+        let old = before.get_record(&entry.show_id, mode);
+        match history.get_record(&entry.show_id, mode) {
+            None => {
+                skipped += 1;
+                println!("- {}: removed from the history since", entry.title);
+            }
+            Some(new)
+                if old.is_some_and(|old| {
+                    old.episode == new.episode && old.watched == new.watched
+                }) =>
+            {
+                up_to_date += 1;
+                println!("  {}: up to date", entry.title);
+            }
+            Some(new) => {
+                imported += 1;
+                println!("+ {}: episode {}", new.show_name, new.episode);
+            }
+        }
+    }
+    history.save()?;
+
+    println!(
+        "Imported {}, up to date {}, skipped {}.",
+        imported, up_to_date, skipped
+    );
+    Ok(())
+}
+
+/// Add a MAL XML export to the watch history.
+///
+/// Shows already further along are left alone. Scores, comments and tags
+/// fill in those of shows that don't have their own yet.
+async fn import_mal(
+    file: &Path,
+    content: &str,
+    mode: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let entries =
+        mal::parse(content).map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+    let mut history = WatchHistory::load()?;
+    let mut annotations = Annotations::load()?;
+    let mut ids = MalIds::load()?;
+    let interactive = io::stdin().is_terminal();
+
+    let (mut imported, mut up_to_date, mut unmatched, mut not_started) = (0, 0, 0, 0);
+    for entry in &entries {
+        let progress = entry.progress();
+        if progress <= 0 {
+            not_started += 1;
+            continue;
+        }
+
+        let known = (entry.mal_id > 0)
+            .then(|| ids.show_for(entry.mal_id))
+            .flatten()
+            .map(str::to_string);
+        let (show_id, name) = match known {
+            Some(show_id) => (show_id, entry.title.clone()),
+            None => match resolve_mal_entry(entry, mode, interactive).await {
+                Ok(Some(show)) => (show.id, show.name),
+                Ok(None) => {
+                    unmatched += 1;
+                    println!("? {}: no matching show on AllAnime", entry.title);
+                    continue;
+                }
+                Err(e) => {
+                    unmatched += 1;
+                    println!("✗ {}: {}", entry.title, e);
+                    continue;
+                }
+            },
+        };
+        if entry.mal_id > 0 {
+            ids.shows.insert(
+                show_id.clone(),
+                MalShow {
+                    mal_id: entry.mal_id,
+                    episodes: entry.episodes,
+                },
+            );
+        }
+
+        annotations.update(&show_id, &name, |a| {
+            if a.score.is_none() {
+                a.score = entry.score;
+            }
+            if a.notes.is_empty() {
+                a.notes = entry.comments.clone();
+            }
+            if a.tags.is_empty() {
+                a.tags = entry.tags.iter().cloned().collect();
+            }
+        });
+
+        if history.import_progress(&show_id, &name, mode, progress, entry.finished_at()) {
+            imported += 1;
+            println!("+ {}: episode {}", name, progress);
+        } else {
+            up_to_date += 1;
+            println!("  {}: up to date", name);
+        }
+    }
+    history.save()?;
+    annotations.save()?;
+    ids.save()?;
+
+    println!(
+        "Imported {}, up to date {}, unmatched {}, {} not started skipped.",
+        imported, up_to_date, unmatched, not_started
+    );
+    Ok(())
+}
+
+/// Bars of a text sparkline, from lowest to highest.
+const SPARK_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Draw values as a one-line sparkline; zeros are left blank.
+fn sparkline(values: &[u64]) -> String {
+    let max = values.iter().copied().max().unwrap_or(0).max(1);
+    values
+        .iter()
+        .map(|&value| match value {
+            0 => ' ',
+            _ => SPARK_BARS[((value * 8 - 1) / max).min(7) as usize],
+        })
+        .collect()
+}
+
+/// Run `stats`: print watch statistics of the whole history.
+pub fn run_stats(json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let history = WatchHistory::load()?;
+    let stats = Stats::compute(&history, Local::now().date_naive());
+
+    if json {
+        let periods = |periods: &[stats::Period]| -> Vec<serde_json::Value> {
+            periods
+                .iter()
+                .map(|p| json!({ "start": p.start.to_string(), "episodes": p.episodes }))
+                .collect()
+        };
+        let top: Vec<_> = stats
+            .top_shows
+            .iter()
+            .map(|show| {
+                json!({
+                    "show_id": show.show_id,
+                    "name": show.name,
+                    "episodes": show.episodes,
+                    "hours": show.hours,
+                })
+            })
+            .collect();
+        println!(
+            "{}",
+            json!({
+                "episodes": stats.episodes,
+                "hours": stats.hours,
+                "weekly": periods(&stats.weekly),
+                "monthly": periods(&stats.monthly),
+                "top_shows": top,
+                "current_streak": stats.current_streak,
+                "longest_streak": stats.longest_streak,
+                "by_mode": stats.by_mode,
+            })
+        );
+        return Ok(());
+    }
+
+    if stats.episodes == 0 {
+        println!("No watched episodes yet.");
+        return Ok(());
+    }
+
+    println!(
+        "Watched {} episodes, about {:.0} hours",
+        stats.episodes, stats.hours
+    );
+    let days = |n: u32| format!("{} day{}", n, if n == 1 { "" } else { "s" });
+    println!(
+        "Streak: {} (longest {})",
+        days(stats.current_streak),
+        days(stats.longest_streak)
+    );
+    let modes: Vec<String> = stats
+        .by_mode
+        .iter()
+        .map(|(mode, episodes)| format!("{} {}", mode, episodes))
+        .collect();
+    println!("{}", modes.join(" · "));
+
+    let weekly: Vec<u64> = stats.weekly.iter().map(|p| p.episodes).collect();
+    println!("\nEpisodes per week (last {} weeks)", weekly.len());
+    println!(
+        "  │{}│ {} this week",
+        sparkline(&weekly),
+        weekly.last().copied().unwrap_or(0)
+    );
+
+    println!("\nEpisodes per month");
+    let max = stats
+        .monthly
+        .iter()
+        .map(|p| p.episodes)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    for period in &stats.monthly {
+        let width = (period.episodes * 30).div_ceil(max) as usize;
+        println!(
+            "  {}  {:<30} {}",
+            period.start.format("%Y-%m"),
+            "█".repeat(width),
+            period.episodes
+        );
+    }
+
+    println!("\nMost watched");
+    for (i, show) in stats.top_shows.iter().enumerate() {
+        println!(
+            "  {}. {}  {} episodes, ~{:.1} h",
+            i + 1,
+            show.name,
+            show.episodes,
+            show.hours
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn show(id: &str, name: &str, english: Option<&str>) -> Show {
+        Show {
+            id: id.to_string(),
+            name: name.to_string(),
+            english_name: english.map(|e| e.to_string()),
+            native_name: None,
+            available_episodes: 12,
+        }
+    }
+
+    #[test]
+    fn test_parse_episode_spec() {
+        let available = [1, 2, 3, 4, 5];
+        assert_eq!(
+            parse_episode_spec("5, 1-2 ,2", &available),
+            Ok(vec![1, 2, 5])
+        );
+        assert_eq!(parse_episode_spec("ALL", &available).unwrap().len(), 5);
+        assert!(parse_episode_spec("", &available).is_err());
+        assert!(parse_episode_spec("1-x", &available).is_err());
+        assert!(parse_episode_spec("1-9999999999", &[]).is_err());
+        assert!(parse_episode_spec("1-6000,7001-13000", &[]).is_err());
+        assert_eq!(parse_episode_spec("1-10000", &[]).unwrap().len(), 10_000);
+    }
+
+    #[test]
+    fn test_best_match() {
+        let shows = [
+            show("a1", "Sousou no Frieren 2nd Season", None),
+            show(
+                "b2",
+                "Sousou no Frieren",
+                Some("Frieren: Beyond Journey's End"),
+            ),
+        ];
+        assert_eq!(best_match(&shows, "b2").unwrap().id, "b2");
+        assert_eq!(best_match(&shows, "sousou no frieren").unwrap().id, "b2");
+        assert_eq!(
+            best_match(&shows, "Frieren: Beyond Journey's End")
+                .unwrap()
+                .id,
+            "b2"
+        );
+        assert_eq!(best_match(&shows, "frieren").unwrap().id, "a1");
+        assert!(best_match(&[], "frieren").is_none());
+        assert!(exact_match(&shows, "frieren").is_none());
+        assert!(exact_match(&shows, "c3").is_none());
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[0, 1, 4, 8]), " ▁▄█");
+        assert_eq!(sparkline(&[0, 0]), "  ");
+        assert_eq!(sparkline(&[3, 3]), "██");
+        assert_eq!(sparkline(&[]), "");
+    }
+}
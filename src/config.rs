@@ -3,12 +3,15 @@
 //! This module provides functionality for loading and saving user preferences
 //! from a TOML configuration file.
 
+use crate::anilist::DEFAULT_API_URL;
+use crate::download::DEFAULT_TEMPLATE;
+use crate::paths;
+use crate::persist;
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::style::Color;
 use serde::{Deserialize, Serialize};
-use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// A key binding that can match against key events.
 /// Supports format like "j", "Enter", "Esc", "Ctrl+c", "Up", "Down", etc.
@@ -37,8 +40,8 @@ impl KeyBinding {
         let binding = self.0.to_lowercase();
 
         // Check for modifier prefixes
-        let (has_ctrl, key_part) = if binding.starts_with("ctrl+") {
-            (true, &binding[5..])
+        let (has_ctrl, key_part) = if let Some(rest) = binding.strip_prefix("ctrl+") {
+            (true, rest)
         } else {
             (false, binding.as_str())
         };
@@ -140,11 +143,85 @@ pub struct Keybindings {
     /// Back to episode selection
     #[serde(default = "default_episodes")]
     pub episodes: Vec<KeyBinding>,
+    /// Follow or unfollow the current show
+    #[serde(default = "default_follow")]
+    pub follow: Vec<KeyBinding>,
 
     // Startup
     /// New search from startup
     #[serde(default = "default_new_search")]
     pub new_search: Vec<KeyBinding>,
+
+    // Download queue
+    /// Open the download queue
+    #[serde(default = "default_queue")]
+    pub queue: Vec<KeyBinding>,
+    /// Pause or resume the queue
+    #[serde(default = "default_pause")]
+    pub pause: Vec<KeyBinding>,
+    /// Move the selected item earlier
+    #[serde(default = "default_move_up")]
+    pub move_up: Vec<KeyBinding>,
+    /// Move the selected item later
+    #[serde(default = "default_move_down")]
+    pub move_down: Vec<KeyBinding>,
+    /// Retry failed items
+    #[serde(default = "default_retry")]
+    pub retry: Vec<KeyBinding>,
+    /// Remove the selected item
+    #[serde(default = "default_delete")]
+    pub delete: Vec<KeyBinding>,
+    /// Remove completed items
+    #[serde(default = "default_clear")]
+    pub clear: Vec<KeyBinding>,
+
+    // Watch history
+    /// Open the watch history
+    #[serde(default = "default_history")]
+    pub history: Vec<KeyBinding>,
+    /// Change the sort order
+    #[serde(default = "default_sort")]
+    pub sort: Vec<KeyBinding>,
+    /// Set the last watched episode of the selected show
+    #[serde(default = "default_set_episode")]
+    pub set_episode: Vec<KeyBinding>,
+    /// Mark every episode of the selected show watched
+    #[serde(default = "default_mark_finished")]
+    pub mark_finished: Vec<KeyBinding>,
+
+    // Watch statistics
+    /// Open the watch statistics
+    #[serde(default = "default_stats")]
+    pub stats: Vec<KeyBinding>,
+
+    // Watchlist
+    /// Open the watchlist
+    #[serde(default = "default_watchlist")]
+    pub watchlist: Vec<KeyBinding>,
+    /// Add the selected show to the watchlist
+    #[serde(default = "default_add_to_watchlist")]
+    pub add_to_watchlist: Vec<KeyBinding>,
+    /// Change the status of the selected show
+    #[serde(default = "default_set_status")]
+    pub set_status: Vec<KeyBinding>,
+    /// Change the priority of the selected show
+    #[serde(default = "default_set_priority")]
+    pub set_priority: Vec<KeyBinding>,
+    /// Show only one watchlist status, cycling through them
+    #[serde(default = "default_status_filter")]
+    pub status_filter: Vec<KeyBinding>,
+    /// Score the selected show
+    #[serde(default = "default_rate")]
+    pub rate: Vec<KeyBinding>,
+    /// Edit the notes of the selected show
+    #[serde(default = "default_edit_notes")]
+    pub edit_notes: Vec<KeyBinding>,
+    /// Edit the tags of the selected show
+    #[serde(default = "default_edit_tags")]
+    pub edit_tags: Vec<KeyBinding>,
+    /// Turn incognito mode on or off
+    #[serde(default = "default_incognito")]
+    pub incognito: Vec<KeyBinding>,
 }
 
 impl Default for Keybindings {
@@ -163,7 +240,29 @@ impl Default for Keybindings {
             previous: default_previous(),
             replay: default_replay(),
             episodes: default_episodes(),
+            follow: default_follow(),
             new_search: default_new_search(),
+            queue: default_queue(),
+            pause: default_pause(),
+            move_up: default_move_up(),
+            move_down: default_move_down(),
+            retry: default_retry(),
+            delete: default_delete(),
+            clear: default_clear(),
+            history: default_history(),
+            sort: default_sort(),
+            set_episode: default_set_episode(),
+            mark_finished: default_mark_finished(),
+            stats: default_stats(),
+            watchlist: default_watchlist(),
+            add_to_watchlist: default_add_to_watchlist(),
+            set_status: default_set_status(),
+            set_priority: default_set_priority(),
+            status_filter: default_status_filter(),
+            rate: default_rate(),
+            edit_notes: default_edit_notes(),
+            edit_tags: default_edit_tags(),
+            incognito: default_incognito(),
         }
     }
 }
@@ -245,11 +344,121 @@ fn default_episodes() -> Vec<KeyBinding> {
     vec![KeyBinding("e".to_string())]
 }
 
+/// Returns the default keybindings for following a show.
+fn default_follow() -> Vec<KeyBinding> {
+    vec![KeyBinding("+".to_string())]
+}
+
 /// Returns the default keybindings for starting a new search.
 fn default_new_search() -> Vec<KeyBinding> {
     vec![KeyBinding("s".to_string()), KeyBinding("n".to_string())]
 }
 
+/// Returns the default keybindings for opening the download queue.
+fn default_queue() -> Vec<KeyBinding> {
+    vec![KeyBinding("d".to_string())]
+}
+
+/// Returns the default keybindings for pausing or resuming the queue.
+fn default_pause() -> Vec<KeyBinding> {
+    vec![KeyBinding("Space".to_string())]
+}
+
+/// Returns the default keybindings for moving a queue item earlier.
+fn default_move_up() -> Vec<KeyBinding> {
+    vec![KeyBinding("[".to_string())]
+}
+
+/// Returns the default keybindings for moving a queue item later.
+fn default_move_down() -> Vec<KeyBinding> {
+    vec![KeyBinding("]".to_string())]
+}
+
+/// Returns the default keybindings for retrying failed downloads.
+fn default_retry() -> Vec<KeyBinding> {
+    vec![KeyBinding("r".to_string())]
+}
+
+/// Returns the default keybindings for removing an item.
+fn default_delete() -> Vec<KeyBinding> {
+    vec![KeyBinding("x".to_string())]
+}
+
+/// Returns the default keybindings for clearing completed items.
+fn default_clear() -> Vec<KeyBinding> {
+    vec![KeyBinding("c".to_string())]
+}
+
+/// Returns the default keybindings for opening the watch history.
+fn default_history() -> Vec<KeyBinding> {
+    vec![KeyBinding("h".to_string())]
+}
+
+/// Returns the default keybindings for changing the sort order.
+fn default_sort() -> Vec<KeyBinding> {
+    vec![KeyBinding("o".to_string())]
+}
+
+/// Returns the default keybindings for setting the last watched episode.
+fn default_set_episode() -> Vec<KeyBinding> {
+    vec![KeyBinding("e".to_string())]
+}
+
+/// Returns the default keybindings for marking a show finished.
+fn default_mark_finished() -> Vec<KeyBinding> {
+    vec![KeyBinding("m".to_string())]
+}
+
+/// Returns the default keybindings for opening the watch statistics.
+fn default_stats() -> Vec<KeyBinding> {
+    vec![KeyBinding("t".to_string())]
+}
+
+/// Returns the default keybindings for opening the watchlist.
+fn default_watchlist() -> Vec<KeyBinding> {
+    vec![KeyBinding("w".to_string())]
+}
+
+/// Returns the default keybindings for adding a show to the watchlist.
+fn default_add_to_watchlist() -> Vec<KeyBinding> {
+    vec![KeyBinding("a".to_string())]
+}
+
+/// Returns the default keybindings for changing a watchlist status.
+fn default_set_status() -> Vec<KeyBinding> {
+    vec![KeyBinding("Space".to_string())]
+}
+
+/// Returns the default keybindings for changing a watchlist priority.
+fn default_set_priority() -> Vec<KeyBinding> {
+    vec![KeyBinding("p".to_string())]
+}
+
+/// Returns the default keybindings for cycling the watchlist status filter.
+fn default_status_filter() -> Vec<KeyBinding> {
+    vec![KeyBinding("v".to_string())]
+}
+
+/// Returns the default keybindings for scoring a show.
+fn default_rate() -> Vec<KeyBinding> {
+    vec![KeyBinding("r".to_string())]
+}
+
+/// Returns the default keybindings for editing the notes of a show.
+fn default_edit_notes() -> Vec<KeyBinding> {
+    vec![KeyBinding("n".to_string())]
+}
+
+/// Returns the default keybindings for editing the tags of a show.
+fn default_edit_tags() -> Vec<KeyBinding> {
+    vec![KeyBinding("#".to_string())]
+}
+
+/// Returns the default keybindings for toggling incognito mode.
+fn default_incognito() -> Vec<KeyBinding> {
+    vec![KeyBinding("i".to_string())]
+}
+
 /// Color scheme configuration for the TUI.
 ///
 /// Colors can be specified as:
@@ -494,6 +703,34 @@ pub struct Config {
     #[serde(default = "default_download_dir")]
     pub download_dir: String,
 
+    /// Number of episodes to download in parallel
+    #[serde(default = "default_download_workers")]
+    pub download_workers: usize,
+
+    /// Filename template for downloads, relative to the download directory
+    #[serde(default = "default_filename_template")]
+    pub filename_template: String,
+
+    /// Download layout: "flat" (uses filename_template) or "media_server"
+    #[serde(default = "default_output_layout")]
+    pub output_layout: String,
+
+    /// Total download bandwidth cap, e.g. "500K" or "2M" (bytes per second)
+    #[serde(default)]
+    pub rate_limit: Option<String>,
+
+    /// Local time windows during which queued downloads start, e.g. "01:00-07:00"
+    #[serde(default)]
+    pub download_windows: Vec<String>,
+
+    /// Maximum size of the download directory, e.g. "200G"
+    #[serde(default)]
+    pub download_quota: Option<String>,
+
+    /// What to delete when the quota is reached: "none" or "watched"
+    #[serde(default = "default_quota_cleanup")]
+    pub quota_cleanup: String,
+
     /// Video player command (overrides platform default)
     #[serde(default)]
     pub player: Option<String>,
@@ -502,6 +739,18 @@ pub struct Config {
     #[serde(default)]
     pub player_args: Vec<String>,
 
+    /// Percentage of an episode that must play before it is marked watched (mpv only)
+    #[serde(default = "default_watched_threshold")]
+    pub watched_threshold: u8,
+
+    /// AniList GraphQL endpoint
+    #[serde(default = "default_anilist_api_url")]
+    pub anilist_api_url: String,
+
+    /// ID of your AniList API client, used to build the login link
+    #[serde(default)]
+    pub anilist_client_id: Option<String>,
+
     /// Log verbosity level: 0=error, 1=warn, 2=info, 3=debug, 4=trace
     #[serde(default = "default_log_level")]
     pub log_level: u8,
@@ -536,6 +785,36 @@ fn default_download_dir() -> String {
     ".".to_string()
 }
 
+/// Returns the default number of parallel download workers.
+fn default_download_workers() -> usize {
+    3
+}
+
+/// Returns the default download filename template.
+fn default_filename_template() -> String {
+    DEFAULT_TEMPLATE.to_string()
+}
+
+/// Returns the default output layout (flat).
+fn default_output_layout() -> String {
+    "flat".to_string()
+}
+
+/// Returns the default quota cleanup policy (delete nothing).
+fn default_quota_cleanup() -> String {
+    "none".to_string()
+}
+
+/// Returns the default watched threshold (85% played).
+fn default_watched_threshold() -> u8 {
+    85
+}
+
+/// Returns the default AniList endpoint.
+fn default_anilist_api_url() -> String {
+    DEFAULT_API_URL.to_string()
+}
+
 /// Returns the default log level (warn).
 fn default_log_level() -> u8 {
     1
@@ -548,8 +827,18 @@ impl Config {
             mode: default_mode(),
             quality: default_quality(),
             download_dir: default_download_dir(),
+            download_workers: default_download_workers(),
+            filename_template: default_filename_template(),
+            output_layout: default_output_layout(),
+            rate_limit: None,
+            download_windows: Vec::new(),
+            download_quota: None,
+            quota_cleanup: default_quota_cleanup(),
             player: None,
             player_args: Vec::new(),
+            watched_threshold: default_watched_threshold(),
+            anilist_api_url: default_anilist_api_url(),
+            anilist_client_id: None,
             log_level: default_log_level(),
             keybindings: Keybindings::default(),
             colors: ColorScheme::default(),
@@ -561,28 +850,31 @@ impl Config {
     /// Returns ~/.config/anime-watcher/config.toml on Linux,
     /// or a platform-appropriate location on other systems.
     pub fn get_config_path() -> Result<PathBuf, io::Error> {
-        let config_dir = dirs::config_dir()
-            .ok_or_else(|| {
-                io::Error::new(io::ErrorKind::NotFound, "Could not find config directory")
-            })?
-            .join("anime-watcher");
-
-        Ok(config_dir.join("config.toml"))
+        Ok(paths::config_dir()?.join("config.toml"))
     }
 
-    /// Load config from disk.
+    /// Load config from disk, with the overlay of the profile in use on top.
     ///
-    /// Returns default config if the file doesn't exist.
+    /// Returns default config if neither file exists.
     pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
-        let path = Self::get_config_path()?;
-
-        if !path.exists() {
-            return Ok(Self::new());
+        let mut table = Self::load_table(&Self::get_config_path()?)?;
+        if let Some(name) = paths::profile() {
+            merge_tables(
+                &mut table,
+                Self::load_table(&paths::profile_config_path(name)?)?,
+            );
         }
+        Ok(toml::Value::Table(table).try_into()?)
+    }
 
-        let content = fs::read_to_string(&path)?;
-        let config: Config = toml::from_str(&content)?;
-        Ok(config)
+    /// Read a config file as a TOML table, checking that it is a valid
+    /// config on its own so errors point at the right file.
+    fn load_table(path: &Path) -> Result<toml::Table, Box<dyn std::error::Error>> {
+        let table = persist::load(path, |content| {
+            toml::from_str::<Config>(content)?;
+            toml::from_str::<toml::Table>(content)
+        })?;
+        Ok(table.unwrap_or_default())
     }
 
     /// Save config to disk (reserved for future use).
@@ -591,14 +883,9 @@ impl Config {
     #[allow(dead_code)]
     pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
         let path = Self::get_config_path()?;
-
-        // Create parent directories if they don't exist
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
-        }
-
+        let _lock = persist::lock(&path)?;
         let content = toml::to_string_pretty(self)?;
-        fs::write(&path, content)?;
+        persist::write_atomic(&path, content.as_bytes())?;
         Ok(())
     }
 
@@ -618,6 +905,21 @@ impl Config {
     }
 }
 
+/// Lay the settings of `overlay` over `base`. Tables such as
+/// `[keybindings]` are merged key by key; other values are replaced.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => {
+                merge_tables(base, overlay)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -638,8 +940,18 @@ mod tests {
             mode: "dub".to_string(),
             quality: "720".to_string(),
             download_dir: "/tmp".to_string(),
+            download_workers: 2,
+            filename_template: "{show}/{episode:02}.{ext}".to_string(),
+            output_layout: "media_server".to_string(),
+            rate_limit: Some("2M".to_string()),
+            download_windows: vec!["01:00-07:00".to_string()],
+            download_quota: Some("200G".to_string()),
+            quota_cleanup: "watched".to_string(),
             player: Some("vlc".to_string()),
             player_args: vec!["--fullscreen".to_string()],
+            watched_threshold: 90,
+            anilist_api_url: "http://localhost:4000".to_string(),
+            anilist_client_id: Some("1234".to_string()),
             log_level: 2,
             keybindings: Keybindings::default(),
             colors: ColorScheme::default(),
@@ -679,6 +991,7 @@ mod tests {
         assert_eq!(config.mode, "dub");
         assert_eq!(config.quality, "best"); // default
         assert_eq!(config.download_dir, "."); // default
+        assert_eq!(config.download_workers, 3); // default
     }
 
     #[test]
@@ -835,4 +1148,33 @@ mod tests {
         // up should still have defaults
         assert_eq!(config.keybindings.up.len(), 2);
     }
+
+    #[test]
+    fn test_profile_overlay() {
+        let mut base: toml::Table = toml::from_str(
+            r#"
+            mode = "sub"
+            quality = "1080"
+            [keybindings]
+            quit = ["x"]
+            up = ["w"]
+        "#,
+        )
+        .unwrap();
+        let overlay: toml::Table = toml::from_str(
+            r#"
+            mode = "dub"
+            [keybindings]
+            up = ["i"]
+        "#,
+        )
+        .unwrap();
+        merge_tables(&mut base, overlay);
+
+        let config: Config = toml::Value::Table(base).try_into().unwrap();
+        assert_eq!(config.mode, "dub");
+        assert_eq!(config.quality, "1080");
+        assert_eq!(config.keybindings.up[0].0, "i");
+        assert_eq!(config.keybindings.quit[0].0, "x");
+    }
 }
@@ -0,0 +1,69 @@
+//! Dual-audio downloads.
+//!
+//! A "dual" download fetches both the sub and the dub release of an episode
+//! and muxes the dub's audio into the sub release with ffmpeg. The result
+//! keeps the sub release's video, chapters and subtitles, with the Japanese
+//! and English audio tracks tagged. Episodes without a dub, or systems
+//! without ffmpeg, get the sub release only.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+
+use super::subtitles::{fetch_subtitles, mux_mkv, remove_all};
+use super::{download_file, media_extension, part_path, verify_file, RateLimit};
+use crate::types::StreamSource;
+
+/// Download the sub and dub releases of an episode and mux them together.
+///
+/// `output_path` must have the `.mkv` extension. If the dub fails to
+/// download, the sub release is still muxed with its subtitles. If muxing
+/// fails, the sub release is kept as a plain video with sidecar subtitles.
+/// Returns the path the episode was saved to.
+pub async fn download_dual(
+    sub: &StreamSource,
+    dub: &StreamSource,
+    output_path: &Path,
+    rate_limit: &RateLimit,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    // Named as unfinished downloads, so `verify` cleans up after a crash
+    let sub_ext = media_extension(&sub.url);
+    let sub_video = part_path(&output_path.with_extension(format!("sub.{}", sub_ext)));
+    let dub_video =
+        part_path(&output_path.with_extension(format!("dub.{}", media_extension(&dub.url))));
+
+    download_file(&sub.url, &sub_video, rate_limit).await?;
+    let dub_error = download_file(&dub.url, &dub_video, rate_limit)
+        .await
+        .err()
+        .map(|e| e.to_string());
+    if let Some(e) = &dub_error {
+        warn!("Failed to download the dub, keeping the sub only: {}", e);
+    }
+    let subtitles = fetch_subtitles(&sub.subtitles, output_path).await;
+
+    let part = part_path(output_path);
+    let dub_audio = dub_error.is_none().then_some(dub_video.as_path());
+    let muxed = match mux_mkv(&sub_video, dub_audio, &subtitles, &part).await {
+        Ok(()) => verify_file(&part, None).await,
+        Err(e) => Err(e),
+    };
+    remove_all(std::iter::once(dub_video.as_path()));
+
+    match muxed {
+        Ok(()) => {
+            fs::rename(&part, output_path)?;
+            remove_all(std::iter::once(sub_video.as_path()));
+            remove_all(subtitles.iter().map(|(_, path)| path.as_path()));
+            Ok(output_path.to_path_buf())
+        }
+        Err(e) => {
+            warn!("Failed to mux {}: {}", output_path.display(), e);
+            remove_all(std::iter::once(part.as_path()));
+            let fallback = output_path.with_extension(sub_ext);
+            fs::rename(&sub_video, &fallback)?;
+            Ok(fallback)
+        }
+    }
+}
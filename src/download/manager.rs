@@ -0,0 +1,480 @@
+//! Concurrent download manager.
+//!
+//! Jobs submitted to the [`DownloadManager`] flow through two stages:
+//!
+//! 1. A resolver task fetches and picks stream sources for each job. It runs
+//!    ahead of the workers, bounded by the number of workers, so that a worker
+//!    finishing an episode can start the next one without waiting on the API.
+//! 2. A pool of workers downloads resolved jobs in parallel.
+//!
+//! In the media-server layout the resolver also fetches show metadata (once
+//! per show) so the workers can write NFO and poster sidecars.
+//!
+//! Progress is reported back through [`DownloadEvent`]s, which the UI loop
+//! drains without blocking.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use log::{debug, warn};
+use tokio::sync::{mpsc, Mutex};
+
+use super::nfo::write_sidecars;
+use super::subtitles::has_ffmpeg;
+use super::{
+    download_dual, download_with_subtitles, get_output_path, guess_season, is_complete,
+    output_extension, render_template, DownloadOptions, RateLimit, TemplateVars,
+};
+use crate::api::{choose_stream, fetch_show_details, fetch_stream_sources};
+use crate::types::{ShowDetails, StreamSource};
+
+/// A single episode to download.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadJob {
+    /// Identifier of the queue item this job belongs to.
+    pub id: u64,
+    /// Unique identifier for the show.
+    pub show_id: String,
+    /// Display name of the show.
+    pub show_name: String,
+    /// English title, if known.
+    pub english_name: Option<String>,
+    /// Original-language title, if known.
+    pub native_name: Option<String>,
+    /// Episode number to download.
+    pub episode: i64,
+    /// Translation mode (sub/dub).
+    pub mode: String,
+    /// Preferred quality: "best", "worst", or a number.
+    pub quality: String,
+    /// Directory the episode is saved to.
+    pub download_dir: PathBuf,
+}
+
+/// Final result of a download job.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadOutcome {
+    /// The episode was downloaded to the given path.
+    Completed(PathBuf),
+    /// The output file already existed, nothing was downloaded.
+    Skipped(PathBuf),
+    /// No stream sources were found for the episode.
+    NoSources,
+    /// The download failed with the given error message.
+    Failed(String),
+}
+
+/// Events reported by the download manager.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadEvent {
+    /// A worker changed what it is doing.
+    WorkerStatus {
+        /// Worker index (0-based).
+        worker: usize,
+        /// Human-readable status (e.g., "Ep 5: downloading").
+        message: String,
+    },
+    /// A job finished, successfully or not.
+    Finished {
+        /// The job that finished.
+        job: DownloadJob,
+        /// What happened to it.
+        outcome: DownloadOutcome,
+    },
+}
+
+/// A job whose stream source has already been chosen.
+struct ResolvedJob {
+    job: DownloadJob,
+    source: StreamSource,
+    /// Dub release to mux into `source` (dual-audio jobs only).
+    dub: Option<StreamSource>,
+    output_path: PathBuf,
+    /// Show metadata for sidecar files (media-server layout only).
+    details: Option<Arc<ShowDetails>>,
+}
+
+/// Runs episode downloads on a pool of background workers.
+///
+/// Must be created from within a Tokio runtime.
+pub struct DownloadManager {
+    workers: usize,
+    job_tx: mpsc::UnboundedSender<DownloadJob>,
+    event_rx: mpsc::UnboundedReceiver<DownloadEvent>,
+    in_flight: usize,
+}
+
+impl DownloadManager {
+    /// Create a manager and spawn its resolver and download workers.
+    ///
+    /// A worker count of zero is treated as one.
+    pub fn new(options: &DownloadOptions) -> Self {
+        let workers = options.workers.max(1);
+        let (job_tx, job_rx) = mpsc::unbounded_channel();
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        // The bounded channel limits how far the resolver runs ahead.
+        let (work_tx, work_rx) = mpsc::channel(workers);
+        let work_rx = Arc::new(Mutex::new(work_rx));
+        let rate_limit = Arc::new(RateLimit::new(options.rate_limit));
+
+        tokio::spawn(resolver_loop(
+            job_rx,
+            work_tx,
+            event_tx.clone(),
+            options.clone(),
+        ));
+        for worker in 0..workers {
+            tokio::spawn(worker_loop(
+                worker,
+                Arc::clone(&work_rx),
+                event_tx.clone(),
+                Arc::clone(&rate_limit),
+            ));
+        }
+
+        Self {
+            workers,
+            job_tx,
+            event_rx,
+            in_flight: 0,
+        }
+    }
+
+    /// Number of download workers.
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Queue a job for download.
+    pub fn submit(&mut self, job: DownloadJob) {
+        if self.job_tx.send(job).is_ok() {
+            self.in_flight += 1;
+        }
+    }
+
+    /// Whether the manager can take more work without piling up.
+    ///
+    /// Allows one resolved job waiting per worker on top of the active ones.
+    pub fn has_capacity(&self) -> bool {
+        self.in_flight < self.workers * 2
+    }
+
+    /// Take the next pending event without blocking.
+    pub fn try_next_event(&mut self) -> Option<DownloadEvent> {
+        let event = self.event_rx.try_recv().ok()?;
+        if matches!(event, DownloadEvent::Finished { .. }) {
+            self.in_flight = self.in_flight.saturating_sub(1);
+        }
+        Some(event)
+    }
+}
+
+impl DownloadJob {
+    /// Template values for this job, without source-specific details.
+    pub fn template_vars(&self) -> TemplateVars {
+        TemplateVars {
+            show: self.show_name.clone(),
+            english: self.english_name.clone(),
+            native: self.native_name.clone(),
+            episode: self.episode,
+            season: guess_season(&self.show_name),
+            mode: self.mode.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Extensions a finished download may have been saved with.
+pub(super) const KNOWN_EXTENSIONS: &[&str] = &["mp4", "ts", "mkv", "webm"];
+
+/// Find an already downloaded file for a job before resolving its sources.
+///
+/// Empty files (e.g. from an interrupted download) don't count.
+///
+/// Only possible when the template doesn't depend on the chosen source.
+fn existing_output(job: &DownloadJob, template: &str) -> Option<PathBuf> {
+    if template.contains("{quality") || template.contains("{provider") {
+        return None;
+    }
+    let mut vars = job.template_vars();
+    KNOWN_EXTENSIONS.iter().find_map(|ext| {
+        vars.ext = ext.to_string();
+        let path = get_output_path(&job.download_dir, template, &vars);
+        is_complete(&path).then_some(path)
+    })
+}
+
+/// Resolve stream sources for incoming jobs and hand them to the workers.
+async fn resolver_loop(
+    mut job_rx: mpsc::UnboundedReceiver<DownloadJob>,
+    work_tx: mpsc::Sender<ResolvedJob>,
+    event_tx: mpsc::UnboundedSender<DownloadEvent>,
+    options: DownloadOptions,
+) {
+    let template = options.filename_template;
+    let mut details_cache: HashMap<String, Arc<ShowDetails>> = HashMap::new();
+
+    while let Some(job) = job_rx.recv().await {
+        if let Some(output_path) = existing_output(&job, &template) {
+            let _ = event_tx.send(DownloadEvent::Finished {
+                job,
+                outcome: DownloadOutcome::Skipped(output_path),
+            });
+            continue;
+        }
+
+        debug!("Resolving sources for episode {}", job.episode);
+        let mode = if job.mode == "dual" { "sub" } else { &job.mode };
+        let source = match resolve_source(&job, mode).await {
+            Ok(Some(source)) => source,
+            Ok(None) => {
+                let _ = event_tx.send(DownloadEvent::Finished {
+                    job,
+                    outcome: DownloadOutcome::NoSources,
+                });
+                continue;
+            }
+            Err(e) => {
+                let _ = event_tx.send(DownloadEvent::Finished {
+                    job,
+                    outcome: DownloadOutcome::Failed(e),
+                });
+                continue;
+            }
+        };
+        let dub = if job.mode == "dual" {
+            resolve_dub(&job).await
+        } else {
+            None
+        };
+
+        let mut vars = job.template_vars();
+        vars.quality = source.quality;
+        vars.provider = source.provider.clone();
+        vars.ext = match dub {
+            Some(_) => "mkv".to_string(),
+            None => output_extension(&source).to_string(),
+        };
+        let output_path = get_output_path(&job.download_dir, &template, &vars);
+
+        if is_complete(&output_path) {
+            let _ = event_tx.send(DownloadEvent::Finished {
+                job,
+                outcome: DownloadOutcome::Skipped(output_path),
+            });
+            continue;
+        }
+
+        let details = if options.media_server {
+            Some(show_details(&mut details_cache, &job).await)
+        } else {
+            None
+        };
+
+        let resolved = ResolvedJob {
+            job,
+            source,
+            dub,
+            output_path,
+            details,
+        };
+        if work_tx.send(resolved).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Fetch the sources of a job's episode in `mode` and pick one.
+async fn resolve_source(job: &DownloadJob, mode: &str) -> Result<Option<StreamSource>, String> {
+    let sources = fetch_stream_sources(&job.show_id, mode, &job.episode.to_string())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(choose_stream(&sources, &job.quality).ok())
+}
+
+/// Find the dub release for a dual-audio job.
+///
+/// Returns `None`, so the sub release is downloaded alone, when the episode
+/// has no dub or ffmpeg isn't available to mux it.
+async fn resolve_dub(job: &DownloadJob) -> Option<StreamSource> {
+    if !has_ffmpeg() {
+        warn!("ffmpeg not found; downloading the sub release only");
+        return None;
+    }
+    match resolve_source(job, "dub").await {
+        Ok(Some(dub)) => Some(dub),
+        Ok(None) => {
+            debug!("No dub for episode {}; downloading sub only", job.episode);
+            None
+        }
+        Err(e) => {
+            warn!("Failed to fetch the dub of episode {}: {}", job.episode, e);
+            None
+        }
+    }
+}
+
+/// Get show metadata for a job, fetching it on first use.
+///
+/// Falls back to the titles known from the job if the API request fails, so
+/// the sidecars are still written.
+async fn show_details(
+    cache: &mut HashMap<String, Arc<ShowDetails>>,
+    job: &DownloadJob,
+) -> Arc<ShowDetails> {
+    if let Some(details) = cache.get(&job.show_id) {
+        return Arc::clone(details);
+    }
+
+    let details = match fetch_show_details(&job.show_id).await {
+        Ok(details) => details,
+        Err(e) => {
+            warn!("Failed to fetch details for {}: {}", job.show_name, e);
+            ShowDetails {
+                id: job.show_id.clone(),
+                name: job.show_name.clone(),
+                english_name: job.english_name.clone(),
+                native_name: job.native_name.clone(),
+                ..Default::default()
+            }
+        }
+    };
+    let details = Arc::new(details);
+    cache.insert(job.show_id.clone(), Arc::clone(&details));
+    details
+}
+
+/// Create the folders a templated output path needs.
+fn create_parent_dir(path: &Path) -> std::io::Result<()> {
+    match path.parent() {
+        Some(parent) => std::fs::create_dir_all(parent),
+        None => Ok(()),
+    }
+}
+
+/// Download a resolved job, returning the path it was saved to.
+async fn download_resolved(
+    resolved: &ResolvedJob,
+    rate_limit: &RateLimit,
+) -> Result<PathBuf, String> {
+    create_parent_dir(&resolved.output_path).map_err(|e| e.to_string())?;
+    let result = match &resolved.dub {
+        Some(dub) => download_dual(&resolved.source, dub, &resolved.output_path, rate_limit).await,
+        None => download_with_subtitles(&resolved.source, &resolved.output_path, rate_limit).await,
+    };
+    result.map_err(|e| e.to_string())
+}
+
+/// Download resolved jobs until the work channel closes.
+async fn worker_loop(
+    worker: usize,
+    work_rx: Arc<Mutex<mpsc::Receiver<ResolvedJob>>>,
+    event_tx: mpsc::UnboundedSender<DownloadEvent>,
+    rate_limit: Arc<RateLimit>,
+) {
+    loop {
+        let next = work_rx.lock().await.recv().await;
+        let Some(resolved) = next else {
+            break;
+        };
+
+        let _ = event_tx.send(DownloadEvent::WorkerStatus {
+            worker,
+            message: format!(
+                "Ep {}: downloading {}{}",
+                resolved.job.episode,
+                resolved.source.to_display(),
+                if resolved.dub.is_some() { " + dub" } else { "" }
+            ),
+        });
+
+        let outcome = match download_resolved(&resolved, &rate_limit).await {
+            Ok(path) => DownloadOutcome::Completed(path),
+            Err(e) => DownloadOutcome::Failed(e),
+        };
+
+        if let (DownloadOutcome::Completed(path), Some(details)) = (&outcome, &resolved.details) {
+            let vars = resolved.job.template_vars();
+            let show_dir = resolved
+                .job
+                .download_dir
+                .join(render_template("{show}", &vars));
+            if let Err(e) =
+                write_sidecars(path, &show_dir, details, vars.season, vars.episode).await
+            {
+                warn!("Failed to write sidecars for {}: {}", path.display(), e);
+            }
+        }
+
+        let _ = event_tx.send(DownloadEvent::WorkerStatus {
+            worker,
+            message: "idle".to_string(),
+        });
+        let _ = event_tx.send(DownloadEvent::Finished {
+            job: resolved.job,
+            outcome,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job(episode: i64, dir: &std::path::Path) -> DownloadJob {
+        DownloadJob {
+            id: episode as u64,
+            show_id: "show".to_string(),
+            show_name: "Test Show".to_string(),
+            english_name: None,
+            native_name: None,
+            episode,
+            mode: "sub".to_string(),
+            quality: "best".to_string(),
+            download_dir: dir.to_path_buf(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_existing_files_are_skipped() {
+        let dir = std::env::temp_dir().join(format!("aw-manager-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let existing = dir.join("Test Show - Episode 1 [sub].mkv");
+        std::fs::write(&existing, b"data").unwrap();
+
+        let mut manager = DownloadManager::new(&DownloadOptions {
+            workers: 2,
+            ..Default::default()
+        });
+        manager.submit(job(1, &dir));
+
+        let event = loop {
+            if let Some(event) = manager.try_next_event() {
+                break event;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        };
+
+        assert_eq!(
+            event,
+            DownloadEvent::Finished {
+                job: job(1, &dir),
+                outcome: DownloadOutcome::Skipped(existing),
+            }
+        );
+        assert!(manager.has_capacity());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_zero_workers_becomes_one() {
+        let manager = DownloadManager::new(&DownloadOptions {
+            workers: 0,
+            ..Default::default()
+        });
+        assert_eq!(manager.workers(), 1);
+        assert!(manager.has_capacity());
+    }
+}
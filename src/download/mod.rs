@@ -0,0 +1,283 @@
+//! Download functionality for saving anime episodes to disk.
+//!
+//! This module provides functions for downloading video files using yt-dlp
+//! (or plain HTTP for direct file links), a [`DownloadManager`] for
+//! downloading several episodes in parallel, and a persistent
+//! [`DownloadQueue`] that feeds it.
+
+mod dual;
+mod manager;
+mod native;
+mod nfo;
+mod queue;
+mod schedule;
+mod storage;
+mod subtitles;
+mod template;
+mod throttle;
+mod verify;
+
+pub use dual::download_dual;
+pub use manager::{DownloadEvent, DownloadJob, DownloadManager, DownloadOutcome};
+pub use native::is_direct_file;
+pub use nfo::MEDIA_SERVER_TEMPLATE;
+pub use queue::{DownloadQueue, QueueItem, QueueStatus};
+pub use schedule::Schedule;
+pub use storage::{check_space, estimate_batch, format_size, parse_size, CleanupPolicy, Quota};
+pub use subtitles::{download_with_subtitles, output_extension};
+pub use template::{
+    guess_season, media_extension, render_template, validate_template, TemplateVars,
+    DEFAULT_TEMPLATE,
+};
+pub use throttle::{parse_rate, RateLimit};
+pub use verify::{is_complete, part_path, verify_file, verify_queue};
+
+use crate::config::Config;
+use log::warn;
+use native::download_direct;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// Download a video from a URL and verify it.
+///
+/// Direct file links are fetched over HTTP, sharing `rate_limit` with the
+/// other workers; everything else (HLS streams,
+/// embed pages) goes through yt-dlp. The file is written to a `.part` path
+/// (see [`part_path`]) and only renamed to `output_path` after it passes
+/// [`verify_file`], so an interrupted download never looks finished.
+///
+/// # Arguments
+///
+/// * `url` - The URL to download from
+/// * `output_path` - The path where the file should be saved
+/// * `rate_limit` - Bandwidth cap shared by all downloads
+///
+/// # Returns
+///
+/// Ok(()) on success, or an error if the download or verification fails.
+pub async fn download_file(
+    url: &str,
+    output_path: &Path,
+    rate_limit: &RateLimit,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let part = part_path(output_path);
+    let mut share = rate_limit.begin();
+
+    let mut expected_len = None;
+    let mut done = false;
+    if is_direct_file(url) {
+        match download_direct(url, &part, rate_limit).await {
+            Ok(len) => {
+                expected_len = len;
+                done = true;
+            }
+            Err(e) => warn!("Direct download failed, falling back to yt-dlp: {}", e),
+        }
+    }
+    if !done {
+        let _ = fs::remove_file(&part);
+        run_yt_dlp(url, &part, share.reserve()).await?;
+    }
+
+    if let Err(e) = verify_file(&part, expected_len).await {
+        let _ = fs::remove_file(&part);
+        return Err(format!("Downloaded file is broken: {}", e).into());
+    }
+
+    fs::rename(&part, output_path)?;
+    Ok(())
+}
+
+/// Download a video with yt-dlp.
+///
+/// Uses yt-dlp to handle video extraction and downloading, which properly
+/// handles HLS streams, embed pages, and other video formats. The container
+/// follows the extension of `output_path`: `.ts` keeps HLS streams as
+/// MPEG-TS, anything else is merged into that container. `limit_rate` is
+/// passed through as `--limit-rate` (bytes per second).
+async fn run_yt_dlp(
+    url: &str,
+    output_path: &Path,
+    limit_rate: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let output_str = output_path.to_string_lossy();
+    let extension = output_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("mp4");
+
+    // Use yt-dlp for downloading - it handles extraction properly
+    let mut cmd = Command::new("yt-dlp");
+    cmd.arg("--no-warnings")
+        .arg("--no-check-certificate")
+        .arg("-o")
+        .arg(output_str.as_ref());
+    if extension == "ts" {
+        cmd.arg("--hls-use-mpegts");
+    } else {
+        cmd.arg("--merge-output-format").arg(extension);
+    }
+    if let Some(rate) = limit_rate {
+        cmd.arg("--limit-rate").arg(rate.to_string());
+    }
+
+    let status = cmd
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                "yt-dlp not found. Please install it: https://github.com/yt-dlp/yt-dlp".to_string()
+            } else {
+                format!("Failed to run yt-dlp: {}", e)
+            }
+        })?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("yt-dlp exited with status: {}", status.code().unwrap_or(-1)).into())
+    }
+}
+
+/// Download settings shared by every job.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadOptions {
+    /// Number of episodes downloaded in parallel.
+    pub workers: usize,
+    /// Filename template relative to the download directory.
+    pub filename_template: String,
+    /// Whether to write NFO and poster sidecars for media servers.
+    pub media_server: bool,
+    /// Total bandwidth cap in bytes per second, if any.
+    pub rate_limit: Option<u64>,
+    /// Size limit for the download directory, if any.
+    pub quota: Option<Quota>,
+}
+
+impl DownloadOptions {
+    /// Build download options from the user configuration.
+    ///
+    /// The "media_server" output layout replaces the filename template with
+    /// [`MEDIA_SERVER_TEMPLATE`] and turns on sidecar files.
+    pub fn from_config(config: &Config) -> Self {
+        let media_server = config.output_layout == "media_server";
+        let filename_template = if media_server {
+            MEDIA_SERVER_TEMPLATE.to_string()
+        } else {
+            config.filename_template.clone()
+        };
+
+        Self {
+            workers: config.download_workers,
+            filename_template,
+            media_server,
+            rate_limit: config
+                .rate_limit
+                .as_deref()
+                .and_then(|rate| parse_rate(rate).ok()),
+            quota: config
+                .download_quota
+                .as_deref()
+                .and_then(|size| parse_size(size).ok())
+                .map(|limit| Quota {
+                    limit,
+                    cleanup: CleanupPolicy::parse(&config.quota_cleanup).unwrap_or_default(),
+                }),
+        }
+    }
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            workers: 1,
+            filename_template: DEFAULT_TEMPLATE.to_string(),
+            media_server: false,
+            rate_limit: None,
+            quota: None,
+        }
+    }
+}
+
+/// Get the full output path for a download.
+///
+/// # Arguments
+///
+/// * `download_dir` - The download directory
+/// * `template` - Filename template (see [`render_template`])
+/// * `vars` - Values for the template placeholders
+///
+/// # Returns
+///
+/// The full path where the file should be saved.
+pub fn get_output_path(download_dir: &Path, template: &str, vars: &TemplateVars) -> PathBuf {
+    download_dir.join(render_template(template, vars))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_media_server_layout() {
+        let config = Config {
+            output_layout: "media_server".to_string(),
+            ..Config::default()
+        };
+        let options = DownloadOptions::from_config(&config);
+        assert!(options.media_server);
+
+        let vars = TemplateVars {
+            show: "Test Show".to_string(),
+            episode: 5,
+            season: 1,
+            ext: "mkv".to_string(),
+            ..Default::default()
+        };
+        let path = get_output_path(Path::new("/media"), &options.filename_template, &vars);
+        assert_eq!(
+            path,
+            PathBuf::from("/media/Test Show/Season 01/Test Show - S01E05.mkv")
+        );
+    }
+
+    #[test]
+    fn test_get_output_path() {
+        let vars = TemplateVars {
+            show: "Test Show".to_string(),
+            episode: 3,
+            mode: "sub".to_string(),
+            ext: "mp4".to_string(),
+            ..Default::default()
+        };
+        let path = get_output_path(Path::new("/downloads"), DEFAULT_TEMPLATE, &vars);
+        assert_eq!(
+            path,
+            PathBuf::from("/downloads/Test Show - Episode 3 [sub].mp4")
+        );
+    }
+
+    #[test]
+    fn test_get_output_path_with_folders() {
+        let vars = TemplateVars {
+            show: "Test: Show".to_string(),
+            episode: 3,
+            season: 2,
+            ext: "ts".to_string(),
+            ..Default::default()
+        };
+        let path = get_output_path(
+            Path::new("/downloads"),
+            "{show}/Season {season}/{episode:02}.{ext}",
+            &vars,
+        );
+        assert_eq!(path, PathBuf::from("/downloads/Test_ Show/Season 2/03.ts"));
+    }
+}
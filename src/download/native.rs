@@ -0,0 +1,66 @@
+//! Native HTTP downloads for direct video file links.
+//!
+//! Sources that point straight at an `.mp4`/`.mkv`/`.webm` file are fetched
+//! with reqwest instead of yt-dlp, which lets us check the received size
+//! against the server's Content-Length.
+
+use std::path::Path;
+use std::time::Duration;
+
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
+
+use super::RateLimit;
+use crate::api::USER_AGENT;
+
+/// Error type for native downloads; `Send` so it can cross worker tasks.
+pub type NativeError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Whether a URL points directly at a video file that can be fetched natively.
+///
+/// # Examples
+///
+/// ```
+/// use anime_watcher::download::is_direct_file;
+///
+/// assert!(is_direct_file("https://cdn.example.com/ep1.mp4?token=abc"));
+/// assert!(!is_direct_file("https://cdn.example.com/master.m3u8"));
+/// assert!(!is_direct_file("https://embed.example.com/e/abc"));
+/// ```
+pub fn is_direct_file(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+    path.starts_with("http")
+        && (path.ends_with(".mp4") || path.ends_with(".mkv") || path.ends_with(".webm"))
+}
+
+/// Stream a file to `output_path` chunk by chunk, within `rate_limit`.
+///
+/// Returns the Content-Length announced by the server, if any, so the caller
+/// can verify the result.
+pub async fn download_direct(
+    url: &str,
+    output_path: &Path,
+    rate_limit: &RateLimit,
+) -> Result<Option<u64>, NativeError> {
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(Duration::from_secs(30))
+        .build()?;
+
+    let mut resp = client
+        .get(url)
+        .header("Referer", "https://allmanga.to")
+        .send()
+        .await?
+        .error_for_status()?;
+    let expected_len = resp.content_length();
+
+    let mut file = File::create(output_path).await?;
+    while let Some(chunk) = resp.chunk().await? {
+        rate_limit.consume(chunk.len()).await;
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+
+    Ok(expected_len)
+}
@@ -0,0 +1,208 @@
+//! Sidecar files for media servers such as Jellyfin, Plex and Kodi.
+//!
+//! In the media-server layout each show gets a `tvshow.nfo` and a
+//! `poster.jpg` in its folder, and every episode gets an `.nfo` file next to
+//! the video. The NFO files use the Kodi XML format that these servers read.
+
+use std::fs;
+use std::path::Path;
+
+use log::warn;
+use regex::Regex;
+
+use crate::api::download_image;
+use crate::types::ShowDetails;
+
+/// Template producing `Show/Season 01/Show - S01E05.ext`.
+pub const MEDIA_SERVER_TEMPLATE: &str =
+    "{show}/Season {season:02}/{show} - S{season:02}E{episode:02}.{ext}";
+
+/// Escape text for use in XML element content.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Turn an HTML synopsis into plain text.
+fn strip_html(html: &str) -> String {
+    let breaks = Regex::new(r"(?i)<br\s*/?>").unwrap();
+    let tags = Regex::new(r"<[^>]*>").unwrap();
+
+    let text = breaks.replace_all(html, "\n");
+    let text = tags.replace_all(&text, "");
+    text.replace("&amp;", "&")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .trim()
+        .to_string()
+}
+
+/// Append `<name>value</name>` on its own indented line.
+fn push_element(xml: &mut String, name: &str, value: &str) {
+    xml.push_str(&format!("  <{name}>{}</{name}>\n", xml_escape(value)));
+}
+
+/// Generate the `tvshow.nfo` contents for a show.
+pub fn tvshow_nfo(details: &ShowDetails) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n");
+    xml.push_str("<tvshow>\n");
+
+    let title = details.english_name.as_deref().unwrap_or(&details.name);
+    push_element(&mut xml, "title", title);
+    if let Some(native) = &details.native_name {
+        push_element(&mut xml, "originaltitle", native);
+    }
+    if title != details.name {
+        push_element(&mut xml, "sorttitle", &details.name);
+    }
+    if let Some(description) = &details.description {
+        push_element(&mut xml, "plot", &strip_html(description));
+    }
+    if let Some(aired) = &details.aired_start {
+        if let Some(year) = aired.year {
+            push_element(&mut xml, "year", &year.to_string());
+        }
+        if let Some(premiered) = aired.to_iso() {
+            push_element(&mut xml, "premiered", &premiered);
+        }
+    }
+    for genre in &details.genres {
+        push_element(&mut xml, "genre", genre);
+    }
+    xml.push_str(&format!(
+        "  <uniqueid type=\"allanime\" default=\"true\">{}</uniqueid>\n",
+        xml_escape(&details.id)
+    ));
+
+    xml.push_str("</tvshow>\n");
+    xml
+}
+
+/// Generate the `.nfo` contents for a single episode.
+pub fn episode_nfo(details: &ShowDetails, season: i64, episode: i64) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n");
+    xml.push_str("<episodedetails>\n");
+
+    push_element(&mut xml, "title", &format!("Episode {}", episode));
+    let show_title = details.english_name.as_deref().unwrap_or(&details.name);
+    push_element(&mut xml, "showtitle", show_title);
+    push_element(&mut xml, "season", &season.to_string());
+    push_element(&mut xml, "episode", &episode.to_string());
+
+    xml.push_str("</episodedetails>\n");
+    xml
+}
+
+/// Write the show and episode sidecars for a downloaded episode.
+///
+/// Show-level files (`tvshow.nfo`, `poster.jpg`) are only written once. A
+/// failed poster download is logged and otherwise ignored.
+pub async fn write_sidecars(
+    video_path: &Path,
+    show_dir: &Path,
+    details: &ShowDetails,
+    season: i64,
+    episode: i64,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    fs::create_dir_all(show_dir)?;
+
+    let tvshow_path = show_dir.join("tvshow.nfo");
+    if !tvshow_path.exists() {
+        fs::write(&tvshow_path, tvshow_nfo(details))?;
+    }
+
+    fs::write(
+        video_path.with_extension("nfo"),
+        episode_nfo(details, season, episode),
+    )?;
+
+    let poster_path = show_dir.join("poster.jpg");
+    if let Some(url) = &details.thumbnail {
+        if !poster_path.exists() && url.starts_with("http") {
+            if let Err(e) = download_image(url, &poster_path).await {
+                warn!("Failed to download poster for {}: {}", details.name, e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AiredDate;
+
+    fn details() -> ShowDetails {
+        ShowDetails {
+            id: "abc123".to_string(),
+            name: "Sousou no Frieren".to_string(),
+            english_name: Some("Frieren: Beyond Journey's End".to_string()),
+            native_name: Some("葬送のフリーレン".to_string()),
+            description: Some("An elf &amp; her friends.<br>After the quest.".to_string()),
+            thumbnail: None,
+            aired_start: Some(AiredDate {
+                year: Some(2023),
+                month: Some(9),
+                date: Some(29),
+            }),
+            genres: vec!["Adventure".to_string(), "Fantasy".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_tvshow_nfo() {
+        let xml = tvshow_nfo(&details());
+        assert!(xml.contains("<title>Frieren: Beyond Journey&apos;s End</title>"));
+        assert!(xml.contains("<originaltitle>葬送のフリーレン</originaltitle>"));
+        assert!(xml.contains("<sorttitle>Sousou no Frieren</sorttitle>"));
+        assert!(xml.contains("<plot>An elf &amp; her friends.\nAfter the quest.</plot>"));
+        assert!(xml.contains("<premiered>2023-09-29</premiered>"));
+        assert!(xml.contains("<genre>Fantasy</genre>"));
+        assert!(xml.contains("<uniqueid type=\"allanime\" default=\"true\">abc123</uniqueid>"));
+    }
+
+    #[test]
+    fn test_xml_escape() {
+        assert_eq!(xml_escape("Tom & Jerry <3"), "Tom &amp; Jerry &lt;3");
+        assert_eq!(xml_escape("\"a\" 'b'"), "&quot;a&quot; &apos;b&apos;");
+    }
+
+    #[test]
+    fn test_episode_nfo() {
+        let xml = episode_nfo(&details(), 1, 5);
+        assert!(xml.contains("<title>Episode 5</title>"));
+        assert!(xml.contains("<season>1</season>"));
+        assert!(xml.contains("<episode>5</episode>"));
+    }
+
+    #[tokio::test]
+    async fn test_write_sidecars() {
+        let dir = std::env::temp_dir().join(format!("aw-nfo-{}", std::process::id()));
+        let season_dir = dir.join("Season 01");
+        fs::create_dir_all(&season_dir).unwrap();
+        let video = season_dir.join("Show - S01E05.mp4");
+
+        write_sidecars(&video, &dir, &details(), 1, 5)
+            .await
+            .unwrap();
+
+        assert!(dir.join("tvshow.nfo").exists());
+        assert!(season_dir.join("Show - S01E05.nfo").exists());
+        assert!(!dir.join("poster.jpg").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
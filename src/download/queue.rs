@@ -0,0 +1,531 @@
+//! Persistent download queue.
+//!
+//! The queue is stored as `queue.json` in the data directory so that a batch
+//! interrupted by quitting (or a closed terminal) resumes on the next launch.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::{DownloadJob, DownloadOutcome};
+use crate::paths;
+use crate::persist;
+use crate::types::Show;
+
+/// State of an item in the download queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueStatus {
+    /// Waiting to be downloaded.
+    Pending,
+    /// Handed to the download manager.
+    Active,
+    /// The last attempt failed.
+    Failed,
+    /// Downloaded (or already present on disk).
+    Completed,
+}
+
+impl QueueStatus {
+    /// Short label for display in the queue view.
+    pub fn label(&self) -> &'static str {
+        match self {
+            QueueStatus::Pending => "pending",
+            QueueStatus::Active => "active",
+            QueueStatus::Failed => "failed",
+            QueueStatus::Completed => "done",
+        }
+    }
+}
+
+/// An episode in the download queue.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueItem {
+    /// Unique identifier within the queue.
+    pub id: u64,
+    /// Unique identifier for the show.
+    pub show_id: String,
+    /// Display name of the show.
+    pub show_name: String,
+    /// English title, if known.
+    #[serde(default)]
+    pub english_name: Option<String>,
+    /// Original-language title, if known.
+    #[serde(default)]
+    pub native_name: Option<String>,
+    /// Episode number.
+    pub episode: i64,
+    /// Translation mode (sub/dub).
+    pub mode: String,
+    /// Preferred quality: "best", "worst", or a number.
+    pub quality: String,
+    /// Directory the episode is saved to.
+    pub download_dir: PathBuf,
+    /// Current state.
+    pub status: QueueStatus,
+    /// Error from the last failed attempt.
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Where the finished file was written.
+    #[serde(default)]
+    pub output_path: Option<PathBuf>,
+    /// Unix timestamp of when the item was queued.
+    pub added_at: u64,
+    /// Unix timestamp of the last status change.
+    #[serde(default)]
+    pub updated_at: u64,
+}
+
+impl QueueItem {
+    /// Key identifying the episode across queues: show, mode and episode.
+    pub fn key(&self) -> String {
+        format!("{}:{}:{}", self.show_id, self.mode, self.episode)
+    }
+
+    /// Change the status, updating the item's timestamp.
+    pub fn set_status(&mut self, status: QueueStatus) {
+        self.status = status;
+        self.updated_at = now();
+    }
+
+    /// Build the download job for this item.
+    pub fn to_job(&self) -> DownloadJob {
+        DownloadJob {
+            id: self.id,
+            show_id: self.show_id.clone(),
+            show_name: self.show_name.clone(),
+            english_name: self.english_name.clone(),
+            native_name: self.native_name.clone(),
+            episode: self.episode,
+            mode: self.mode.clone(),
+            quality: self.quality.clone(),
+            download_dir: self.download_dir.clone(),
+        }
+    }
+
+    /// Format the item for display in the queue view.
+    pub fn to_display(&self) -> String {
+        let mut line = format!(
+            "{} - Ep {} [{}] ({})",
+            self.show_name,
+            self.episode,
+            self.mode,
+            self.status.label()
+        );
+        if let (QueueStatus::Failed, Some(error)) = (self.status, &self.error) {
+            line.push_str(&format!(": {}", error));
+        }
+        line
+    }
+}
+
+/// The download queue, in download order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DownloadQueue {
+    /// Whether starting new downloads is paused.
+    #[serde(default)]
+    pub paused: bool,
+    /// Queued items, in the order they will be downloaded.
+    #[serde(default)]
+    pub items: Vec<QueueItem>,
+    /// Identifier for the next queued item.
+    #[serde(default)]
+    next_id: u64,
+    /// Episodes removed from the queue by [`QueueItem::key`], with the Unix
+    /// timestamp of removal.
+    ///
+    /// Kept so other instances don't save removed items back.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub removed: HashMap<String, u64>,
+}
+
+impl DownloadQueue {
+    /// Create a new empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the path to the queue file.
+    pub fn get_queue_path() -> Result<PathBuf, io::Error> {
+        Ok(paths::data_dir()?.join("queue.json"))
+    }
+
+    /// Load the queue from disk.
+    ///
+    /// Returns an empty queue if the file doesn't exist. Items that were
+    /// active when the previous session ended are put back to pending.
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::load_from(&Self::get_queue_path()?)
+    }
+
+    /// Load the queue from a specific file.
+    pub fn load_from(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let Some(mut queue) = persist::load_json::<Self>(path)? else {
+            return Ok(Self::new());
+        };
+        for item in &mut queue.items {
+            if item.status == QueueStatus::Active {
+                item.status = QueueStatus::Pending;
+            }
+        }
+        Ok(queue)
+    }
+
+    /// Find the newest backup of a queue file that can be parsed.
+    pub fn newest_backup(path: &Path) -> Option<(PathBuf, Self)> {
+        persist::newest_json_backup(path)
+    }
+
+    /// Save the queue to disk.
+    ///
+    /// Items queued or updated by other running instances (such as a `sync`
+    /// run while the TUI is open) are merged in rather than overwritten.
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.save_to(&Self::get_queue_path()?)
+    }
+
+    /// Save the queue to a specific file, merging with its contents.
+    pub fn save_to(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        persist::save_json(path, self, Self::merge)
+    }
+
+    /// Merge another queue into this one.
+    ///
+    /// Items are matched by show, episode and mode, and the more recently
+    /// updated status wins. The other queue's order and pause state are
+    /// kept; items only in this one follow, renumbered if their ids are
+    /// taken. Items removed since their last update are left out.
+    pub fn merge(&mut self, other: &DownloadQueue) {
+        let mut ours: Vec<QueueItem> = std::mem::take(&mut self.items);
+        let mut items = Vec::with_capacity(ours.len() + other.items.len());
+        for theirs in &other.items {
+            match ours.iter().position(|i| i.key() == theirs.key()) {
+                Some(pos) => {
+                    let mut item = ours.remove(pos);
+                    if theirs.updated_at >= item.updated_at {
+                        item = theirs.clone();
+                    }
+                    item.id = theirs.id;
+                    items.push(item);
+                }
+                None => items.push(theirs.clone()),
+            }
+        }
+
+        self.next_id = self.next_id.max(other.next_id);
+        for mut item in ours {
+            if items.iter().any(|i| i.id == item.id) {
+                self.next_id += 1;
+                item.id = self.next_id;
+            }
+            items.push(item);
+        }
+        self.items = items;
+        self.paused = other.paused;
+
+        for (key, removed_at) in &other.removed {
+            let entry = self.removed.entry(key.clone()).or_insert(*removed_at);
+            *entry = (*entry).max(*removed_at);
+        }
+        let removed = &self.removed;
+        self.items.retain(|item| {
+            removed
+                .get(&item.key())
+                .is_none_or(|removed_at| item.updated_at > *removed_at)
+        });
+    }
+
+    /// Add an episode to the end of the queue.
+    ///
+    /// Episodes that are already pending or active are not added twice;
+    /// finished or failed ones are queued again. Returns the item id.
+    pub fn enqueue(
+        &mut self,
+        show: &Show,
+        episode: i64,
+        mode: &str,
+        quality: &str,
+        download_dir: &Path,
+    ) -> u64 {
+        let show_id = show.id.as_str();
+        if let Some(item) = self.items.iter().find(|i| {
+            i.show_id == show_id
+                && i.episode == episode
+                && i.mode == mode
+                && matches!(i.status, QueueStatus::Pending | QueueStatus::Active)
+        }) {
+            return item.id;
+        }
+
+        // Drop the stale entry so the episode appears once
+        self.items
+            .retain(|i| !(i.show_id == show_id && i.episode == episode && i.mode == mode));
+
+        // Queued again after a removal, the item must be newer than it
+        let key = format!("{}:{}:{}", show_id, mode, episode);
+        let updated_at = match self.removed.remove(&key) {
+            Some(removed_at) => now().max(removed_at + 1),
+            None => now(),
+        };
+
+        self.next_id += 1;
+        let id = self.next_id;
+        self.items.push(QueueItem {
+            id,
+            show_id: show.id.clone(),
+            show_name: show.name.clone(),
+            english_name: show.english_name.clone(),
+            native_name: show.native_name.clone(),
+            episode,
+            mode: mode.to_string(),
+            quality: quality.to_string(),
+            download_dir: download_dir.to_path_buf(),
+            status: QueueStatus::Pending,
+            error: None,
+            output_path: None,
+            added_at: now(),
+            updated_at,
+        });
+        id
+    }
+
+    /// Mark the first pending item active and return its download job.
+    ///
+    /// Returns `None` when the queue is paused or nothing is pending.
+    pub fn start_next(&mut self) -> Option<DownloadJob> {
+        if self.paused {
+            return None;
+        }
+        let item = self
+            .items
+            .iter_mut()
+            .find(|i| i.status == QueueStatus::Pending)?;
+        item.set_status(QueueStatus::Active);
+        Some(item.to_job())
+    }
+
+    /// Record the outcome of a finished download.
+    pub fn finish(&mut self, id: u64, outcome: &DownloadOutcome) {
+        let Some(item) = self.items.iter_mut().find(|i| i.id == id) else {
+            return;
+        };
+        match outcome {
+            DownloadOutcome::Completed(path) | DownloadOutcome::Skipped(path) => {
+                item.set_status(QueueStatus::Completed);
+                item.error = None;
+                item.output_path = Some(path.clone());
+            }
+            DownloadOutcome::NoSources => {
+                item.set_status(QueueStatus::Failed);
+                item.error = Some("no sources".to_string());
+            }
+            DownloadOutcome::Failed(e) => {
+                item.set_status(QueueStatus::Failed);
+                item.error = Some(e.clone());
+            }
+        }
+    }
+
+    /// Put every failed item back to pending. Returns how many were retried.
+    pub fn retry_failed(&mut self) -> usize {
+        let mut count = 0;
+        for item in &mut self.items {
+            if item.status == QueueStatus::Failed {
+                item.set_status(QueueStatus::Pending);
+                item.error = None;
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Move an item one position earlier (`up`) or later in the queue.
+    pub fn move_item(&mut self, id: u64, up: bool) {
+        let Some(pos) = self.items.iter().position(|i| i.id == id) else {
+            return;
+        };
+        if up && pos > 0 {
+            self.items.swap(pos, pos - 1);
+        } else if !up && pos + 1 < self.items.len() {
+            self.items.swap(pos, pos + 1);
+        }
+    }
+
+    /// Remove an item that is not currently downloading.
+    pub fn remove(&mut self, id: u64) {
+        self.remove_where(|i| i.id == id && i.status != QueueStatus::Active);
+    }
+
+    /// Remove all completed items.
+    pub fn clear_completed(&mut self) {
+        self.remove_where(|i| i.status == QueueStatus::Completed);
+    }
+
+    /// Remove the items matching `remove`, remembering their removal.
+    fn remove_where(&mut self, remove: impl Fn(&QueueItem) -> bool) {
+        let timestamp = now();
+        let removed = &mut self.removed;
+        self.items.retain(|item| {
+            if !remove(item) {
+                return true;
+            }
+            removed.insert(item.key(), timestamp);
+            false
+        });
+    }
+
+    /// Number of items still waiting or downloading.
+    pub fn remaining(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|i| matches!(i.status, QueueStatus::Pending | QueueStatus::Active))
+            .count()
+    }
+}
+
+/// Current Unix timestamp in seconds.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn show() -> Show {
+        Show {
+            id: "show".to_string(),
+            name: "Show".to_string(),
+            english_name: None,
+            native_name: None,
+            available_episodes: 12,
+        }
+    }
+
+    fn queue_with(episodes: &[i64]) -> DownloadQueue {
+        let mut queue = DownloadQueue::new();
+        for &ep in episodes {
+            queue.enqueue(&show(), ep, "sub", "best", Path::new("/tmp"));
+        }
+        queue
+    }
+
+    #[test]
+    fn test_enqueue_skips_duplicates() {
+        let mut queue = queue_with(&[1, 2]);
+        let id = queue.enqueue(&show(), 1, "sub", "best", Path::new("/tmp"));
+        assert_eq!(id, queue.items[0].id);
+        assert_eq!(queue.items.len(), 2);
+    }
+
+    #[test]
+    fn test_start_next_respects_order_and_pause() {
+        let mut queue = queue_with(&[1, 2]);
+        queue.move_item(queue.items[1].id, true);
+
+        queue.paused = true;
+        assert!(queue.start_next().is_none());
+
+        queue.paused = false;
+        let job = queue.start_next().unwrap();
+        assert_eq!(job.episode, 2);
+        assert_eq!(queue.items[0].status, QueueStatus::Active);
+    }
+
+    #[test]
+    fn test_finish_and_retry_failed() {
+        let mut queue = queue_with(&[1, 2]);
+        let a = queue.start_next().unwrap();
+        let b = queue.start_next().unwrap();
+        queue.finish(
+            a.id,
+            &DownloadOutcome::Completed(PathBuf::from("/tmp/1.mp4")),
+        );
+        queue.finish(b.id, &DownloadOutcome::Failed("boom".to_string()));
+
+        assert_eq!(queue.items[0].status, QueueStatus::Completed);
+        assert_eq!(queue.items[1].status, QueueStatus::Failed);
+        assert_eq!(queue.remaining(), 0);
+
+        assert_eq!(queue.retry_failed(), 1);
+        assert_eq!(queue.items[1].status, QueueStatus::Pending);
+        assert!(queue.items[1].error.is_none());
+    }
+
+    #[test]
+    fn test_remove_keeps_active_items() {
+        let mut queue = queue_with(&[1, 2]);
+        let active = queue.start_next().unwrap();
+        queue.remove(active.id);
+        queue.remove(queue.items[1].id);
+        assert_eq!(queue.items.len(), 1);
+        assert_eq!(queue.items[0].id, active.id);
+    }
+
+    #[test]
+    fn test_merge_keeps_other_instances_items() {
+        // The TUI and a `sync` run both loaded the same queue
+        let mut saved = queue_with(&[1, 2]);
+        let mut tui = saved.clone();
+        let mut sync = saved.clone();
+
+        let job = tui.start_next().unwrap();
+        tui.finish(
+            job.id,
+            &DownloadOutcome::Completed(PathBuf::from("/tmp/1.mp4")),
+        );
+        tui.items[0].updated_at += 10;
+        sync.enqueue(&show(), 3, "sub", "best", Path::new("/tmp"));
+        let mut other = show();
+        other.id = "other".to_string();
+        tui.enqueue(&other, 1, "sub", "best", Path::new("/tmp"));
+        tui.remove(tui.items[1].id);
+
+        saved.merge(&sync);
+        saved.merge(&tui);
+        let episodes: Vec<(&str, i64, QueueStatus)> = saved
+            .items
+            .iter()
+            .map(|i| (i.show_id.as_str(), i.episode, i.status))
+            .collect();
+        assert_eq!(
+            episodes,
+            [
+                ("show", 1, QueueStatus::Completed),
+                ("other", 1, QueueStatus::Pending),
+                ("show", 3, QueueStatus::Pending),
+            ]
+        );
+        let mut ids: Vec<u64> = saved.items.iter().map(|i| i.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 3);
+
+        // The sync run's stale copy doesn't undo the TUI's progress
+        saved.merge(&sync);
+        assert_eq!(saved.items[0].status, QueueStatus::Completed);
+        assert_eq!(saved.remaining(), 2);
+    }
+
+    #[test]
+    fn test_load_resets_active_items() {
+        let path = std::env::temp_dir().join(format!("aw-queue-{}.json", std::process::id()));
+        let mut queue = queue_with(&[1]);
+        queue.start_next();
+        queue.save_to(&path).unwrap();
+
+        let loaded = DownloadQueue::load_from(&path).unwrap();
+        assert_eq!(loaded.items[0].status, QueueStatus::Pending);
+        assert_eq!(loaded.remaining(), 1);
+
+        fs::remove_file(&path).unwrap();
+        let _ = fs::remove_file(path.with_extension("json.lock"));
+    }
+}
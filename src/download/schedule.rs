@@ -0,0 +1,139 @@
+//! Time windows during which queued downloads may start.
+//!
+//! Windows are written as `"HH:MM-HH:MM"` in local time. A window whose end
+//! is before its start wraps past midnight, e.g. `"23:00-06:00"`. With no
+//! windows configured downloads may run at any time.
+
+use chrono::NaiveTime;
+
+/// A daily time window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeWindow {
+    /// Start of the window (inclusive).
+    pub start: NaiveTime,
+    /// End of the window (exclusive).
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    /// Parse a window such as `"01:00-07:00"`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (start, end) = spec
+            .split_once('-')
+            .ok_or_else(|| format!("invalid download window '{}': expected HH:MM-HH:MM", spec))?;
+        let parse_time = |s: &str| {
+            NaiveTime::parse_from_str(s.trim(), "%H:%M")
+                .map_err(|_| format!("invalid time '{}' in download window '{}'", s.trim(), spec))
+        };
+
+        let window = Self {
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        };
+        if window.start == window.end {
+            return Err(format!("download window '{}' is empty", spec));
+        }
+        Ok(window)
+    }
+
+    /// Whether `time` falls inside this window.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+/// The configured download windows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schedule {
+    windows: Vec<TimeWindow>,
+}
+
+impl Schedule {
+    /// Parse every window; fails on the first invalid one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anime_watcher::download::Schedule;
+    ///
+    /// assert!(Schedule::parse(&["01:00-07:00".to_string()]).is_ok());
+    /// assert!(Schedule::parse(&["25:00-07:00".to_string()]).is_err());
+    /// ```
+    pub fn parse(specs: &[String]) -> Result<Self, String> {
+        let windows = specs
+            .iter()
+            .map(|s| TimeWindow::parse(s))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { windows })
+    }
+
+    /// Whether downloads may start at `time`.
+    pub fn is_open(&self, time: NaiveTime) -> bool {
+        self.windows.is_empty() || self.windows.iter().any(|w| w.contains(time))
+    }
+
+    /// Start of the next window after `time`, or `None` if already open.
+    pub fn next_opening(&self, time: NaiveTime) -> Option<NaiveTime> {
+        if self.is_open(time) {
+            return None;
+        }
+        // Starts later today come first, then wrap around to tomorrow.
+        self.windows
+            .iter()
+            .map(|w| w.start)
+            .min_by_key(|start| (*start < time, *start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn t(s: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(s, "%H:%M").unwrap()
+    }
+
+    fn schedule(specs: &[&str]) -> Schedule {
+        let specs: Vec<String> = specs.iter().map(|s| s.to_string()).collect();
+        Schedule::parse(&specs).unwrap()
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_windows() {
+        assert!(TimeWindow::parse("01:00").is_err());
+        assert!(TimeWindow::parse("1am-7am").is_err());
+        assert!(TimeWindow::parse("03:00-03:00").is_err());
+    }
+
+    #[test]
+    fn test_window_contains() {
+        let night = TimeWindow::parse("01:00-07:00").unwrap();
+        assert!(night.contains(t("01:00")));
+        assert!(night.contains(t("06:59")));
+        assert!(!night.contains(t("07:00")));
+
+        let wrapping = TimeWindow::parse("23:00-02:00").unwrap();
+        assert!(wrapping.contains(t("23:30")));
+        assert!(wrapping.contains(t("01:00")));
+        assert!(!wrapping.contains(t("12:00")));
+    }
+
+    #[test]
+    fn test_empty_schedule_is_always_open() {
+        let always = Schedule::default();
+        assert!(always.is_open(t("15:00")));
+        assert_eq!(always.next_opening(t("15:00")), None);
+    }
+
+    #[test]
+    fn test_next_opening() {
+        let s = schedule(&["01:00-07:00", "13:00-14:00"]);
+        assert_eq!(s.next_opening(t("03:00")), None);
+        assert_eq!(s.next_opening(t("09:00")), Some(t("13:00")));
+        assert_eq!(s.next_opening(t("20:00")), Some(t("01:00")));
+    }
+}
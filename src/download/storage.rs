@@ -0,0 +1,684 @@
+//! Disk space checks and the download quota.
+//!
+//! Before a batch starts, its size is estimated from a few sample episodes:
+//! the server's Content-Length for direct file links, or the playlist bitrate
+//! times its duration for HLS streams. [`check_space`] compares the estimate
+//! against the free space on the download disk and the optional quota for
+//! the download directory. With the "watched" cleanup policy, episodes the
+//! watch history marks as watched are deleted, oldest first, to make room.
+//! They are found by matching the files in the download directory against
+//! the filename templates, so the download queue doesn't need to know them.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use log::debug;
+
+use super::manager::KNOWN_EXTENSIONS;
+use super::template::template_regex;
+use super::{DownloadQueue, QueueStatus, DEFAULT_TEMPLATE, MEDIA_SERVER_TEMPLATE};
+use crate::api::{choose_stream, fetch_stream_sources, USER_AGENT};
+use crate::history::WatchHistory;
+
+/// Number of episodes probed to estimate the size of a batch.
+const SAMPLE_EPISODES: usize = 3;
+
+/// Parse a byte count with an optional K/M/G/T suffix (binary multiples).
+pub(super) fn parse_bytes(spec: &str) -> Option<u64> {
+    let spec = spec.trim();
+    let (number, multiplier) = match spec.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&spec[..spec.len() - 1], 1024.0),
+        Some('M') => (&spec[..spec.len() - 1], 1024.0 * 1024.0),
+        Some('G') => (&spec[..spec.len() - 1], 1024.0 * 1024.0 * 1024.0),
+        Some('T') => (&spec[..spec.len() - 1], 1024.0 * 1024.0 * 1024.0 * 1024.0),
+        _ => (spec, 1.0),
+    };
+
+    let value: f64 = number.trim().parse().ok()?;
+    if !value.is_finite() || value < 0.0 {
+        return None;
+    }
+    Some((value * multiplier) as u64)
+}
+
+/// Parse a size such as `"200G"` or `"1.5T"` into bytes.
+///
+/// # Examples
+///
+/// ```
+/// use anime_watcher::download::parse_size;
+///
+/// assert_eq!(parse_size("500M"), Ok(524_288_000));
+/// assert_eq!(parse_size("2G"), Ok(2_147_483_648));
+/// assert!(parse_size("lots").is_err());
+/// ```
+pub fn parse_size(spec: &str) -> Result<u64, String> {
+    match parse_bytes(spec) {
+        Some(0) => Err(format!("size '{}' must be greater than zero", spec)),
+        Some(bytes) => Ok(bytes),
+        None => Err(format!("invalid size '{}': use e.g. 500M or 200G", spec)),
+    }
+}
+
+/// Format a byte count for display, e.g. `"1.4 GiB"`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// What to delete when the download quota is reached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CleanupPolicy {
+    /// Never delete anything; refuse the batch instead.
+    #[default]
+    None,
+    /// Delete episodes already marked watched, oldest first.
+    Watched,
+}
+
+impl CleanupPolicy {
+    /// Parse the `quota_cleanup` config value.
+    pub fn parse(policy: &str) -> Result<Self, String> {
+        match policy {
+            "none" => Ok(Self::None),
+            "watched" => Ok(Self::Watched),
+            other => Err(format!(
+                "invalid quota_cleanup '{}': use 'none' or 'watched'",
+                other
+            )),
+        }
+    }
+}
+
+/// A size limit for the download directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    /// Maximum size of the download directory in bytes.
+    pub limit: u64,
+    /// What to delete when the limit is reached.
+    pub cleanup: CleanupPolicy,
+}
+
+/// Total size of all files below `dir`.
+pub fn dir_usage(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                dir_usage(&path)
+            } else {
+                entry.metadata().map(|m| m.len()).unwrap_or(0)
+            }
+        })
+        .sum()
+}
+
+/// Free space on the disk holding `dir`, which may not exist yet.
+pub fn available_space(dir: &Path) -> io::Result<u64> {
+    let existing = dir
+        .ancestors()
+        .find(|p| p.exists())
+        .unwrap_or_else(|| Path::new("."));
+    fs4::available_space(existing)
+}
+
+/// Resolve a playlist entry relative to the playlist's URL.
+fn resolve_url(base: &str, uri: &str) -> Option<String> {
+    reqwest::Url::parse(base)
+        .and_then(|base| base.join(uri))
+        .map(|url| url.to_string())
+        .ok()
+}
+
+/// Pick the highest-bandwidth variant of an HLS master playlist.
+///
+/// Returns the variant's bits per second (preferring `AVERAGE-BANDWIDTH`)
+/// and its URI as written in the playlist.
+fn hls_variant(master: &str) -> Option<(u64, String)> {
+    let attribute = |info: &str, name: &str| -> Option<u64> {
+        info.split(',')
+            .filter_map(|attr| attr.split_once('='))
+            .find(|(key, _)| key.trim() == name)
+            .and_then(|(_, value)| value.trim().parse().ok())
+    };
+
+    let mut lines = master.lines().map(str::trim);
+    // (peak bandwidth, average bandwidth, uri) of the best variant so far
+    let mut best: Option<(u64, u64, &str)> = None;
+    while let Some(line) = lines.next() {
+        let Some(info) = line.strip_prefix("#EXT-X-STREAM-INF:") else {
+            continue;
+        };
+        let Some(uri) = lines
+            .by_ref()
+            .find(|l| !l.is_empty() && !l.starts_with('#'))
+        else {
+            break;
+        };
+        let peak = attribute(info, "BANDWIDTH").unwrap_or(0);
+        let average = attribute(info, "AVERAGE-BANDWIDTH").unwrap_or(peak);
+        if best.is_none_or(|(best_peak, _, _)| peak > best_peak) {
+            best = Some((peak, average, uri));
+        }
+    }
+    best.filter(|(_, average, _)| *average > 0)
+        .map(|(_, average, uri)| (average, uri.to_string()))
+}
+
+/// Total duration in seconds of an HLS media playlist.
+fn hls_duration(media: &str) -> f64 {
+    media
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("#EXTINF:"))
+        .filter_map(|info| info.split(',').next()?.trim().parse::<f64>().ok())
+        .sum()
+}
+
+/// Estimate the size of a stream in bytes.
+async fn probe_size(client: &reqwest::Client, url: &str) -> Option<u64> {
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+
+    if super::is_direct_file(url) {
+        let resp = client
+            .head(url)
+            .send()
+            .await
+            .ok()?
+            .error_for_status()
+            .ok()?;
+        return resp.content_length().filter(|len| *len > 0);
+    }
+    if !path.ends_with(".m3u8") {
+        // Embed pages are resolved by yt-dlp; their size isn't known up front.
+        return None;
+    }
+
+    let master = client.get(url).send().await.ok()?.text().await.ok()?;
+    let (bandwidth, uri) = hls_variant(&master)?;
+    let variant_url = resolve_url(url, &uri)?;
+    let media = client
+        .get(&variant_url)
+        .send()
+        .await
+        .ok()?
+        .text()
+        .await
+        .ok()?;
+    let seconds = hls_duration(&media);
+    (seconds > 0.0).then(|| (bandwidth as f64 / 8.0 * seconds) as u64)
+}
+
+/// Estimate the size of one episode, or `None` if it can't be determined.
+pub async fn estimate_episode(
+    show_id: &str,
+    mode: &str,
+    quality: &str,
+    episode: i64,
+) -> Option<u64> {
+    let sources = fetch_stream_sources(show_id, mode, &episode.to_string())
+        .await
+        .ok()?;
+    let source = choose_stream(&sources, quality).ok()?;
+
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(Duration::from_secs(15))
+        .default_headers(
+            [(
+                reqwest::header::REFERER,
+                reqwest::header::HeaderValue::from_static("https://allmanga.to"),
+            )]
+            .into_iter()
+            .collect(),
+        )
+        .build()
+        .ok()?;
+    probe_size(&client, &source.url).await
+}
+
+/// Estimate the total size of downloading `episodes` of a show.
+///
+/// Probes up to three episodes spread across the batch and extrapolates
+/// their average. Returns `None` if none of them could be sized.
+pub async fn estimate_batch(
+    show_id: &str,
+    mode: &str,
+    quality: &str,
+    episodes: &[i64],
+) -> Option<u64> {
+    if episodes.is_empty() {
+        return Some(0);
+    }
+
+    let step = episodes.len().div_ceil(SAMPLE_EPISODES).max(1);
+    let mut sizes = Vec::new();
+    for &episode in episodes.iter().step_by(step).take(SAMPLE_EPISODES) {
+        match estimate_episode(show_id, mode, quality, episode).await {
+            Some(size) => sizes.push(size),
+            None => debug!("Couldn't estimate the size of episode {}", episode),
+        }
+    }
+
+    if sizes.is_empty() {
+        return None;
+    }
+    let average = sizes.iter().sum::<u64>() / sizes.len() as u64;
+    Some(average * episodes.len() as u64)
+}
+
+/// A downloaded episode that the cleanup policy may delete.
+#[derive(Debug, Clone)]
+struct Deletable {
+    path: PathBuf,
+    len: u64,
+    modified: SystemTime,
+}
+
+/// Every file below `dir`.
+fn files_below(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .flat_map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                files_below(&path)
+            } else {
+                vec![path]
+            }
+        })
+        .collect()
+}
+
+/// Whether an episode of a show downloaded in `mode` has been watched in
+/// every one of `histories`.
+///
+/// A dual-audio release, or a file whose name doesn't give the mode,
+/// counts once either track is watched.
+fn is_watched(
+    histories: &[&WatchHistory],
+    show_id: &str,
+    mode: Option<&str>,
+    episode: i64,
+) -> bool {
+    !histories.is_empty()
+        && histories.iter().all(|history| {
+            history
+                .tracks(show_id)
+                .filter(|record| mode.is_none_or(|mode| mode == "dual" || record.mode == mode))
+                .any(|record| record.is_watched(episode))
+        })
+}
+
+/// Downloaded episodes below `dir` already watched in all of `histories`,
+/// oldest first.
+///
+/// Files are matched against `template` and the built-in layouts for every
+/// show in the histories, so episodes downloaded before the queue existed or
+/// cleared from it are found too. Finished queue items are included in case
+/// the template changed since they were downloaded.
+fn watched_downloads(
+    dir: &Path,
+    template: &str,
+    queue: &DownloadQueue,
+    histories: &[&WatchHistory],
+) -> Vec<Deletable> {
+    let mut templates = vec![template, DEFAULT_TEMPLATE, MEDIA_SERVER_TEMPLATE];
+    templates.dedup();
+    let files: Vec<(PathBuf, String)> = files_below(dir)
+        .into_iter()
+        .filter_map(|path| {
+            let relative = path.strip_prefix(dir).ok()?;
+            let relative = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            Some((path, relative))
+        })
+        .collect();
+    let shows: BTreeSet<(&str, &str)> = histories
+        .iter()
+        .flat_map(|history| history.records.values())
+        .map(|record| (record.show_id.as_str(), record.show_name.as_str()))
+        .collect();
+
+    let mut paths = BTreeSet::new();
+    for (show_id, show_name) in shows {
+        let patterns: Vec<_> = templates
+            .iter()
+            .filter_map(|template| template_regex(template, show_name, KNOWN_EXTENSIONS))
+            .collect();
+        for (path, relative) in &files {
+            let watched = patterns
+                .iter()
+                .filter_map(|pattern| pattern.captures(relative))
+                .any(|caps| {
+                    caps["episode"].parse().is_ok_and(|episode| {
+                        let mode = caps.name("mode").map(|m| m.as_str());
+                        is_watched(histories, show_id, mode, episode)
+                    })
+                });
+            if watched {
+                paths.insert(path.clone());
+            }
+        }
+    }
+    paths.extend(
+        queue
+            .items
+            .iter()
+            .filter(|item| item.status == QueueStatus::Completed)
+            .filter(|item| is_watched(histories, &item.show_id, Some(&item.mode), item.episode))
+            .filter_map(|item| item.output_path.clone()),
+    );
+
+    let mut found: Vec<Deletable> = paths
+        .into_iter()
+        .filter_map(|path| {
+            let metadata = fs::metadata(&path).ok()?;
+            Some(Deletable {
+                path,
+                len: metadata.len(),
+                modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            })
+        })
+        .collect();
+    found.sort_by_key(|d| d.modified);
+    found
+}
+
+/// Files saved next to a video that belong to it, such as its `.nfo` and
+/// `.en.vtt` subtitles: everything named `<stem>.*` except other videos.
+fn sidecars(video: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem)) = (video.parent(), video.file_stem()) else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.", stem.to_string_lossy());
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+                && !path
+                    .extension()
+                    .is_some_and(|ext| KNOWN_EXTENSIONS.contains(&ext.to_string_lossy().as_ref()))
+                && path.is_file()
+        })
+        .collect()
+}
+
+/// Episodes deleted to stay within the quota.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CleanupReport {
+    /// Deleted video files.
+    pub deleted: Vec<PathBuf>,
+    /// Bytes freed.
+    pub freed: u64,
+}
+
+/// Make sure a batch of about `needed` bytes fits in `dir`.
+///
+/// Fails if the download quota or the free disk space would be exceeded.
+/// With [`CleanupPolicy::Watched`], watched episodes are deleted first to
+/// make room — but only when that is enough for the batch to fit. Episodes
+/// are recognized by the filename `template` in use, and only deleted once
+/// watched in each of `histories`: every profile's, since downloads are
+/// shared. Deleted episodes are removed from `queue`; the caller saves it.
+pub fn check_space(
+    dir: &Path,
+    template: &str,
+    needed: u64,
+    quota: Option<Quota>,
+    queue: &mut DownloadQueue,
+    histories: &[&WatchHistory],
+) -> Result<CleanupReport, String> {
+    let mut to_delete = Vec::new();
+    let mut freeing = 0;
+
+    if let Some(quota) = quota {
+        let usage = dir_usage(dir);
+        let over = (usage + needed).saturating_sub(quota.limit);
+        if over > 0 && quota.cleanup == CleanupPolicy::Watched {
+            for candidate in watched_downloads(dir, template, queue, histories) {
+                if freeing >= over {
+                    break;
+                }
+                freeing += candidate.len;
+                to_delete.push(candidate);
+            }
+        }
+        if over > freeing {
+            return Err(format!(
+                "download quota of {} would be exceeded: {} used, batch needs about {}",
+                format_size(quota.limit),
+                format_size(usage),
+                format_size(needed)
+            ));
+        }
+    }
+
+    if needed > 0 {
+        if let Ok(free) = available_space(dir) {
+            if needed > free + freeing {
+                return Err(format!(
+                    "not enough disk space: batch needs about {}, {} free",
+                    format_size(needed),
+                    format_size(free)
+                ));
+            }
+        }
+    }
+
+    let mut report = CleanupReport::default();
+    for candidate in to_delete {
+        if let Err(e) = fs::remove_file(&candidate.path) {
+            debug!("Couldn't delete {}: {}", candidate.path.display(), e);
+            continue;
+        }
+        for sidecar in sidecars(&candidate.path) {
+            let _ = fs::remove_file(sidecar);
+        }
+        let items: Vec<u64> = queue
+            .items
+            .iter()
+            .filter(|item| item.output_path.as_ref() == Some(&candidate.path))
+            .map(|item| item.id)
+            .collect();
+        for id in items {
+            queue.remove(id);
+        }
+        report.freed += candidate.len;
+        report.deleted.push(candidate.path);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download::DownloadOutcome;
+    use crate::types::Show;
+
+    #[test]
+    fn test_parse_size_and_format() {
+        assert_eq!(parse_size("1T"), Ok(1_099_511_627_776));
+        assert!(parse_size("0").is_err());
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536 * 1024 * 1024), "1.5 GiB");
+    }
+
+    #[test]
+    fn test_hls_estimate() {
+        let master = "#EXTM3U\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360\n\
+            360/index.m3u8\n\
+            #EXT-X-STREAM-INF:BANDWIDTH=5000000,AVERAGE-BANDWIDTH=4000000,RESOLUTION=1920x1080\n\
+            1080/index.m3u8\n";
+        assert_eq!(
+            hls_variant(master),
+            Some((4_000_000, "1080/index.m3u8".to_string()))
+        );
+        assert_eq!(hls_variant("#EXTM3U\n#EXTINF:10.0,\nseg1.ts\n"), None);
+
+        let media = "#EXTM3U\n#EXTINF:10.0,\nseg1.ts\n#EXTINF:4.5,\nseg2.ts\n#EXT-X-ENDLIST\n";
+        assert_eq!(hls_duration(media), 14.5);
+
+        assert_eq!(
+            resolve_url(
+                "https://cdn.example.com/show/master.m3u8?t=1",
+                "1080/index.m3u8"
+            ),
+            Some("https://cdn.example.com/show/1080/index.m3u8".to_string())
+        );
+    }
+
+    #[test]
+    fn test_check_space_deletes_oldest_watched_first() {
+        let dir = std::env::temp_dir().join(format!("aw-storage-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let show = Show {
+            id: "show1".to_string(),
+            name: "Show".to_string(),
+            english_name: None,
+            native_name: None,
+            available_episodes: 3,
+        };
+
+        let mut queue = DownloadQueue::new();
+        for episode in 1..=3 {
+            let path = dir.join(format!("Ep {}.mp4", episode));
+            fs::write(&path, vec![0u8; 100]).unwrap();
+            let id = queue.enqueue(&show, episode, "sub", "best", &dir);
+            queue.start_next().unwrap();
+            queue.finish(id, &DownloadOutcome::Completed(path));
+            // Keep modification times ordered by episode.
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let mut history = WatchHistory::new();
+        history.update("show1", "Show", 2, "sub");
+        history.update("show1", "Show", 1, "sub");
+        let quota = Some(Quota {
+            limit: 300,
+            cleanup: CleanupPolicy::Watched,
+        });
+
+        // Needing more than the watched episodes can free fails untouched.
+        assert!(check_space(&dir, DEFAULT_TEMPLATE, 250, quota, &mut queue, &[&history]).is_err());
+        assert_eq!(queue.items.len(), 3);
+
+        let report =
+            check_space(&dir, DEFAULT_TEMPLATE, 50, quota, &mut queue, &[&history]).unwrap();
+        assert_eq!(report.deleted, vec![dir.join("Ep 1.mp4")]);
+        assert_eq!(report.freed, 100);
+        assert_eq!(queue.items.len(), 2);
+        assert!(dir.join("Ep 2.mp4").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cleanup_finds_files_outside_the_queue() {
+        let dir = std::env::temp_dir().join(format!("aw-storage-scan-{}", std::process::id()));
+        let season = dir.join("Frieren").join("Season 01");
+        fs::create_dir_all(&season).unwrap();
+        let files = [
+            dir.join("Frieren - Episode 1 [sub].mp4"),
+            season.join("Frieren - S01E02.mkv"),
+            dir.join("Frieren - Episode 1 [dub].mp4"),
+            dir.join("Frieren - Episode 3 [sub].mp4"),
+            dir.join("Bocchi - Episode 1 [sub].mp4"),
+        ];
+        for path in &files {
+            fs::write(path, vec![0u8; 100]).unwrap();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let mut history = WatchHistory::new();
+        history.update("show1", "Frieren", 1, "sub");
+        history.update("show1", "Frieren", 2, "sub");
+        let mut queue = DownloadQueue::new();
+
+        let found: Vec<PathBuf> =
+            watched_downloads(&dir, "{show}/{episode}.{ext}", &queue, &[&history])
+                .into_iter()
+                .map(|d| d.path)
+                .collect();
+        assert_eq!(found, files[..2]);
+
+        // Another profile that has only watched episode 1 keeps episode 2
+        let mut other = WatchHistory::new();
+        other.update("show1", "Frieren", 1, "sub");
+        let found: Vec<PathBuf> =
+            watched_downloads(&dir, "{show}/{episode}.{ext}", &queue, &[&history, &other])
+                .into_iter()
+                .map(|d| d.path)
+                .collect();
+        assert_eq!(found, files[..1]);
+        assert!(watched_downloads(
+            &dir,
+            DEFAULT_TEMPLATE,
+            &queue,
+            &[&history, &WatchHistory::new()]
+        )
+        .is_empty());
+
+        let quota = Some(Quota {
+            limit: 500,
+            cleanup: CleanupPolicy::Watched,
+        });
+        let subtitles = dir.join("Frieren - Episode 1 [sub].en.vtt");
+        let nfo = season.join("Frieren - S01E02.nfo");
+        fs::write(&subtitles, "WEBVTT").unwrap();
+        fs::write(&nfo, "<episodedetails/>").unwrap();
+
+        let report =
+            check_space(&dir, DEFAULT_TEMPLATE, 150, quota, &mut queue, &[&history]).unwrap();
+        assert_eq!(report.deleted, files[..2]);
+        assert!(!subtitles.exists());
+        assert!(!nfo.exists());
+        assert!(files[2].exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cleanup_ignores_templates_without_a_title() {
+        let dir = std::env::temp_dir().join(format!("aw-storage-untitled-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // Could be any show's episode 5
+        fs::write(dir.join("05.mp4"), vec![0u8; 100]).unwrap();
+
+        let mut history = WatchHistory::new();
+        history.update("show1", "Frieren", 5, "sub");
+        let queue = DownloadQueue::new();
+
+        assert!(watched_downloads(&dir, "{episode:02}.{ext}", &queue, &[&history]).is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
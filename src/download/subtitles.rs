@@ -0,0 +1,364 @@
+//! Soft subtitles for downloaded episodes.
+//!
+//! Some providers serve subtitle tracks next to the video instead of burning
+//! them in. When ffmpeg is installed, the video, its subtitles and chapter
+//! markers are remuxed into a single `.mkv` with language tags set.
+//! Otherwise the subtitles are kept as sidecar files (`Ep 1.en.vtt`) that
+//! players and media servers pick up automatically.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use log::warn;
+use tokio::process::Command;
+
+use super::{download_file, media_extension, part_path, verify_file, RateLimit};
+use crate::api::USER_AGENT;
+use crate::types::{StreamSource, SubtitleTrack};
+
+/// Whether ffmpeg can be run. Checked once per process.
+pub(super) fn has_ffmpeg() -> bool {
+    static AVAILABLE: OnceLock<bool> = OnceLock::new();
+    *AVAILABLE.get_or_init(|| {
+        std::process::Command::new("ffmpeg")
+            .arg("-version")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(false)
+    })
+}
+
+/// Extension a source is saved with: `mkv` when its subtitles will be muxed
+/// in, otherwise the extension of the video itself.
+pub fn output_extension(source: &StreamSource) -> &'static str {
+    if !source.subtitles.is_empty() && has_ffmpeg() {
+        "mkv"
+    } else {
+        media_extension(&source.url)
+    }
+}
+
+/// Convert a provider language code to the ISO 639-2 code Matroska expects.
+///
+/// Unknown codes are passed through unchanged.
+fn iso639_2(lang: &str) -> String {
+    let lang = lang.trim().to_lowercase();
+    let primary = lang.split(['-', '_']).next().unwrap_or("");
+    let code = match primary {
+        "ar" => "ara",
+        "de" => "ger",
+        "en" => "eng",
+        "es" => "spa",
+        "fr" => "fre",
+        "id" => "ind",
+        "it" => "ita",
+        "ja" => "jpn",
+        "ko" => "kor",
+        "ms" => "may",
+        "pl" => "pol",
+        "pt" => "por",
+        "ru" => "rus",
+        "th" => "tha",
+        "tr" => "tur",
+        "vi" => "vie",
+        "zh" => "chi",
+        _ => return lang,
+    };
+    code.to_string()
+}
+
+/// File extension of a subtitle URL, defaulting to WebVTT.
+fn subtitle_extension(url: &str) -> &'static str {
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+    match Path::new(&path).extension().and_then(|e| e.to_str()) {
+        Some("srt") => "srt",
+        Some("ass") => "ass",
+        Some("ssa") => "ssa",
+        _ => "vtt",
+    }
+}
+
+/// Sidecar path for the `index`th subtitle track of a video.
+///
+/// Tracks are named by language (`Ep 1.en.vtt`); later tracks with the same
+/// language get their track number appended (`Ep 1.en.3.vtt`).
+fn subtitle_path(video: &Path, tracks: &[SubtitleTrack], index: usize) -> PathBuf {
+    let track = &tracks[index];
+    let lang = match track.lang.trim() {
+        "" => "und",
+        lang => lang,
+    };
+    let duplicate = tracks[..index]
+        .iter()
+        .any(|t| t.lang.trim() == track.lang.trim());
+
+    let stem = video
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = if duplicate {
+        format!(
+            "{}.{}.{}.{}",
+            stem,
+            lang,
+            index + 1,
+            subtitle_extension(&track.url)
+        )
+    } else {
+        format!("{}.{}.{}", stem, lang, subtitle_extension(&track.url))
+    };
+    video.with_file_name(name)
+}
+
+/// Download every subtitle track next to `video`.
+///
+/// Tracks that fail to download are skipped with a warning. Returns the
+/// tracks that were saved, with their paths.
+pub(super) async fn fetch_subtitles(
+    tracks: &[SubtitleTrack],
+    video: &Path,
+) -> Vec<(SubtitleTrack, PathBuf)> {
+    let client = match reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(Duration::from_secs(30))
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Failed to create HTTP client for subtitles: {}", e);
+            return Vec::new();
+        }
+    };
+
+    let mut saved = Vec::new();
+    for (index, track) in tracks.iter().enumerate() {
+        let url = match track.url.strip_prefix("//") {
+            Some(rest) => format!("https://{}", rest),
+            None => track.url.clone(),
+        };
+        let path = subtitle_path(video, tracks, index);
+
+        let result = async {
+            let bytes = client
+                .get(&url)
+                .header("Referer", "https://allmanga.to")
+                .send()
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?;
+            tokio::fs::write(&path, &bytes).await?;
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(())
+        }
+        .await;
+
+        match result {
+            Ok(()) => saved.push((track.clone(), path)),
+            Err(e) => warn!("Failed to download {} subtitles: {}", track.label, e),
+        }
+    }
+    saved
+}
+
+/// Remux a video and its subtitle files into a Matroska file with ffmpeg.
+///
+/// Video and audio are copied as-is. ASS/SSA subtitles keep their styling;
+/// other formats are converted to SRT, which every Matroska player reads.
+/// Chapter markers of the video are kept. With `dub_audio`, the first audio
+/// track of each file is kept: the video's as Japanese, then the dub's as
+/// English. Muxing fails if either has no audio.
+pub(super) async fn mux_mkv(
+    video: &Path,
+    dub_audio: Option<&Path>,
+    subtitles: &[(SubtitleTrack, PathBuf)],
+    output_path: &Path,
+) -> Result<(), String> {
+    let mut cmd = Command::new("ffmpeg");
+    cmd.args(["-y", "-loglevel", "error", "-i"]).arg(video);
+    if let Some(dub) = dub_audio {
+        cmd.arg("-i").arg(dub);
+    }
+    for (_, path) in subtitles {
+        cmd.arg("-i").arg(path);
+    }
+
+    cmd.args(["-map", "0:v"]);
+    let first_subtitle = if dub_audio.is_some() {
+        cmd.args(["-map", "0:a:0", "-map", "1:a:0"]);
+        2
+    } else {
+        cmd.args(["-map", "0:a?"]);
+        1
+    };
+    for i in 0..subtitles.len() {
+        cmd.args(["-map", &(first_subtitle + i).to_string()]);
+    }
+    cmd.args(["-map_chapters", "0", "-c", "copy"]);
+
+    if dub_audio.is_some() {
+        cmd.args([
+            "-metadata:s:a:0",
+            "language=jpn",
+            "-metadata:s:a:0",
+            "title=Japanese",
+        ]);
+        cmd.args([
+            "-metadata:s:a:1",
+            "language=eng",
+            "-metadata:s:a:1",
+            "title=English",
+        ]);
+        cmd.args(["-disposition:a:0", "default", "-disposition:a:1", "0"]);
+    }
+
+    for (i, (track, path)) in subtitles.iter().enumerate() {
+        let codec = match path.extension().and_then(|e| e.to_str()) {
+            Some("ass") | Some("ssa") => "copy",
+            _ => "srt",
+        };
+        cmd.arg(format!("-c:s:{}", i)).arg(codec);
+        if !track.lang.trim().is_empty() {
+            cmd.arg(format!("-metadata:s:s:{}", i))
+                .arg(format!("language={}", iso639_2(&track.lang)));
+        }
+        if !track.label.is_empty() {
+            cmd.arg(format!("-metadata:s:s:{}", i))
+                .arg(format!("title={}", track.label));
+        }
+    }
+    if !subtitles.is_empty() {
+        cmd.args(["-disposition:s:0", "default"]);
+    }
+
+    let status = cmd
+        .arg(output_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .map_err(|e| format!("failed to run ffmpeg: {}", e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "ffmpeg exited with status: {}",
+            status.code().unwrap_or(-1)
+        ))
+    }
+}
+
+/// Remove files, ignoring ones that are already gone.
+pub(super) fn remove_all<'a>(paths: impl IntoIterator<Item = &'a Path>) {
+    for path in paths {
+        if let Err(e) = fs::remove_file(path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Download a source together with its subtitle tracks.
+///
+/// `output_path` must use the extension from [`output_extension`]. Returns
+/// the path the episode was saved to: `output_path`, or the plain video if
+/// muxing failed, in which case the subtitles stay as sidecar files.
+pub async fn download_with_subtitles(
+    source: &StreamSource,
+    output_path: &Path,
+    rate_limit: &RateLimit,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let video_ext = media_extension(&source.url);
+    let mux = output_extension(source) == "mkv" && !source.subtitles.is_empty();
+    if !mux {
+        download_file(&source.url, output_path, rate_limit).await?;
+        fetch_subtitles(&source.subtitles, output_path).await;
+        return Ok(output_path.to_path_buf());
+    }
+
+    // The plain video is downloaded first, under a name of its own.
+    let video = output_path.with_extension(format!("video.{}", video_ext));
+    download_file(&source.url, &video, rate_limit).await?;
+    let subtitles = fetch_subtitles(&source.subtitles, output_path).await;
+
+    let part = part_path(output_path);
+    let muxed = match mux_mkv(&video, None, &subtitles, &part).await {
+        Ok(()) => verify_file(&part, None).await,
+        Err(e) => Err(e),
+    };
+
+    match muxed {
+        Ok(()) => {
+            fs::rename(&part, output_path)?;
+            remove_all(std::iter::once(video.as_path()));
+            remove_all(subtitles.iter().map(|(_, path)| path.as_path()));
+            Ok(output_path.to_path_buf())
+        }
+        Err(e) => {
+            warn!(
+                "Failed to mux subtitles into {}: {}",
+                output_path.display(),
+                e
+            );
+            remove_all(std::iter::once(part.as_path()));
+            let fallback = output_path.with_extension(video_ext);
+            fs::rename(&video, &fallback)?;
+            Ok(fallback)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(lang: &str, url: &str) -> SubtitleTrack {
+        SubtitleTrack {
+            lang: lang.to_string(),
+            label: String::new(),
+            url: url.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_subtitle_paths() {
+        let video = Path::new("/anime/Show - Episode 1.mkv");
+        let tracks = [
+            track("en", "https://cdn.example.com/en.vtt?t=1"),
+            track("es", "https://cdn.example.com/es.ass"),
+            track("en", "https://cdn.example.com/en-signs.srt"),
+            track("", "https://cdn.example.com/sub"),
+        ];
+
+        let names: Vec<PathBuf> = (0..tracks.len())
+            .map(|i| subtitle_path(video, &tracks, i))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "/anime/Show - Episode 1.en.vtt",
+                "/anime/Show - Episode 1.es.ass",
+                "/anime/Show - Episode 1.en.3.srt",
+                "/anime/Show - Episode 1.und.vtt",
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn test_language_tags() {
+        assert_eq!(iso639_2("en"), "eng");
+        assert_eq!(iso639_2("pt-BR"), "por");
+        assert_eq!(iso639_2("FIL"), "fil");
+    }
+}
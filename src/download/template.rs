@@ -0,0 +1,353 @@
+//! Filename and folder templates for downloaded episodes.
+//!
+//! A template is a path relative to the download directory, with `/`
+//! separating folders. Placeholders are written in braces:
+//!
+//! | Placeholder | Value |
+//! |-------------|-------|
+//! | `{show}` | Show name |
+//! | `{english}` | English title (falls back to the show name) |
+//! | `{native}` | Original-language title (falls back to the show name) |
+//! | `{episode}` | Episode number |
+//! | `{season}` | Season number, guessed from the show name (default 1) |
+//! | `{mode}` | Translation mode (sub/dub) |
+//! | `{quality}` | Resolution of the chosen source (e.g., "1080p") |
+//! | `{provider}` | Provider of the chosen source |
+//! | `{ext}` | File extension without the dot |
+//!
+//! Numbers can be zero-padded with a width, e.g. `{episode:02}` or `{season:02}`.
+
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+/// Template matching the historical flat naming scheme.
+pub const DEFAULT_TEMPLATE: &str = "{show} - Episode {episode} [{mode}].{ext}";
+
+/// Values substituted into a filename template.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TemplateVars {
+    /// Show name.
+    pub show: String,
+    /// English title, if known.
+    pub english: Option<String>,
+    /// Original-language title, if known.
+    pub native: Option<String>,
+    /// Episode number.
+    pub episode: i64,
+    /// Season number.
+    pub season: i64,
+    /// Translation mode (sub/dub).
+    pub mode: String,
+    /// Source resolution in pixels; 0 when unknown.
+    pub quality: i32,
+    /// Source provider name.
+    pub provider: String,
+    /// File extension without the dot.
+    pub ext: String,
+}
+
+/// Placeholder names accepted in templates.
+const PLACEHOLDERS: &[&str] = &[
+    "show", "english", "native", "episode", "season", "mode", "quality", "provider", "ext",
+];
+
+/// Regex matching `{name}` or `{name:width}` placeholders.
+fn placeholder_regex() -> Regex {
+    Regex::new(r"\{([a-z]+)(?::(0?\d+))?\}").unwrap()
+}
+
+/// Check that a template only uses known placeholders and is not empty.
+///
+/// # Examples
+///
+/// ```
+/// use anime_watcher::download::validate_template;
+///
+/// assert!(validate_template("{show}/{show} - {episode:02}.{ext}").is_ok());
+/// assert!(validate_template("{title}.{ext}").is_err());
+/// ```
+pub fn validate_template(template: &str) -> Result<(), String> {
+    if template.trim().is_empty() {
+        return Err("filename template is empty".to_string());
+    }
+    if template.starts_with('/') {
+        return Err("filename template must be relative to the download directory".to_string());
+    }
+
+    for caps in placeholder_regex().captures_iter(template) {
+        let name = &caps[1];
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(format!(
+                "unknown placeholder '{{{}}}' in filename template",
+                name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Replace a filesystem-unsafe character.
+fn safe_char(c: char) -> char {
+    match c {
+        '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+        _ => c,
+    }
+}
+
+/// Replace filesystem-unsafe characters in a single path component.
+///
+/// Also guards against components that would escape the download directory.
+fn sanitize_component(component: &str) -> String {
+    let safe: String = component.chars().map(safe_char).collect();
+
+    let trimmed = safe.trim();
+    if trimmed.is_empty() || trimmed == "." || trimmed == ".." {
+        "_".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+/// Render a template into a relative path.
+///
+/// The template is split into components first, so slashes inside values
+/// (e.g., "Fate/Zero") are sanitized rather than creating folders.
+pub fn render_template(template: &str, vars: &TemplateVars) -> PathBuf {
+    let re = placeholder_regex();
+
+    template
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .map(|component| {
+            let rendered = re.replace_all(component, |caps: &regex::Captures| {
+                let width: usize = caps
+                    .get(2)
+                    .and_then(|w| w.as_str().parse().ok())
+                    .unwrap_or(0);
+                match &caps[1] {
+                    "show" => vars.show.clone(),
+                    "english" => vars.english.clone().unwrap_or_else(|| vars.show.clone()),
+                    "native" => vars.native.clone().unwrap_or_else(|| vars.show.clone()),
+                    "episode" => format!("{:0width$}", vars.episode, width = width),
+                    "season" => format!("{:0width$}", vars.season, width = width),
+                    "mode" => vars.mode.clone(),
+                    "quality" if vars.quality > 0 => format!("{}p", vars.quality),
+                    "quality" => "unknown".to_string(),
+                    "provider" => vars.provider.clone(),
+                    "ext" => vars.ext.clone(),
+                    _ => caps[0].to_string(),
+                }
+            });
+            sanitize_component(&rendered)
+        })
+        .collect()
+}
+
+/// Regex matching the paths `template` renders for episodes of `show`,
+/// relative to the download directory with `/` between folders.
+///
+/// The episode number is captured as `episode`, and the translation mode
+/// as `mode` if the template has one. English and native titles match the
+/// show name, as they do when rendered without them; the season, quality
+/// and provider match any value, and the extension any of `extensions`.
+/// Returns `None` for templates without an episode number or a show
+/// title, whose files can't be told apart from other shows'.
+pub(super) fn template_regex(template: &str, show: &str, extensions: &[&str]) -> Option<Regex> {
+    let re = placeholder_regex();
+    let show = regex::escape(show.chars().map(safe_char).collect::<String>().trim());
+    let (mut named, mut episode, mut mode) = (false, false, false);
+
+    let components: Vec<String> = template
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .map(|component| {
+            let mut pattern = String::new();
+            let mut literal_start = 0;
+            for caps in re.captures_iter(component) {
+                let placeholder = caps.get(0).unwrap();
+                pattern.push_str(&regex::escape(
+                    &component[literal_start..placeholder.start()],
+                ));
+                literal_start = placeholder.end();
+                // Only the first episode and mode are captured
+                let part = match &caps[1] {
+                    "show" | "english" | "native" => {
+                        named = true;
+                        show.clone()
+                    }
+                    "episode" if !episode => {
+                        episode = true;
+                        r"(?P<episode>\d+)".to_string()
+                    }
+                    "mode" if !mode => {
+                        mode = true;
+                        "(?P<mode>sub|dub|dual)".to_string()
+                    }
+                    "episode" | "season" => r"\d+".to_string(),
+                    "mode" => "(?:sub|dub|dual)".to_string(),
+                    "ext" => format!("(?:{})", extensions.join("|")),
+                    _ => "[^/]*".to_string(),
+                };
+                pattern.push_str(&part);
+            }
+            pattern.push_str(&regex::escape(&component[literal_start..]));
+            pattern
+        })
+        .collect();
+
+    if !named || !episode {
+        return None;
+    }
+    Regex::new(&format!("^{}$", components.join("/"))).ok()
+}
+
+/// Guess the season number from a show name.
+///
+/// Recognizes "Season 2", "2nd Season" and a trailing "S2"; defaults to 1.
+///
+/// # Examples
+///
+/// ```
+/// use anime_watcher::download::guess_season;
+///
+/// assert_eq!(guess_season("Spy x Family Season 2"), 2);
+/// assert_eq!(guess_season("Mushoku Tensei 2nd Season"), 2);
+/// assert_eq!(guess_season("Frieren"), 1);
+/// ```
+pub fn guess_season(show_name: &str) -> i64 {
+    let patterns = [
+        r"(?i)\bseason\s*(\d+)\b",
+        r"(?i)\b(\d+)(?:st|nd|rd|th)\s+season\b",
+        r"(?i)\bs(\d+)$",
+    ];
+
+    patterns
+        .iter()
+        .filter_map(|p| Regex::new(p).unwrap().captures(show_name))
+        .find_map(|caps| caps[1].parse().ok())
+        .unwrap_or(1)
+}
+
+/// Guess the file extension a source will be saved with.
+///
+/// HLS playlists are saved as MPEG-TS; other URLs keep their video extension,
+/// defaulting to mp4.
+pub fn media_extension(url: &str) -> &'static str {
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+    let ext = Path::new(&path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("");
+
+    match ext {
+        "m3u8" => "ts",
+        "mkv" => "mkv",
+        "webm" => "webm",
+        "ts" => "ts",
+        _ => "mp4",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> TemplateVars {
+        TemplateVars {
+            show: "My Anime".to_string(),
+            english: None,
+            native: Some("Watashi no Anime".to_string()),
+            episode: 5,
+            season: 1,
+            mode: "sub".to_string(),
+            quality: 1080,
+            provider: "S-mp4".to_string(),
+            ext: "mp4".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_default_template_matches_legacy_names() {
+        let path = render_template(DEFAULT_TEMPLATE, &vars());
+        assert_eq!(path, PathBuf::from("My Anime - Episode 5 [sub].mp4"));
+    }
+
+    #[test]
+    fn test_render_all_placeholders() {
+        let template =
+            "{english}/{native}/S{season:02}E{episode:03} {quality} {provider} {mode}.{ext}";
+        let path = render_template(template, &vars());
+        assert_eq!(
+            path,
+            PathBuf::from("My Anime/Watashi no Anime/S01E005 1080p S-mp4 sub.mp4")
+        );
+    }
+
+    #[test]
+    fn test_sanitization_is_per_component() {
+        let mut v = vars();
+        v.show = "Fate/Zero: Part?".to_string();
+        let path = render_template("{show}/{show} - {episode}.{ext}", &v);
+        assert_eq!(
+            path,
+            PathBuf::from("Fate_Zero_ Part_/Fate_Zero_ Part_ - 5.mp4")
+        );
+    }
+
+    #[test]
+    fn test_sanitize_component_rejects_parent_dirs() {
+        assert_eq!(sanitize_component(".."), "_");
+        assert_eq!(sanitize_component("  "), "_");
+        assert_eq!(
+            sanitize_component("A/B\\C:D*E?F\"G<H>I|J"),
+            "A_B_C_D_E_F_G_H_I_J"
+        );
+    }
+
+    #[test]
+    fn test_template_regex() {
+        let re = template_regex(
+            "{show}/S{season:02}/{show} - {episode:02} {quality} [{mode}].{ext}",
+            "Fate/Zero",
+            &["mp4", "mkv"],
+        )
+        .unwrap();
+        let caps = re
+            .captures("Fate_Zero/S01/Fate_Zero - 07 1080p [dual].mkv")
+            .unwrap();
+        assert_eq!(&caps["episode"], "07");
+        assert_eq!(&caps["mode"], "dual");
+        assert!(!re.is_match("Fate_Zero/S01/Fate_Zero - 07 1080p [sub].en.vtt"));
+        assert!(!re.is_match("Other/S01/Other - 07 1080p [sub].mp4"));
+
+        assert!(template_regex("{show}.{ext}", "Show", &["mp4"]).is_none());
+        assert!(template_regex("{episode:02}.{ext}", "Show", &["mp4"]).is_none());
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert!(validate_template(DEFAULT_TEMPLATE).is_ok());
+        assert!(validate_template("").is_err());
+        assert!(validate_template("/abs/{show}.{ext}").is_err());
+        assert!(validate_template("{show} {bogus}.{ext}").is_err());
+    }
+
+    #[test]
+    fn test_unknown_quality() {
+        let mut v = vars();
+        v.quality = 0;
+        assert_eq!(
+            render_template("{quality}.{ext}", &v),
+            PathBuf::from("unknown.mp4")
+        );
+    }
+
+    #[test]
+    fn test_media_extension() {
+        assert_eq!(media_extension("https://cdn/x/master.m3u8?token=1"), "ts");
+        assert_eq!(media_extension("https://cdn/video.MP4"), "mp4");
+        assert_eq!(media_extension("https://cdn/video.mkv#t=1"), "mkv");
+        assert_eq!(media_extension("https://embed.example.com/e/abc"), "mp4");
+    }
+}
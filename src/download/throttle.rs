@@ -0,0 +1,225 @@
+//! Global download bandwidth limit.
+//!
+//! Native downloads share a token bucket, so the cap holds no matter how many
+//! workers are running. yt-dlp downloads can't share the bucket; each process
+//! reserves a slice of the cap for `--limit-rate` when it starts, and the
+//! bucket fills at what is left.
+
+use std::sync::Mutex as SyncMutex;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+
+use super::storage::parse_bytes;
+
+/// Parse a rate such as `"500K"`, `"2M"` or `"1.5M"` into bytes per second.
+///
+/// Uses the same binary suffixes as yt-dlp's `--limit-rate`.
+///
+/// # Examples
+///
+/// ```
+/// use anime_watcher::download::parse_rate;
+///
+/// assert_eq!(parse_rate("500K"), Ok(512_000));
+/// assert_eq!(parse_rate("2M"), Ok(2_097_152));
+/// assert!(parse_rate("fast").is_err());
+/// ```
+pub fn parse_rate(spec: &str) -> Result<u64, String> {
+    match parse_bytes(spec) {
+        Some(0) => Err(format!("rate limit '{}' must be greater than zero", spec)),
+        Some(bytes) => Ok(bytes),
+        None => Err(format!(
+            "invalid rate limit '{}': use e.g. 500K or 2M",
+            spec
+        )),
+    }
+}
+
+/// Token bucket state.
+#[derive(Debug)]
+struct Bucket {
+    /// Bytes that may be sent right now; negative when in debt.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Downloads running under the cap.
+#[derive(Debug, Default)]
+struct Shares {
+    /// Running downloads, native or yt-dlp.
+    active: usize,
+    /// Running downloads that reserved a slice for yt-dlp.
+    processes: usize,
+    /// Bytes per second reserved by yt-dlp processes.
+    reserved: u64,
+}
+
+/// A bandwidth cap shared by all download workers.
+#[derive(Debug)]
+pub struct RateLimit {
+    bytes_per_sec: Option<u64>,
+    shares: SyncMutex<Shares>,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimit {
+    /// Create a limit of `bytes_per_sec`. `None` means unlimited.
+    pub fn new(bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            bytes_per_sec,
+            shares: SyncMutex::new(Shares::default()),
+            bucket: Mutex::new(Bucket {
+                tokens: bytes_per_sec.unwrap_or(0) as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Register a download running under the cap until the share is dropped.
+    pub fn begin(&self) -> Share<'_> {
+        self.shares.lock().unwrap().active += 1;
+        Share {
+            limit: self,
+            reserved: None,
+        }
+    }
+
+    /// Account for `bytes` received, sleeping if the cap is exceeded.
+    ///
+    /// Native downloads share whatever yt-dlp processes haven't reserved,
+    /// and wait while they hold all of it.
+    pub async fn consume(&self, bytes: usize) {
+        let Some(cap) = self.bytes_per_sec else {
+            return;
+        };
+        let rate = loop {
+            let free = cap.saturating_sub(self.shares.lock().unwrap().reserved);
+            if free > 0 {
+                break free as f64;
+            }
+            sleep(Duration::from_millis(100)).await;
+        };
+
+        let deficit = {
+            let mut bucket = self.bucket.lock().await;
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            // Allow at most one second of burst.
+            bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+            bucket.last_refill = now;
+            bucket.tokens -= bytes as f64;
+            -bucket.tokens
+        };
+
+        if deficit > 0.0 {
+            sleep(Duration::from_secs_f64(deficit / rate)).await;
+        }
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+/// A download running under a [`RateLimit`].
+#[derive(Debug)]
+pub struct Share<'a> {
+    limit: &'a RateLimit,
+    reserved: Option<u64>,
+}
+
+impl Share<'_> {
+    /// Reserve a slice of the cap for a yt-dlp process, in bytes per second.
+    ///
+    /// The unreserved part of the cap is split evenly between the running
+    /// downloads that hold no slice yet, so a lone download gets all of it.
+    /// The slice is held until the share is dropped.
+    pub fn reserve(&mut self) -> Option<u64> {
+        let cap = self.limit.bytes_per_sec?;
+        if let Some(slice) = self.reserved {
+            return Some(slice);
+        }
+        let mut shares = self.limit.shares.lock().unwrap();
+        let free = cap.saturating_sub(shares.reserved);
+        let sharing = (shares.active - shares.processes).max(1) as u64;
+        let slice = (free / sharing).max(1);
+        shares.reserved += slice;
+        shares.processes += 1;
+        self.reserved = Some(slice);
+        Some(slice)
+    }
+}
+
+impl Drop for Share<'_> {
+    fn drop(&mut self) {
+        let mut shares = self.limit.shares.lock().unwrap();
+        shares.active -= 1;
+        if let Some(slice) = self.reserved {
+            shares.reserved -= slice;
+            shares.processes -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("1024"), Ok(1024));
+        assert_eq!(parse_rate("1.5k"), Ok(1536));
+        assert_eq!(parse_rate("1G"), Ok(1_073_741_824));
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("").is_err());
+    }
+
+    #[test]
+    fn test_reserve_splits_the_free_cap() {
+        let limit = RateLimit::new(Some(3000));
+        let mut lone = limit.begin();
+        assert_eq!(lone.reserve(), Some(3000));
+        drop(lone);
+
+        let (mut a, _native, mut b) = (limit.begin(), limit.begin(), limit.begin());
+        assert_eq!(a.reserve(), Some(1000));
+        assert_eq!(b.reserve(), Some(1000));
+        assert_eq!(limit.shares.lock().unwrap().reserved, 2000);
+        drop(a);
+        assert_eq!(limit.shares.lock().unwrap().reserved, 1000);
+
+        assert_eq!(RateLimit::new(None).begin().reserve(), None);
+    }
+
+    #[tokio::test]
+    async fn test_native_downloads_share_what_yt_dlp_leaves() {
+        let limit = RateLimit::new(Some(20_000));
+        let mut yt_dlp = limit.begin();
+        let _native = limit.begin();
+        assert_eq!(yt_dlp.reserve(), Some(10_000));
+        let start = Instant::now();
+
+        // Only the unreserved half is left for native downloads
+        limit.consume(10_000).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+        limit.consume(1_000).await;
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn test_consume_waits_when_over_the_cap() {
+        let limit = RateLimit::new(Some(10_000));
+        let start = Instant::now();
+
+        // The first second's worth is allowed as a burst.
+        limit.consume(10_000).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+
+        limit.consume(1_000).await;
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+}
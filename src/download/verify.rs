@@ -0,0 +1,264 @@
+//! Integrity checks for downloaded files.
+//!
+//! Downloads are written to a `.part` file and only renamed to their final
+//! name once they pass [`verify_file`], so a file at the final path is known
+//! to be complete. [`verify_queue`] re-checks finished downloads later and
+//! puts broken ones back in the queue.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use tokio::process::Command;
+
+use super::{DownloadQueue, QueueStatus};
+
+/// Marker inserted before the extension of unfinished downloads.
+const PART_MARKER: &str = ".part";
+
+/// Path an unfinished download is written to.
+///
+/// The marker goes before the extension (`Ep 1.part.mp4`) so that yt-dlp
+/// keeps the container it was asked for.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::{Path, PathBuf};
+/// use anime_watcher::download::part_path;
+///
+/// assert_eq!(
+///     part_path(Path::new("/anime/Ep 1.mp4")),
+///     PathBuf::from("/anime/Ep 1.part.mp4")
+/// );
+/// ```
+pub fn part_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}{}.{}", stem, PART_MARKER, ext.to_string_lossy()),
+        None => format!("{}{}", stem, PART_MARKER),
+    };
+    path.with_file_name(name)
+}
+
+/// Whether a path is an unfinished download left behind by [`part_path`].
+pub fn is_part_file(path: &Path) -> bool {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().ends_with(PART_MARKER))
+        .unwrap_or(false)
+}
+
+/// Whether a finished download exists at `path` and is not empty.
+pub fn is_complete(path: &Path) -> bool {
+    fs::metadata(path).map(|m| m.len() > 0).unwrap_or(false)
+}
+
+/// Read the media duration in seconds with ffprobe.
+///
+/// Returns `Ok(None)` when ffprobe is not installed, and an error when it
+/// can't read the file.
+async fn probe_duration(path: &Path) -> Result<Option<f64>, String> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-show_entries", "format=duration"])
+        .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await;
+
+    let output = match output {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("failed to run ffprobe: {}", e)),
+    };
+
+    if !output.status.success() {
+        return Err("ffprobe could not read the file".to_string());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout.trim().parse().ok())
+}
+
+/// Check that a downloaded file looks complete.
+///
+/// The file must be non-empty, match `expected_len` when the server sent a
+/// Content-Length, and have a positive duration if ffprobe is available.
+pub async fn verify_file(path: &Path, expected_len: Option<u64>) -> Result<(), String> {
+    let len = fs::metadata(path)
+        .map_err(|e| format!("cannot read {}: {}", path.display(), e))?
+        .len();
+
+    if len == 0 {
+        return Err("file is empty".to_string());
+    }
+    if let Some(expected) = expected_len {
+        if len != expected {
+            return Err(format!("expected {} bytes, got {}", expected, len));
+        }
+    }
+
+    match probe_duration(path).await? {
+        Some(duration) if duration <= 0.0 => Err("file has no playable duration".to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// A finished download that failed verification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrokenDownload {
+    /// Queue item description (show, episode, mode).
+    pub description: String,
+    /// Why the file was rejected.
+    pub reason: String,
+}
+
+/// Result of [`verify_queue`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VerifyReport {
+    /// Number of finished downloads checked.
+    pub checked: usize,
+    /// Downloads that were broken and have been queued again.
+    pub requeued: Vec<BrokenDownload>,
+    /// Leftover `.part` files that were removed.
+    pub removed_parts: Vec<PathBuf>,
+}
+
+/// Re-check every completed download and queue broken ones again.
+///
+/// Broken files are deleted. Leftover `.part` files under `download_dir` are
+/// removed too, since nothing will resume them.
+pub async fn verify_queue(queue: &mut DownloadQueue, download_dir: &Path) -> VerifyReport {
+    let mut report = VerifyReport::default();
+
+    for item in &mut queue.items {
+        if item.status != QueueStatus::Completed {
+            continue;
+        }
+        let Some(path) = item.output_path.clone() else {
+            continue;
+        };
+        report.checked += 1;
+
+        let result = if path.exists() {
+            verify_file(&path, None).await
+        } else {
+            Err("file is missing".to_string())
+        };
+
+        if let Err(reason) = result {
+            let _ = fs::remove_file(&path);
+            item.set_status(QueueStatus::Pending);
+            item.output_path = None;
+            item.error = None;
+            report.requeued.push(BrokenDownload {
+                description: format!("{} - Ep {} [{}]", item.show_name, item.episode, item.mode),
+                reason,
+            });
+        }
+    }
+
+    let mut parts = Vec::new();
+    find_part_files(download_dir, &mut parts);
+    for part in parts {
+        if fs::remove_file(&part).is_ok() {
+            report.removed_parts.push(part);
+        }
+    }
+
+    report
+}
+
+/// Recursively collect `.part` files below `dir`.
+fn find_part_files(dir: &Path, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            find_part_files(&path, found);
+        } else if is_part_file(&path) {
+            found.push(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::download::DownloadOutcome;
+    use crate::types::Show;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("aw-verify-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_part_path_round_trip() {
+        let part = part_path(Path::new("/a/Show - S01E01.mkv"));
+        assert_eq!(part, PathBuf::from("/a/Show - S01E01.part.mkv"));
+        assert!(is_part_file(&part));
+        assert!(!is_part_file(Path::new("/a/Show - S01E01.mkv")));
+    }
+
+    #[tokio::test]
+    async fn test_verify_file_rejects_empty_and_short_files() {
+        let dir = temp_dir("file");
+        let path = dir.join("ep.bin");
+
+        fs::write(&path, b"").unwrap();
+        assert_eq!(
+            verify_file(&path, None).await,
+            Err("file is empty".to_string())
+        );
+
+        fs::write(&path, b"abc").unwrap();
+        assert_eq!(
+            verify_file(&path, Some(10)).await,
+            Err("expected 10 bytes, got 3".to_string())
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_verify_queue_requeues_broken_files() {
+        let dir = temp_dir("queue");
+        let show = Show {
+            id: "show".to_string(),
+            name: "Show".to_string(),
+            english_name: None,
+            native_name: None,
+            available_episodes: 1,
+        };
+        let mut queue = DownloadQueue::new();
+        let id = queue.enqueue(&show, 1, "sub", "best", &dir);
+        queue.start_next();
+
+        let empty = dir.join("Show - Episode 1 [sub].mp4");
+        fs::write(&empty, b"").unwrap();
+        queue.finish(id, &DownloadOutcome::Completed(empty.clone()));
+        let leftover = dir.join("Show - Episode 2 [sub].part.mp4");
+        fs::write(&leftover, b"partial").unwrap();
+
+        let report = verify_queue(&mut queue, &dir).await;
+
+        assert_eq!(report.checked, 1);
+        assert_eq!(report.requeued.len(), 1);
+        assert_eq!(report.removed_parts, vec![leftover.clone()]);
+        assert_eq!(queue.items[0].status, QueueStatus::Pending);
+        assert!(!empty.exists());
+        assert!(!leftover.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
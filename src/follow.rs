@@ -0,0 +1,259 @@
+//! Followed shows for anime-watcher.
+//!
+//! Following a show records the episodes available at that moment. The
+//! `sync` command later compares each show's episode list against what it
+//! has seen and queues anything new for download.
+
+use crate::paths;
+use crate::persist;
+use crate::types::Show;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A show being followed for new episodes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FollowedShow {
+    /// Unique identifier for the show.
+    pub show_id: String,
+    /// Display name of the show.
+    pub show_name: String,
+    /// English title, if known.
+    #[serde(default)]
+    pub english_name: Option<String>,
+    /// Original-language title, if known.
+    #[serde(default)]
+    pub native_name: Option<String>,
+    /// Translation mode to download (sub/dub).
+    pub mode: String,
+    /// Preferred quality for downloads.
+    pub quality: String,
+    /// Episode numbers already seen, in ascending order.
+    #[serde(default)]
+    pub known_episodes: Vec<i64>,
+    /// Unix timestamp of when the show was followed.
+    pub followed_at: u64,
+}
+
+impl FollowedShow {
+    /// The show as used by the download queue.
+    pub fn to_show(&self) -> Show {
+        Show {
+            id: self.show_id.clone(),
+            name: self.show_name.clone(),
+            english_name: self.english_name.clone(),
+            native_name: self.native_name.clone(),
+            available_episodes: self.known_episodes.len() as i64,
+        }
+    }
+
+    /// Episodes in `available` that haven't been seen yet, in ascending order.
+    pub fn new_episodes(&self, available: &[i64]) -> Vec<i64> {
+        let mut new: Vec<i64> = available
+            .iter()
+            .copied()
+            .filter(|ep| self.known_episodes.binary_search(ep).is_err())
+            .collect();
+        new.sort_unstable();
+        new.dedup();
+        new
+    }
+
+    /// Record episodes as seen.
+    pub fn mark_known(&mut self, episodes: &[i64]) {
+        self.known_episodes.extend_from_slice(episodes);
+        self.known_episodes.sort_unstable();
+        self.known_episodes.dedup();
+    }
+}
+
+/// All followed shows.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FollowList {
+    /// Map of show_id to followed show.
+    pub shows: HashMap<String, FollowedShow>,
+    /// Shows no longer followed, with the Unix timestamp of unfollowing.
+    ///
+    /// Kept so other instances don't save unfollowed shows back.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub removed: HashMap<String, u64>,
+}
+
+impl FollowList {
+    /// Create a new empty follow list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the path to the follow list, next to the watch history.
+    pub fn get_follows_path() -> Result<PathBuf, io::Error> {
+        Ok(paths::data_dir()?.join("follows.json"))
+    }
+
+    /// Load the follow list from disk.
+    ///
+    /// Returns an empty list if the file doesn't exist.
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::load_from(&Self::get_follows_path()?)
+    }
+
+    /// Load the follow list from a specific file.
+    pub fn load_from(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(persist::load_json(path)?.unwrap_or_else(Self::new))
+    }
+
+    /// Find the newest backup of a follow list that can be parsed.
+    pub fn newest_backup(path: &Path) -> Option<(PathBuf, Self)> {
+        persist::newest_json_backup(path)
+    }
+
+    /// Save the follow list to disk.
+    ///
+    /// Changes saved by other running instances since the list was loaded
+    /// are merged in rather than overwritten.
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.save_to(&Self::get_follows_path()?)
+    }
+
+    /// Save the follow list to a specific file, merging with its contents.
+    pub fn save_to(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        persist::save_json(path, self, Self::merge)
+    }
+
+    /// Merge another follow list into this one.
+    ///
+    /// For shows in both, the settings of the more recent follow win and
+    /// the known episodes are combined. Shows unfollowed since they were
+    /// followed are left out.
+    pub fn merge(&mut self, other: &FollowList) {
+        for (show_id, theirs) in &other.shows {
+            let Some(ours) = self.shows.get_mut(show_id) else {
+                self.shows.insert(show_id.clone(), theirs.clone());
+                continue;
+            };
+            let mut newer = if theirs.followed_at >= ours.followed_at {
+                theirs.clone()
+            } else {
+                ours.clone()
+            };
+            newer.mark_known(&ours.known_episodes);
+            newer.mark_known(&theirs.known_episodes);
+            *ours = newer;
+        }
+
+        for (show_id, removed_at) in &other.removed {
+            let entry = self.removed.entry(show_id.clone()).or_insert(*removed_at);
+            *entry = (*entry).max(*removed_at);
+        }
+        let removed = &self.removed;
+        self.shows.retain(|show_id, followed| {
+            removed
+                .get(show_id)
+                .is_none_or(|removed_at| followed.followed_at > *removed_at)
+        });
+    }
+
+    /// Whether a show is followed.
+    pub fn is_following(&self, show_id: &str) -> bool {
+        self.shows.contains_key(show_id)
+    }
+
+    /// Follow a show; `known_episodes` won't be downloaded by `sync`.
+    pub fn follow(&mut self, show: &Show, mode: &str, quality: &str, known_episodes: &[i64]) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        let mut followed = FollowedShow {
+            show_id: show.id.clone(),
+            show_name: show.name.clone(),
+            english_name: show.english_name.clone(),
+            native_name: show.native_name.clone(),
+            mode: mode.to_string(),
+            quality: quality.to_string(),
+            known_episodes: Vec::new(),
+            followed_at: timestamp,
+        };
+        followed.mark_known(known_episodes);
+
+        self.shows.insert(show.id.clone(), followed);
+    }
+
+    /// Stop following a show.
+    pub fn unfollow(&mut self, show_id: &str) {
+        if self.shows.remove(show_id).is_some() {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            self.removed.insert(show_id.to_string(), timestamp);
+        }
+    }
+
+    /// Followed shows sorted by name.
+    pub fn sorted(&self) -> Vec<&FollowedShow> {
+        let mut shows: Vec<&FollowedShow> = self.shows.values().collect();
+        shows.sort_by_key(|s| s.show_name.to_lowercase());
+        shows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn show() -> Show {
+        Show {
+            id: "show1".to_string(),
+            name: "Test Show".to_string(),
+            english_name: None,
+            native_name: None,
+            available_episodes: 3,
+        }
+    }
+
+    #[test]
+    fn test_follow_and_unfollow() {
+        let mut follows = FollowList::new();
+        follows.follow(&show(), "dub", "720", &[3, 1, 2]);
+
+        let followed = &follows.shows["show1"];
+        assert_eq!(followed.mode, "dub");
+        assert_eq!(followed.known_episodes, vec![1, 2, 3]);
+        assert!(follows.is_following("show1"));
+
+        follows.unfollow("show1");
+        assert!(!follows.is_following("show1"));
+    }
+
+    #[test]
+    fn test_new_episodes() {
+        let mut follows = FollowList::new();
+        follows.follow(&show(), "sub", "best", &[1, 2, 3]);
+        let followed = follows.shows.get_mut("show1").unwrap();
+
+        assert_eq!(followed.new_episodes(&[5, 1, 2, 3, 4]), vec![4, 5]);
+        followed.mark_known(&[4, 5]);
+        assert!(followed.new_episodes(&[1, 2, 3, 4, 5]).is_empty());
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut saved = FollowList::new();
+        saved.follow(&show(), "sub", "best", &[1, 2]);
+
+        // A sync run saw new episodes while this list was loaded
+        let ours = saved.clone();
+        saved.shows.get_mut("show1").unwrap().mark_known(&[3]);
+        saved.merge(&ours);
+        assert_eq!(saved.shows["show1"].known_episodes, vec![1, 2, 3]);
+
+        let mut ours = saved.clone();
+        ours.unfollow("show1");
+        saved.merge(&ours);
+        assert!(!saved.is_following("show1"));
+    }
+}
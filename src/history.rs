@@ -3,33 +3,230 @@
 //! This module provides functionality for saving and loading watch history,
 //! allowing users to resume watching from where they left off.
 
+use crate::paths;
+use crate::persist::{self, CorruptFile, NewerFormat};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-/// A record of watching progress for a single show.
+/// Positions earlier than this many seconds are not saved for resuming.
+const MIN_RESUME_POSITION: f64 = 10.0;
+
+/// Version of the history file format written by this build.
+///
+/// Bump it together with a new entry in `MIGRATIONS` whenever the format
+/// changes in a way older builds would misread. Files from before the
+/// format was versioned have no version field and count as version 0.
+pub const HISTORY_VERSION: u64 = 1;
+
+/// Migrations between format versions; entry `n` turns a version `n` file
+/// into a version `n + 1` one.
+const MIGRATIONS: [fn(&mut Map<String, Value>); HISTORY_VERSION as usize] = [migrate_unversioned];
+
+/// Migrate a file from before the format was versioned.
+///
+/// Those files grew one field at a time, so anything missing is filled in:
+/// records from before per-episode tracking only know the last episode, and
+/// records from before separate sub and dub tracks are keyed by the show
+/// alone, where a removal covered both modes.
+fn migrate_unversioned(file: &mut Map<String, Value>) {
+    if let Some(Value::Object(records)) = file.get_mut("records") {
+        let mut rekeyed = Map::new();
+        for (key, mut record) in std::mem::take(records) {
+            let Value::Object(fields) = &mut record else {
+                rekeyed.insert(key, record);
+                continue;
+            };
+            let unwatched = fields
+                .get("watched")
+                .and_then(Value::as_object)
+                .is_none_or(Map::is_empty);
+            if unwatched {
+                if let (Some(episode), Some(timestamp)) =
+                    (fields.get("episode"), fields.get("timestamp"))
+                {
+                    let watched = Map::from_iter([(episode.to_string(), timestamp.clone())]);
+                    fields.insert("watched".to_string(), Value::Object(watched));
+                }
+            }
+            let key = match (
+                fields.get("show_id").and_then(Value::as_str),
+                fields.get("mode").and_then(Value::as_str),
+            ) {
+                (Some(show_id), Some(mode)) => record_key(show_id, mode),
+                _ => key,
+            };
+            rekeyed.insert(key, record);
+        }
+        *records = rekeyed;
+    }
+
+    if let Some(Value::Object(removed)) = file.get_mut("removed") {
+        for (key, removed_at) in std::mem::take(removed) {
+            if key.contains(':') {
+                removed.insert(key, removed_at);
+            } else {
+                for mode in ["sub", "dub"] {
+                    removed.insert(record_key(&key, mode), removed_at.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Why the contents of a history file couldn't be read.
+#[derive(Debug)]
+enum FormatError {
+    /// Written in a newer format version than [`HISTORY_VERSION`].
+    Newer(u64),
+    /// Not valid JSON, or not a history.
+    Invalid(serde_json::Error),
+}
+
+impl FormatError {
+    /// The error to report for the file at `path`.
+    fn into_error(self, path: &Path) -> Box<dyn std::error::Error> {
+        match self {
+            FormatError::Newer(version) => Box::new(NewerFormat {
+                path: path.to_path_buf(),
+                version,
+                supported: HISTORY_VERSION,
+            }),
+            FormatError::Invalid(e) => Box::new(CorruptFile {
+                path: path.to_path_buf(),
+                message: e.to_string(),
+            }),
+        }
+    }
+}
+
+/// A history as written to disk, tagged with its format version.
+#[derive(Serialize)]
+struct HistoryFile<'a> {
+    version: u64,
+    #[serde(flatten)]
+    history: &'a WatchHistory,
+}
+
+/// Watched timestamp of episodes marked watched without being played here,
+/// such as imported progress; they have no place in the watch timeline.
+pub const UNKNOWN_TIME: u64 = 0;
+
+/// Key of a show's record in the given translation mode.
+///
+/// Sub and dub are tracked separately, since their episodes are released
+/// at different times and people often watch both.
+pub fn record_key(show_id: &str, mode: &str) -> String {
+    format!("{}:{}", show_id, mode)
+}
+
+/// Current Unix timestamp.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// A record of watching progress for a single show in one translation mode.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WatchRecord {
     /// Unique identifier for the show.
     pub show_id: String,
     /// Display name of the show.
     pub show_name: String,
-    /// Last watched episode number.
+    /// Last played episode number.
     pub episode: i64,
     /// Translation mode used (sub/dub).
     pub mode: String,
     /// Unix timestamp of when this was last watched.
     pub timestamp: u64,
+    /// Watched episode numbers, with the Unix timestamp each was last played
+    /// or [`UNKNOWN_TIME`].
+    #[serde(default)]
+    pub watched: BTreeMap<i64, u64>,
+    /// Saved playback positions in seconds, for episodes left unfinished.
+    #[serde(default)]
+    pub positions: BTreeMap<i64, f64>,
+    /// Episode lengths in seconds, as reported by the player.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub durations: BTreeMap<i64, f64>,
+    /// Unix timestamp of the last manual edit of the progress, if any.
+    ///
+    /// Watched episodes from before the edit are not merged back in from
+    /// other instances.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub edited: u64,
+}
+
+/// Whether a timestamp is unset.
+fn is_zero(timestamp: &u64) -> bool {
+    *timestamp == 0
+}
+
+impl WatchRecord {
+    /// Key of this record in [`WatchHistory::records`].
+    pub fn key(&self) -> String {
+        record_key(&self.show_id, &self.mode)
+    }
+
+    /// Whether an episode has been watched.
+    pub fn is_watched(&self, episode: i64) -> bool {
+        self.watched.contains_key(&episode)
+    }
+
+    /// Saved playback position of an unfinished episode, in seconds.
+    pub fn resume_position(&self, episode: i64) -> Option<f64> {
+        self.positions.get(&episode).copied()
+    }
+
+    /// The highest watched episode number.
+    pub fn furthest_watched(&self) -> Option<i64> {
+        self.watched.keys().next_back().copied()
+    }
+
+    /// Episode to continue with: the first unwatched episode in `available`
+    /// after the furthest watched one.
+    ///
+    /// Returns `None` when every later episode has been watched.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anime_watcher::history::WatchHistory;
+    ///
+    /// let mut history = WatchHistory::new();
+    /// history.update("show1", "Show", 20, "sub");
+    /// history.update("show1", "Show", 3, "sub");
+    ///
+    /// let record = history.get_record("show1", "sub").unwrap();
+    /// assert_eq!(record.episode, 3);
+    /// assert_eq!(record.resume_episode(&[1, 2, 3, 20, 21, 22]), Some(21));
+    /// ```
+    pub fn resume_episode(&self, available: &[i64]) -> Option<i64> {
+        let furthest = self.furthest_watched().unwrap_or(i64::MIN);
+        available
+            .iter()
+            .copied()
+            .filter(|ep| *ep > furthest && !self.is_watched(*ep))
+            .min()
+    }
 }
 
 /// Watch history containing all watch records.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct WatchHistory {
-    /// Map of show_id to watch record.
+    /// Watch records by [`record_key`].
     pub records: HashMap<String, WatchRecord>,
+    /// Records removed from the history by [`record_key`], with the Unix
+    /// timestamp of removal.
+    ///
+    /// Kept so other instances don't save removed shows back.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub removed: HashMap<String, u64>,
 }
 
 impl WatchHistory {
@@ -37,101 +234,370 @@ impl WatchHistory {
     pub fn new() -> Self {
         Self {
             records: HashMap::new(),
+            removed: HashMap::new(),
         }
     }
 
     /// Get the path to the history file.
     ///
     /// Returns ~/.local/share/anime-watcher/history.json on Linux,
-    /// or a platform-appropriate location on other systems.
+    /// or a platform-appropriate location on other systems, and
+    /// `profiles/<name>/history.json` there for other profiles.
     pub fn get_history_path() -> Result<PathBuf, io::Error> {
-        let data_dir = if cfg!(target_os = "linux") {
-            dirs::data_local_dir()
-                .ok_or_else(|| {
-                    io::Error::new(io::ErrorKind::NotFound, "Could not find data directory")
-                })?
-                .join("anime-watcher")
-        } else if cfg!(target_os = "macos") {
-            dirs::data_dir()
-                .ok_or_else(|| {
-                    io::Error::new(io::ErrorKind::NotFound, "Could not find data directory")
-                })?
-                .join("anime-watcher")
-        } else {
-            // Windows or other
-            dirs::data_local_dir()
-                .ok_or_else(|| {
-                    io::Error::new(io::ErrorKind::NotFound, "Could not find data directory")
-                })?
-                .join("anime-watcher")
-        };
-
-        Ok(data_dir.join("history.json"))
+        Ok(paths::profile_data_dir()?.join("history.json"))
     }
 
     /// Load watch history from disk.
     ///
-    /// Returns an empty history if the file doesn't exist.
+    /// Returns an empty history if the file doesn't exist, and a
+    /// [`CorruptFile`] error if it can't be parsed.
     pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
-        let path = Self::get_history_path()?;
+        Self::load_from(&Self::get_history_path()?)
+    }
+
+    /// Load the watch histories of every profile but the one in use.
+    ///
+    /// A history that can't be read counts as empty, so nothing counts as
+    /// watched by that profile.
+    pub fn load_other_profiles() -> Vec<Self> {
+        let current = paths::profile().unwrap_or(paths::DEFAULT_PROFILE);
+        let mut names = paths::profiles().unwrap_or_default();
+        names.push(paths::DEFAULT_PROFILE.to_string());
+        names
+            .iter()
+            .filter(|name| *name != current)
+            .map(|name| {
+                paths::data_dir_of(name)
+                    .map_err(Into::into)
+                    .and_then(|dir| Self::load_from(&dir.join("history.json")))
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
 
-        if !path.exists() {
+    /// Load watch history from a specific file.
+    ///
+    /// Files in an older format are migrated; one written by a newer
+    /// version of anime-watcher gives a [`NewerFormat`] error.
+    pub fn load_from(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let Some(content) = persist::read(path)? else {
             return Ok(Self::new());
+        };
+        Self::parse(&content).map_err(|e| e.into_error(path))
+    }
+
+    /// Parse the contents of a history file, migrating older formats.
+    fn parse(content: &str) -> Result<Self, FormatError> {
+        let value: Value = serde_json::from_str(content).map_err(FormatError::Invalid)?;
+        let Value::Object(mut file) = value else {
+            return Err(FormatError::Invalid(serde::de::Error::custom(
+                "expected a JSON object",
+            )));
+        };
+        let version = match file.remove("version") {
+            Some(version) => version.as_u64().ok_or_else(|| {
+                FormatError::Invalid(serde::de::Error::custom("invalid format version"))
+            })?,
+            None => 0,
+        };
+        if version > HISTORY_VERSION {
+            return Err(FormatError::Newer(version));
+        }
+        for migrate in &MIGRATIONS[version as usize..] {
+            migrate(&mut file);
         }
+        serde_json::from_value(Value::Object(file)).map_err(FormatError::Invalid)
+    }
 
-        let content = fs::read_to_string(&path)?;
-        let history: WatchHistory = serde_json::from_str(&content)?;
-        Ok(history)
+    /// Find the newest backup of a history file that can be parsed.
+    ///
+    /// Returns the backup's path with its contents.
+    pub fn newest_backup(path: &Path) -> Option<(PathBuf, Self)> {
+        persist::newest_backup(path, Self::parse)
     }
 
     /// Save watch history to disk.
+    ///
+    /// Changes saved by other running instances since this history was
+    /// loaded are merged in rather than overwritten.
     pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let path = Self::get_history_path()?;
+        self.save_to(&Self::get_history_path()?)
+    }
 
-        // Create parent directories if they don't exist
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
-        }
+    /// Save watch history to a specific file, merging with its contents.
+    ///
+    /// A file written by a newer version of anime-watcher is left alone.
+    pub fn save_to(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let _lock = persist::lock(path)?;
+
+        let mut merged = match persist::read(path)?.map(|content| Self::parse(&content)) {
+            Some(Ok(history)) => history,
+            Some(Err(e @ FormatError::Newer(_))) => return Err(e.into_error(path)),
+            // A file that can't be parsed was already offered for recovery
+            // at startup; its backups are kept.
+            Some(Err(FormatError::Invalid(_))) | None => Self::default(),
+        };
+        merged.merge(self);
 
-        let content = serde_json::to_string_pretty(self)?;
-        fs::write(&path, content)?;
+        let file = HistoryFile {
+            version: HISTORY_VERSION,
+            history: &merged,
+        };
+        let content = serde_json::to_string_pretty(&file)?;
+        persist::write_atomic(path, content.as_bytes())?;
         Ok(())
     }
 
-    /// Update or add a watch record.
+    /// Merge another history into this one.
+    ///
+    /// For records in both, the watched episodes are combined and the
+    /// last-played pointer and saved positions of the more recently watched
+    /// record win. Progress from before a manual edit, and shows removed
+    /// since they were last watched, are left out.
+    pub fn merge(&mut self, other: &WatchHistory) {
+        for (key, theirs) in &other.records {
+            let Some(ours) = self.records.get_mut(key) else {
+                self.records.insert(key.clone(), theirs.clone());
+                continue;
+            };
+
+            // Progress from before the latest manual edit only counts from
+            // the record that was edited
+            let edited = ours.edited.max(theirs.edited);
+            let (mut newer, older) =
+                if (theirs.timestamp, theirs.edited) >= (ours.timestamp, ours.edited) {
+                    (theirs.clone(), &*ours)
+                } else {
+                    (ours.clone(), theirs)
+                };
+            if newer.edited < edited {
+                newer.watched.retain(|_, timestamp| *timestamp > edited);
+            }
+            for (episode, timestamp) in &older.watched {
+                if older.edited < edited && *timestamp <= edited {
+                    continue;
+                }
+                let entry = newer.watched.entry(*episode).or_insert(*timestamp);
+                *entry = (*entry).max(*timestamp);
+            }
+            if older.timestamp > edited {
+                for (episode, position) in &older.positions {
+                    if !newer.watched.contains_key(episode) {
+                        newer.positions.entry(*episode).or_insert(*position);
+                    }
+                }
+            }
+            for (episode, duration) in &older.durations {
+                newer.durations.entry(*episode).or_insert(*duration);
+            }
+            newer.edited = edited;
+            *ours = newer;
+        }
+
+        for (key, removed_at) in &other.removed {
+            let entry = self.removed.entry(key.clone()).or_insert(*removed_at);
+            *entry = (*entry).max(*removed_at);
+        }
+        let removed = &self.removed;
+        self.records.retain(|key, record| {
+            removed
+                .get(key)
+                .is_none_or(|removed_at| record.timestamp > *removed_at)
+        });
+    }
+
+    /// Record that an episode was played.
+    ///
+    /// Moves the last-played pointer of the show's track in `mode` to
+    /// `episode` and marks it watched; earlier watched episodes are kept.
     pub fn update(&mut self, show_id: &str, show_name: &str, episode: i64, mode: &str) {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-
-        let record = WatchRecord {
-            show_id: show_id.to_string(),
-            show_name: show_name.to_string(),
-            episode,
-            mode: mode.to_string(),
-            timestamp,
+        self.record_play(show_id, show_name, episode, mode);
+        self.mark_watched(show_id, mode, episode);
+    }
+
+    /// Record that an episode was started, without marking it watched.
+    ///
+    /// Used when the player reports its progress, so the episode is marked
+    /// watched by [`record_position`](Self::record_position) instead.
+    pub fn record_play(&mut self, show_id: &str, show_name: &str, episode: i64, mode: &str) {
+        let timestamp = now();
+        let record = self
+            .records
+            .entry(record_key(show_id, mode))
+            .or_insert_with(|| WatchRecord {
+                show_id: show_id.to_string(),
+                show_name: String::new(),
+                episode,
+                mode: mode.to_string(),
+                timestamp,
+                watched: BTreeMap::new(),
+                positions: BTreeMap::new(),
+                durations: BTreeMap::new(),
+                edited: 0,
+            });
+        record.show_name = show_name.to_string();
+        record.episode = episode;
+        record.timestamp = timestamp;
+    }
+
+    /// Mark an episode of a show in the history as watched.
+    pub fn mark_watched(&mut self, show_id: &str, mode: &str, episode: i64) {
+        if let Some(record) = self.records.get_mut(&record_key(show_id, mode)) {
+            record.watched.insert(episode, now());
+            record.positions.remove(&episode);
+        }
+    }
+
+    /// Save the playback position of an episode.
+    ///
+    /// Once `threshold` percent of the episode has played it is marked
+    /// watched and the position is dropped, so it starts from the beginning
+    /// when rewatched. Positions in the first few seconds aren't worth
+    /// resuming and are ignored. The episode's length is kept for the watch
+    /// statistics. Returns whether the episode was newly marked watched.
+    pub fn record_position(
+        &mut self,
+        show_id: &str,
+        mode: &str,
+        episode: i64,
+        position: f64,
+        duration: f64,
+        threshold: u8,
+    ) -> bool {
+        let Some(record) = self.records.get_mut(&record_key(show_id, mode)) else {
+            return false;
         };
+        if duration > 0.0 {
+            record.durations.insert(episode, duration);
+        }
+        if duration > 0.0 && position / duration * 100.0 >= f64::from(threshold) {
+            record.positions.remove(&episode);
+            return record.watched.insert(episode, now()).is_none();
+        }
+        if position >= MIN_RESUME_POSITION {
+            record.positions.insert(episode, position);
+        }
+        false
+    }
+
+    /// Seed a show's progress from another tracker.
+    ///
+    /// Marks episodes 1 to `progress` watched in the show's track in `mode`,
+    /// creating the record if needed. The episodes are stamped
+    /// [`UNKNOWN_TIME`], since the other tracker doesn't know when each was
+    /// watched. The record is dated `watched_at` if the tracker knows when the
+    /// show was, and otherwise counts as watched now. Progress behind what is
+    /// already recorded is ignored. Returns whether anything changed.
+    pub fn import_progress(
+        &mut self,
+        show_id: &str,
+        show_name: &str,
+        mode: &str,
+        progress: i64,
+        watched_at: Option<u64>,
+    ) -> bool {
+        let furthest = self
+            .get_record(show_id, mode)
+            .and_then(|r| r.furthest_watched())
+            .unwrap_or(0);
+        if progress <= furthest {
+            return false;
+        }
 
-        self.records.insert(show_id.to_string(), record);
+        let played_at = self.get_record(show_id, mode).map(|r| r.timestamp);
+        self.record_play(show_id, show_name, progress, mode);
+        if let Some(record) = self.records.get_mut(&record_key(show_id, mode)) {
+            if let Some(watched_at) = watched_at {
+                // Playing the show here since still counts as more recent
+                record.timestamp = played_at.unwrap_or(0).max(watched_at);
+            }
+            for episode in 1..=progress {
+                record.watched.entry(episode).or_insert(UNKNOWN_TIME);
+            }
+        }
+        true
+    }
+
+    /// Remove a show's track in `mode` from the history.
+    ///
+    /// Returns whether the track was in the history.
+    pub fn remove(&mut self, show_id: &str, mode: &str) -> bool {
+        let key = record_key(show_id, mode);
+        self.removed.insert(key.clone(), now());
+        self.records.remove(&key).is_some()
+    }
+
+    /// Remove every show from the history.
+    pub fn clear(&mut self) {
+        let timestamp = now();
+        for key in self.records.keys() {
+            self.removed.insert(key.clone(), timestamp);
+        }
+        self.records.clear();
+    }
+
+    /// Correct the progress of a show's track in `mode` to `episode`.
+    ///
+    /// The episode becomes the last played one and is marked watched;
+    /// later episodes are unmarked and their saved positions dropped.
+    /// Returns whether the track was in the history.
+    pub fn set_episode(&mut self, show_id: &str, mode: &str, episode: i64) -> bool {
+        let Some(record) = self.records.get_mut(&record_key(show_id, mode)) else {
+            return false;
+        };
+        let timestamp = now();
+        record.watched.retain(|ep, _| *ep < episode);
+        record.positions.retain(|ep, _| *ep < episode);
+        record.watched.insert(episode, timestamp);
+        record.episode = episode;
+        record.timestamp = timestamp;
+        record.edited = timestamp;
+        true
+    }
+
+    /// Mark every episode in `episodes` of a show's track in `mode` watched.
+    ///
+    /// Episodes not watched before are stamped [`UNKNOWN_TIME`]. The last
+    /// one becomes the last played episode. Returns whether the track was in
+    /// the history.
+    pub fn mark_finished(&mut self, show_id: &str, mode: &str, episodes: &[i64]) -> bool {
+        let Some(record) = self.records.get_mut(&record_key(show_id, mode)) else {
+            return false;
+        };
+        let timestamp = now();
+        for episode in episodes {
+            record.watched.entry(*episode).or_insert(UNKNOWN_TIME);
+        }
+        record.positions.clear();
+        if let Some(last) = episodes.iter().max() {
+            record.episode = *last;
+        }
+        record.timestamp = timestamp;
+        record.edited = timestamp;
+        true
     }
 
-    /// Get the most recently watched shows, sorted by timestamp.
+    /// Get the most recently watched tracks, sorted by timestamp.
+    ///
+    /// A show watched both subbed and dubbed appears once per mode.
     pub fn get_recent(&self, limit: usize) -> Vec<&WatchRecord> {
         let mut records: Vec<&WatchRecord> = self.records.values().collect();
-        records.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        records.sort_by_key(|r| std::cmp::Reverse(r.timestamp));
         records.truncate(limit);
         records
     }
 
-    /// Get the watch record for a specific show (reserved for future use).
-    #[allow(dead_code)]
-    pub fn get_record(&self, show_id: &str) -> Option<&WatchRecord> {
-        self.records.get(show_id)
+    /// Get the watch record of a show in a specific mode.
+    pub fn get_record(&self, show_id: &str, mode: &str) -> Option<&WatchRecord> {
+        self.records.get(&record_key(show_id, mode))
+    }
+
+    /// Get the records of a show in every mode it was watched in.
+    pub fn tracks<'a>(&'a self, show_id: &'a str) -> impl Iterator<Item = &'a WatchRecord> {
+        self.records.values().filter(move |r| r.show_id == show_id)
     }
 
-    /// Check if there's any watch history (reserved for future use).
-    #[allow(dead_code)]
+    /// Check if there's any watch history.
     pub fn is_empty(&self) -> bool {
         self.records.is_empty()
     }
@@ -140,6 +606,7 @@ impl WatchHistory {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
 
     #[test]
     fn test_new_history_is_empty() {
@@ -153,7 +620,7 @@ mod tests {
         history.update("show1", "Test Show", 5, "sub");
 
         assert!(!history.is_empty());
-        let record = history.get_record("show1").unwrap();
+        let record = history.get_record("show1", "sub").unwrap();
         assert_eq!(record.show_name, "Test Show");
         assert_eq!(record.episode, 5);
         assert_eq!(record.mode, "sub");
@@ -165,43 +632,102 @@ mod tests {
         history.update("show1", "Test Show", 5, "sub");
         history.update("show1", "Test Show", 10, "sub");
 
-        let record = history.get_record("show1").unwrap();
+        let record = history.get_record("show1", "sub").unwrap();
         assert_eq!(record.episode, 10);
     }
 
+    #[test]
+    fn test_rewatching_keeps_progress() {
+        let mut history = WatchHistory::new();
+        for ep in 1..=20 {
+            history.update("show1", "Test Show", ep, "sub");
+        }
+        history.update("show1", "Test Show", 3, "sub");
+
+        let record = history.get_record("show1", "sub").unwrap();
+        assert_eq!(record.episode, 3);
+        assert_eq!(record.furthest_watched(), Some(20));
+        assert!(record.is_watched(15));
+        assert!(!record.is_watched(21));
+
+        let available: Vec<i64> = (1..=24).collect();
+        assert_eq!(record.resume_episode(&available), Some(21));
+        assert_eq!(record.resume_episode(&available[..20]), None);
+    }
+
+    #[test]
+    fn test_record_position() {
+        let mut history = WatchHistory::new();
+        history.record_play("show1", "Test Show", 4, "sub");
+
+        assert!(!history.record_position("show1", "sub", 4, 5.0, 1440.0, 85));
+        assert_eq!(
+            history
+                .get_record("show1", "sub")
+                .unwrap()
+                .resume_position(4),
+            None
+        );
+
+        assert!(!history.record_position("show1", "sub", 4, 600.0, 1440.0, 85));
+        let record = history.get_record("show1", "sub").unwrap();
+        assert!(!record.is_watched(4));
+        assert_eq!(record.resume_position(4), Some(600.0));
+
+        assert!(history.record_position("show1", "sub", 4, 1300.0, 1440.0, 85));
+        let record = history.get_record("show1", "sub").unwrap();
+        assert!(record.is_watched(4));
+        assert_eq!(record.resume_position(4), None);
+        assert_eq!(record.durations.get(&4), Some(&1440.0));
+
+        assert!(!history.record_position("unknown", "sub", 1, 600.0, 1440.0, 85));
+    }
+
     #[test]
     fn test_get_recent_returns_sorted() {
         let mut history = WatchHistory::new();
 
         // Manually create records with specific timestamps
         history.records.insert(
-            "show1".to_string(),
+            "show1:sub".to_string(),
             WatchRecord {
                 show_id: "show1".to_string(),
                 show_name: "Show 1".to_string(),
                 episode: 1,
                 mode: "sub".to_string(),
                 timestamp: 1000,
+                watched: BTreeMap::new(),
+                positions: BTreeMap::new(),
+                durations: BTreeMap::new(),
+                edited: 0,
             },
         );
         history.records.insert(
-            "show2".to_string(),
+            "show2:sub".to_string(),
             WatchRecord {
                 show_id: "show2".to_string(),
                 show_name: "Show 2".to_string(),
                 episode: 2,
                 mode: "sub".to_string(),
                 timestamp: 2000,
+                watched: BTreeMap::new(),
+                positions: BTreeMap::new(),
+                durations: BTreeMap::new(),
+                edited: 0,
             },
         );
         history.records.insert(
-            "show3".to_string(),
+            "show3:sub".to_string(),
             WatchRecord {
                 show_id: "show3".to_string(),
                 show_name: "Show 3".to_string(),
                 episode: 3,
                 mode: "sub".to_string(),
                 timestamp: 3000,
+                watched: BTreeMap::new(),
+                positions: BTreeMap::new(),
+                durations: BTreeMap::new(),
+                edited: 0,
             },
         );
 
@@ -212,9 +738,151 @@ mod tests {
         assert_eq!(recent[1].show_id, "show2");
     }
 
+    #[test]
+    fn test_save_merges_other_instance() {
+        let dir = std::env::temp_dir().join(format!("aw-history-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("history.json");
+
+        // Two instances start from the same history
+        let mut first = WatchHistory::new();
+        first.update("show1", "Show 1", 1, "sub");
+        first.save_to(&path).unwrap();
+        let mut second = WatchHistory::load_from(&path).unwrap();
+
+        first.update("show1", "Show 1", 2, "sub");
+        first.save_to(&path).unwrap();
+        second.update("show2", "Show 2", 7, "dub");
+        second.save_to(&path).unwrap();
+
+        let merged = WatchHistory::load_from(&path).unwrap();
+        assert!(merged.get_record("show1", "sub").unwrap().is_watched(2));
+        assert_eq!(merged.get_record("show2", "dub").unwrap().episode, 7);
+
+        fs::write(&path, "{\"records\": {").unwrap();
+        let err = WatchHistory::load_from(&path).unwrap_err();
+        assert!(err.downcast_ref::<persist::CorruptFile>().is_some());
+        let (_, backup) = WatchHistory::newest_backup(&path).unwrap();
+        assert!(backup.get_record("show1", "sub").is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_edits_survive_merge() {
+        let mut history = WatchHistory::new();
+        for ep in 1..=10 {
+            history.update("show1", "Show 1", ep, "sub");
+        }
+        history.update("show2", "Show 2", 1, "sub");
+        // Another instance loaded the history before the edits
+        let stale = history.clone();
+
+        assert!(history.set_episode("show1", "sub", 4));
+        assert!(history.remove("show2", "sub"));
+        history.merge(&stale);
+
+        let record = history.get_record("show1", "sub").unwrap();
+        assert_eq!(record.episode, 4);
+        assert_eq!(record.furthest_watched(), Some(4));
+        assert!(history.get_record("show2", "sub").is_none());
+
+        // Watching a removed show again brings it back
+        let mut later = stale.clone();
+        later.records.get_mut("show2:sub").unwrap().timestamp = u64::MAX;
+        history.merge(&later);
+        assert!(history.get_record("show2", "sub").is_some());
+    }
+
+    #[test]
+    fn test_mark_finished_and_clear() {
+        let mut history = WatchHistory::new();
+        history.record_play("show1", "Show 1", 3, "sub");
+        history.record_position("show1", "sub", 3, 600.0, 1440.0, 85);
+
+        assert!(history.mark_finished("show1", "sub", &[1, 2, 3, 4]));
+        let record = history.get_record("show1", "sub").unwrap();
+        assert_eq!(record.episode, 4);
+        assert_eq!(record.watched.len(), 4);
+        assert_eq!(record.resume_position(3), None);
+        assert!(!history.mark_finished("unknown", "sub", &[1]));
+
+        history.clear();
+        assert!(history.is_empty());
+        assert!(history.removed.contains_key("show1:sub"));
+    }
+
+    #[test]
+    fn test_sub_and_dub_tracked_separately() {
+        let mut history = WatchHistory::new();
+        history.update("show1", "Show 1", 20, "sub");
+        history.update("show1", "Show 1", 5, "dub");
+
+        assert_eq!(history.get_record("show1", "sub").unwrap().episode, 20);
+        assert_eq!(history.get_record("show1", "dub").unwrap().episode, 5);
+        assert_eq!(history.tracks("show1").count(), 2);
+        assert_eq!(history.get_recent(10).len(), 2);
+
+        assert!(history.remove("show1", "dub"));
+        assert!(history.get_record("show1", "sub").is_some());
+    }
+
+    #[test]
+    fn test_import_progress() {
+        let mut history = WatchHistory::new();
+        history.update("show1", "Show 1", 5, "sub");
+
+        assert!(history.import_progress("show1", "Show 1", "sub", 12, None));
+        let record = history.get_record("show1", "sub").unwrap();
+        assert_eq!(record.episode, 12);
+        assert_eq!(record.watched.len(), 12);
+
+        assert!(!history.import_progress("show1", "Show 1", "sub", 8, None));
+        assert!(history.import_progress("show2", "Show 2", "dub", 3, Some(1000)));
+        let record = history.get_record("show2", "dub").unwrap();
+        assert_eq!(record.timestamp, 1000);
+        assert_eq!(record.watched.get(&1), Some(&UNKNOWN_TIME));
+    }
+
+    #[test]
+    fn test_parse_rekeys_old_records() {
+        let content = r#"{
+            "records": {
+                "show1": {"show_id": "show1", "show_name": "Show 1", "episode": 3,
+                          "mode": "dub", "timestamp": 1000}
+            },
+            "removed": {"show2": 2000}
+        }"#;
+        let history = WatchHistory::parse(content).unwrap();
+
+        let record = history.get_record("show1", "dub").unwrap();
+        assert!(record.is_watched(3));
+        assert!(history.records.contains_key("show1:dub"));
+        assert!(history.removed.contains_key("show2:sub"));
+        assert!(history.removed.contains_key("show2:dub"));
+    }
+
+    #[test]
+    fn test_parse_versions() {
+        let history = WatchHistory::parse(r#"{"version": 1, "records": {}}"#).unwrap();
+        assert!(history.is_empty());
+        assert!(matches!(
+            WatchHistory::parse(r#"{"version": 2, "records": {}}"#),
+            Err(FormatError::Newer(2))
+        ));
+        assert!(matches!(
+            WatchHistory::parse(r#"{"version": "one", "records": {}}"#),
+            Err(FormatError::Invalid(_))
+        ));
+        assert!(matches!(
+            WatchHistory::parse("[]"),
+            Err(FormatError::Invalid(_))
+        ));
+    }
+
     #[test]
     fn test_get_record_not_found() {
         let history = WatchHistory::new();
-        assert!(history.get_record("nonexistent").is_none());
+        assert!(history.get_record("nonexistent", "sub").is_none());
     }
 }
@@ -22,10 +22,21 @@
 //! cargo run -- -m dub
 //! ```
 
+pub mod anicli;
+pub mod anilist;
+pub mod annotations;
 pub mod api;
+pub mod commands;
 pub mod config;
 pub mod download;
 pub mod error;
+pub mod follow;
 pub mod history;
+pub mod mal;
+pub mod paths;
+pub mod persist;
+pub mod player;
+pub mod stats;
 pub mod tui;
 pub mod types;
+pub mod watchlist;
@@ -1,32 +1,60 @@
 //! Main entry point for the anime-watcher CLI application.
 
+mod anicli;
+mod anilist;
+mod annotations;
 mod api;
+mod commands;
 mod config;
 mod download;
 mod error;
+mod follow;
 mod history;
+mod mal;
+mod paths;
+mod persist;
+mod player;
+mod stats;
 mod tui;
 mod types;
-
-use crate::api::{fetch_episodes, fetch_stream_sources, search_shows};
+mod watchlist;
+
+use crate::anilist::{AniList, ProgressSync};
+use crate::annotations::{parse_score, parse_tags, Annotations};
+use crate::api::{choose_stream, fetch_episodes, fetch_stream_sources, search_shows};
+use crate::commands::{
+    exit_code, run_anilist_login, run_anilist_logout, run_anilist_map, run_anilist_pull,
+    run_anilist_push, run_anilist_unmap, run_download, run_history_export, run_history_import,
+    run_stats, run_sync, run_verify, DownloadRequest,
+};
 use crate::config::Config;
-use crate::download::{download_file, get_output_path};
-use crate::history::WatchHistory;
-use crate::tui::{draw, poll_event, Action, App};
-use crate::types::StreamSource;
-use clap::Parser;
+use crate::download::{
+    check_space, estimate_batch, format_size, parse_rate, parse_size, validate_template,
+    CleanupPolicy, DownloadEvent, DownloadManager, DownloadOptions, DownloadOutcome, DownloadQueue,
+    Quota, Schedule,
+};
+use crate::follow::FollowList;
+use crate::history::{record_key, WatchHistory};
+use crate::persist::{CorruptFile, NewerFormat};
+use crate::player::{format_position, PlaybackEvent, Player};
+use crate::stats::Stats;
+use crate::tui::{
+    draw, poll_event, Action, AnnotationField, AnnotationInput, App, HistoryEdit, WatchlistEdit,
+};
+use crate::watchlist::{WatchStatus, Watchlist};
+use chrono::Local;
+use clap::{Parser, Subcommand};
 use crossterm::{
     event::Event,
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use log::{debug, info};
+use log::{debug, info, warn};
 use ratatui::prelude::*;
 use std::env;
-use std::io::{self, stdout};
+use std::io::{self, stdout, IsTerminal, Write};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 /// Command-line arguments for the anime-watcher application.
 #[derive(Parser, Debug)]
@@ -37,7 +65,8 @@ use std::time::Duration;
     long_about = "Search, browse, and stream anime from AllAnime using a TUI interface."
 )]
 struct Args {
-    /// Translation mode: "sub" for subtitled, "dub" for dubbed
+    /// Translation mode: "sub" for subtitled, "dub" for dubbed, "dual" for
+    /// downloads with both audio tracks
     #[arg(short, long, default_value = "sub")]
     mode: String,
 
@@ -60,6 +89,142 @@ struct Args {
     /// Video player to use (overrides config and platform default)
     #[arg(short, long)]
     player: Option<String>,
+
+    /// Profile with its own history, watchlist and settings (asked at
+    /// startup if any exist)
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// Watch without recording history or syncing trackers (toggle in the
+    /// TUI with `i`)
+    #[arg(long)]
+    incognito: bool,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+/// Subcommands that run without the TUI.
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Re-check finished downloads and queue broken episodes again
+    Verify,
+
+    /// Download episodes without the TUI (for scripts and cron)
+    Download {
+        /// Show ID or search query
+        show: String,
+
+        /// Episodes to download, e.g. "1-10,12" or "all"
+        #[arg(short, long, default_value = "all")]
+        episodes: String,
+
+        /// Translation mode: "sub", "dub" or "dual" (defaults to config)
+        #[arg(short, long)]
+        mode: Option<String>,
+
+        /// Preferred quality (defaults to config)
+        #[arg(short, long)]
+        quality: Option<String>,
+
+        /// Directory for downloads (defaults to config)
+        #[arg(short, long)]
+        dir: Option<String>,
+
+        /// Print what would be downloaded without downloading
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Print progress as JSON lines
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Queue new episodes of followed shows and download them
+    Sync {
+        /// Only queue new episodes; don't download them now
+        #[arg(long)]
+        queue_only: bool,
+    },
+
+    /// Sync watch progress with AniList
+    Anilist {
+        #[command(subcommand)]
+        command: AnilistCommand,
+    },
+
+    /// Import or export the watch history
+    History {
+        #[command(subcommand)]
+        command: HistoryCommand,
+    },
+
+    /// Show watch statistics
+    Stats {
+        /// Print the statistics as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Watch history subcommands.
+#[derive(Subcommand, Debug)]
+enum HistoryCommand {
+    /// Write the watch history in another tracker's format
+    Export {
+        /// Output format
+        #[arg(long, default_value = "mal-xml", value_parser = ["mal-xml"])]
+        format: String,
+
+        /// File to write (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Add another tracker's export to the watch history
+    Import {
+        /// MyAnimeList XML export or ani-cli history (defaults to ani-cli's)
+        file: Option<PathBuf>,
+
+        /// Input format (detected from the file by default)
+        #[arg(long, value_parser = ["mal-xml", "ani-cli"])]
+        format: Option<String>,
+
+        /// Translation mode to record progress in (defaults to config)
+        #[arg(short, long)]
+        mode: Option<String>,
+    },
+}
+
+/// AniList subcommands.
+#[derive(Subcommand, Debug)]
+enum AnilistCommand {
+    /// Log in with an AniList access token
+    Login,
+
+    /// Forget the stored access token
+    Logout,
+
+    /// Map a show to an AniList entry by hand
+    Map {
+        /// AllAnime show ID
+        show_id: String,
+
+        /// AniList media ID (the number in the anime's AniList URL)
+        media_id: i64,
+    },
+
+    /// Remove a show's mapping so it is matched by title again
+    Unmap {
+        /// AllAnime show ID
+        show_id: String,
+    },
+
+    /// Push the progress of every show in the watch history
+    Push,
+
+    /// Seed the watch history from your AniList list
+    Pull,
 }
 
 /// Search for an executable in the system PATH.
@@ -128,66 +293,6 @@ fn find_in_path<P: AsRef<Path>>(exe_name: P) -> Option<PathBuf> {
     })
 }
 
-/// Select a stream source based on quality preference.
-fn choose_stream(
-    sources: &[StreamSource],
-    quality: &str,
-) -> Result<StreamSource, Box<dyn std::error::Error>> {
-    if sources.is_empty() {
-        return Err("No sources available".into());
-    }
-
-    if sources.len() == 1 {
-        return Ok(sources[0].clone());
-    }
-
-    let mut known_quality: Vec<&StreamSource> = sources.iter().filter(|s| s.quality > 0).collect();
-    let unknown_quality: Vec<&StreamSource> = sources.iter().filter(|s| s.quality == 0).collect();
-
-    known_quality.sort_by(|a, b| b.quality.cmp(&a.quality));
-
-    match quality.to_lowercase().as_str() {
-        "best" => {
-            if let Some(source) = known_quality.first() {
-                Ok((*source).clone())
-            } else if let Some(source) = unknown_quality.first() {
-                Ok((*source).clone())
-            } else {
-                Ok(sources[0].clone())
-            }
-        }
-        "worst" => {
-            if let Some(source) = known_quality.last() {
-                Ok((*source).clone())
-            } else if let Some(source) = unknown_quality.first() {
-                Ok((*source).clone())
-            } else {
-                Ok(sources[0].clone())
-            }
-        }
-        q => {
-            if let Ok(target_quality) = q.parse::<i32>() {
-                if let Some(source) = known_quality.iter().find(|s| s.quality == target_quality) {
-                    return Ok((*source).clone());
-                }
-
-                if !known_quality.is_empty() {
-                    let closest = known_quality
-                        .iter()
-                        .min_by_key(|s| (s.quality - target_quality).abs())
-                        .unwrap();
-                    return Ok((*closest).clone());
-                }
-
-                Ok(sources[0].clone())
-            } else {
-                // Return first source if quality string is invalid
-                Ok(sources[0].clone())
-            }
-        }
-    }
-}
-
 /// Get the appropriate video player for the current operating system.
 fn get_player() -> Result<&'static str, String> {
     match std::env::consts::OS {
@@ -213,12 +318,162 @@ fn restore_terminal() -> io::Result<()> {
     Ok(())
 }
 
+/// Load a data file, asking what to do if it is corrupt.
+///
+/// Runs before the TUI starts. A corrupt file is never overwritten: it is
+/// kept with a `.corrupt-<time>` suffix, and either its newest readable
+/// backup is restored or `name` starts out empty. A file written by a newer
+/// version of anime-watcher ends the run instead.
+fn load_or_recover<T: Default>(
+    name: &str,
+    loaded: Result<T, Box<dyn std::error::Error>>,
+    newest_backup: impl Fn(&Path) -> Option<(PathBuf, T)>,
+) -> T {
+    let error = match loaded {
+        Ok(value) => return value,
+        Err(e) => e,
+    };
+    if let Some(newer) = error.downcast_ref::<NewerFormat>() {
+        // Starting empty would overwrite the file on the next save
+        eprintln!("Error: {}", newer);
+        std::process::exit(1);
+    }
+    let Some(corrupt) = error.downcast_ref::<CorruptFile>() else {
+        eprintln!("Warning: Failed to load {}: {}", name, error);
+        return T::default();
+    };
+
+    eprintln!("Error: {}", corrupt);
+    let backup = newest_backup(&corrupt.path);
+    let question = match &backup {
+        Some((path, _)) => {
+            let saved = std::fs::metadata(path)
+                .and_then(|m| m.modified())
+                .map(|t| {
+                    chrono::DateTime::<Local>::from(t)
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string()
+                })
+                .unwrap_or_else(|_| "unknown date".to_string());
+            eprintln!(
+                "The newest readable backup is {} ({}).",
+                path.display(),
+                saved
+            );
+            format!(
+                "Restore it [r], start with an empty {} [e], or quit [q]? ",
+                name
+            )
+        }
+        None => {
+            eprintln!("No readable backup was found.");
+            format!("Start with an empty {} [e], or quit [q]? ", name)
+        }
+    };
+
+    let restore = loop {
+        eprint!("{}", question);
+        let _ = io::stderr().flush();
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+            std::process::exit(1);
+        }
+        match answer.trim().to_lowercase().as_str() {
+            "r" if backup.is_some() => break true,
+            "e" => break false,
+            "q" => std::process::exit(1),
+            _ => {}
+        }
+    };
+
+    match persist::quarantine(&corrupt.path) {
+        Ok(moved) => eprintln!("The corrupt file was kept as {}", moved.display()),
+        Err(e) => {
+            eprintln!("Error: Failed to move {}: {}", corrupt.path.display(), e);
+            std::process::exit(1);
+        }
+    }
+
+    match backup {
+        Some((path, value)) if restore => {
+            if let Err(e) = std::fs::copy(&path, &corrupt.path) {
+                eprintln!("Error: Failed to restore {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+            value
+        }
+        _ => T::default(),
+    }
+}
+
+/// Ask which profile to use when starting the TUI.
+///
+/// Only asked if profiles other than the default exist and stdin is a
+/// terminal; otherwise the default profile is used. A name that isn't
+/// listed starts a new profile.
+fn choose_profile() -> Option<String> {
+    let profiles = paths::profiles().unwrap_or_default();
+    if profiles.is_empty() || !io::stdin().is_terminal() {
+        return None;
+    }
+
+    let names: Vec<&str> = std::iter::once(paths::DEFAULT_PROFILE)
+        .chain(profiles.iter().map(String::as_str))
+        .collect();
+    eprintln!("Profiles:");
+    for (i, name) in names.iter().enumerate() {
+        eprintln!("  {}) {}", i + 1, name);
+    }
+    loop {
+        eprint!("Choose a profile by number or name [1]: ");
+        let _ = io::stderr().flush();
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer).unwrap_or(0) == 0 {
+            std::process::exit(1);
+        }
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return None;
+        }
+        if let Some(name) = answer
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| names.get(n.wrapping_sub(1)))
+        {
+            return Some(name.to_string());
+        }
+        match paths::validate_profile(answer) {
+            Ok(()) => return Some(answer.to_string()),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
+    // The profile decides which config overlay and data files are used
+    let profile = match &args.profile {
+        Some(name) => Some(name.clone()),
+        None if args.command.is_none() => choose_profile(),
+        None => None,
+    };
+    if let Some(name) = &profile {
+        if let Err(e) = paths::set_profile(name) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+
     // Load config first (before logging, so we can use config log_level)
-    let config = Config::load().unwrap_or_default();
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: Failed to load config: {}", e);
+            std::process::exit(1);
+        }
+    };
 
     // Merge CLI log with config log_level
     let log_level_value = if args.log == 1 {
@@ -266,16 +521,175 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Validate mode
     let mode = match mode_str.as_str() {
-        "sub" | "dub" => mode_str.clone(),
+        "sub" | "dub" | "dual" => mode_str.clone(),
         other => {
-            eprintln!("Error: Invalid mode '{}'. Use 'sub' or 'dub'.", other);
+            eprintln!(
+                "Error: Invalid mode '{}'. Use 'sub', 'dub' or 'dual'.",
+                other
+            );
             std::process::exit(1);
         }
     };
 
     let download_dir = Path::new(&download_dir_str);
     let download_mode = args.download;
-    let quality = quality_str.clone();
+    let quality = quality_str;
+
+    // Validate filename template
+    if let Err(e) = validate_template(&config.filename_template) {
+        eprintln!("Error: Invalid filename_template: {}", e);
+        std::process::exit(1);
+    }
+
+    // Validate output layout
+    if !matches!(config.output_layout.as_str(), "flat" | "media_server") {
+        eprintln!(
+            "Error: Invalid output_layout '{}'. Use 'flat' or 'media_server'.",
+            config.output_layout
+        );
+        std::process::exit(1);
+    }
+
+    // Validate bandwidth limit and download windows
+    if let Some(rate) = &config.rate_limit {
+        if let Err(e) = parse_rate(rate) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+    let schedule = match Schedule::parse(&config.download_windows) {
+        Ok(schedule) => schedule,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Validate download quota
+    if let Some(quota) = &config.download_quota {
+        if let Err(e) = parse_size(quota) {
+            eprintln!("Error: Invalid download_quota: {}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Err(e) = CleanupPolicy::parse(&config.quota_cleanup) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Some(Commands::Verify) = args.command {
+        return run_verify(download_dir).await;
+    }
+
+    if let Some(Commands::Stats { json }) = args.command {
+        return run_stats(json);
+    }
+
+    if let Some(Commands::History { command }) = &args.command {
+        return match command {
+            HistoryCommand::Export { output, .. } => {
+                run_history_export(output.as_deref(), &config.anilist_api_url).await
+            }
+            HistoryCommand::Import {
+                file,
+                format,
+                mode: import_mode,
+            } => {
+                // Dual-audio downloads are watched as the sub
+                let import_mode = match import_mode.as_deref().unwrap_or(&mode) {
+                    "dual" => "sub",
+                    m => m,
+                };
+                if !matches!(import_mode, "sub" | "dub") {
+                    eprintln!("Error: Invalid mode '{}'. Use 'sub' or 'dub'.", import_mode);
+                    std::process::exit(exit_code::USAGE);
+                }
+                run_history_import(file.as_deref(), format.as_deref(), import_mode).await
+            }
+        };
+    }
+
+    if let Some(Commands::Anilist { command }) = &args.command {
+        let api_url = &config.anilist_api_url;
+        return match command {
+            AnilistCommand::Login => {
+                run_anilist_login(api_url, config.anilist_client_id.as_deref()).await
+            }
+            AnilistCommand::Logout => run_anilist_logout(),
+            AnilistCommand::Map { show_id, media_id } => {
+                run_anilist_map(api_url, show_id, *media_id).await
+            }
+            AnilistCommand::Unmap { show_id } => run_anilist_unmap(show_id),
+            AnilistCommand::Push => run_anilist_push(api_url).await,
+            // Dual-audio downloads are watched as the sub
+            AnilistCommand::Pull => {
+                let mode = if mode == "dual" { "sub" } else { &mode };
+                run_anilist_pull(api_url, mode).await
+            }
+        };
+    }
+
+    if let Some(Commands::Sync { queue_only }) = args.command {
+        if !queue_only && find_in_path("yt-dlp").is_none() {
+            eprintln!("Error: yt-dlp not found in PATH. Please install yt-dlp.");
+            std::process::exit(exit_code::USAGE);
+        }
+        let options = DownloadOptions::from_config(&config);
+        let code = run_sync(download_dir, &options, &schedule, queue_only).await?;
+        std::process::exit(code);
+    }
+
+    if let Some(Commands::Download {
+        show,
+        episodes,
+        mode: download_mode,
+        quality: download_quality,
+        dir,
+        dry_run,
+        json,
+    }) = &args.command
+    {
+        let request = DownloadRequest {
+            query: show.clone(),
+            episodes: episodes.clone(),
+            mode: download_mode.clone().unwrap_or_else(|| mode.clone()),
+            quality: download_quality.clone().unwrap_or_else(|| quality.clone()),
+            download_dir: dir
+                .as_ref()
+                .map(PathBuf::from)
+                .unwrap_or_else(|| download_dir.to_path_buf()),
+            dry_run: *dry_run,
+            json: *json,
+        };
+
+        if !matches!(request.mode.as_str(), "sub" | "dub" | "dual") {
+            eprintln!(
+                "Error: Invalid mode '{}'. Use 'sub', 'dub' or 'dual'.",
+                request.mode
+            );
+            std::process::exit(exit_code::USAGE);
+        }
+        if !request.dry_run && !request.download_dir.is_dir() {
+            eprintln!(
+                "Error: Download directory '{}' does not exist.",
+                request.download_dir.display()
+            );
+            std::process::exit(exit_code::USAGE);
+        }
+        if !request.dry_run && find_in_path("yt-dlp").is_none() {
+            eprintln!("Error: yt-dlp not found in PATH. Please install yt-dlp.");
+            std::process::exit(exit_code::USAGE);
+        }
+
+        let code = run_download(&request, &DownloadOptions::from_config(&config)).await;
+        std::process::exit(code);
+    }
+
+    // Dual audio only exists for downloads
+    if mode == "dual" && !download_mode {
+        eprintln!("Error: 'dual' mode is only available for downloads. Use -D.");
+        std::process::exit(1);
+    }
 
     // Verify download directory
     if download_mode && !download_dir.exists() {
@@ -308,8 +722,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
-    let player_args = config.player_args.clone();
-
     if find_in_path(&player).is_none() {
         eprintln!("Error: {} not found in PATH.", player);
         std::process::exit(1);
@@ -317,8 +729,40 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Using video player: {}", player);
 
-    // Load watch history
-    let mut watch_history = WatchHistory::load().unwrap_or_default();
+    if config.watched_threshold == 0 || config.watched_threshold > 100 {
+        eprintln!("Error: watched_threshold must be between 1 and 100");
+        std::process::exit(1);
+    }
+
+    // Load data files, offering recovery from backups if any are corrupt
+    let mut watch_history = load_or_recover(
+        "watch history",
+        WatchHistory::load(),
+        WatchHistory::newest_backup,
+    );
+    // Unfinished queue items resume automatically
+    let queue = load_or_recover(
+        "download queue",
+        DownloadQueue::load(),
+        DownloadQueue::newest_backup,
+    );
+    let follows = load_or_recover(
+        "followed shows",
+        FollowList::load(),
+        FollowList::newest_backup,
+    );
+    let mut watchlist = load_or_recover("watchlist", Watchlist::load(), Watchlist::newest_backup);
+    let mut annotations = load_or_recover(
+        "scores, notes and tags",
+        Annotations::load(),
+        Annotations::newest_backup,
+    );
+    // Notes used to be kept on the watchlist
+    if watchlist.move_notes(&mut annotations) {
+        if let Err(e) = annotations.save().and_then(|_| watchlist.save()) {
+            eprintln!("Warning: Failed to move watchlist notes: {}", e);
+        }
+    }
 
     // Initialize terminal
     let mut terminal = init_terminal()?;
@@ -331,8 +775,171 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         config.keybindings.clone(),
         config.colors.clone(),
     );
+    app.profile = paths::profile().map(str::to_string);
+    app.incognito = args.incognito;
 
     // Set up history for startup screen
+    refresh_history(&mut app, &watch_history);
+
+    app.set_queue(&queue);
+    app.set_follows(&follows);
+    app.set_watchlist(&watchlist);
+    app.set_annotations(&annotations);
+
+    // Progress is pushed to AniList once logged in
+    let anilist = match anilist::load_token() {
+        Ok(Some(token)) => Some(ProgressSync::new(AniList::new(
+            &config.anilist_api_url,
+            Some(token),
+        )?)),
+        Ok(None) => None,
+        Err(e) => {
+            warn!("Failed to read the AniList token: {}", e);
+            None
+        }
+    };
+
+    let download_options = DownloadOptions::from_config(&config);
+    let mut ctx = RunContext {
+        mode,
+        quality,
+        download_dir: download_dir.to_path_buf(),
+        player: Player::new(player, config.player_args.clone()),
+        watched_threshold: config.watched_threshold,
+        downloads: DownloadManager::new(&download_options),
+        quota: download_options.quota,
+        filename_template: download_options.filename_template,
+        queue,
+        schedule,
+        follows,
+        watchlist,
+        annotations,
+        anilist,
+    };
+
+    // Main event loop
+    let result = run_app(&mut terminal, &mut app, &mut watch_history, &mut ctx).await;
+
+    // Restore terminal
+    restore_terminal()?;
+
+    result
+}
+
+/// Session settings and background services shared by the event loop.
+struct RunContext {
+    /// Translation mode (sub/dub)
+    mode: String,
+    /// Preferred quality
+    quality: String,
+    /// Directory for downloads
+    download_dir: PathBuf,
+    /// Video player, tracking playback positions where supported
+    player: Player,
+    /// Percentage of an episode that must play to mark it watched
+    watched_threshold: u8,
+    /// Background download workers
+    downloads: DownloadManager,
+    /// Size limit for the download directory
+    quota: Option<Quota>,
+    /// Filename template of downloaded episodes
+    filename_template: String,
+    /// Persistent download queue feeding the workers
+    queue: DownloadQueue,
+    /// Time windows during which queued downloads may start
+    schedule: Schedule,
+    /// Shows followed for new episodes
+    follows: FollowList,
+    /// Shows being watched or planned
+    watchlist: Watchlist,
+    /// Scores, notes and tags of shows
+    annotations: Annotations,
+    /// Pushes progress to AniList, when logged in
+    anilist: Option<ProgressSync>,
+}
+
+/// Hand pending queue items to the download manager while it has room.
+///
+/// Nothing new starts outside the configured download windows; episodes
+/// already downloading are allowed to finish.
+fn pump_download_queue(app: &mut App, ctx: &mut RunContext) {
+    let next_opening = ctx.schedule.next_opening(Local::now().time());
+    app.download_window_opens = next_opening.map(|t| t.format("%H:%M").to_string());
+    if next_opening.is_some() {
+        return;
+    }
+
+    let mut started = false;
+    while ctx.downloads.has_capacity() {
+        let Some(job) = ctx.queue.start_next() else {
+            break;
+        };
+        ctx.downloads.submit(job);
+        started = true;
+    }
+
+    if started {
+        let _ = ctx.queue.save();
+        app.set_queue(&ctx.queue);
+    }
+}
+
+/// Apply pending download manager events to the app and queue state.
+fn process_download_events(app: &mut App, ctx: &mut RunContext) {
+    let mut queue_changed = false;
+
+    while let Some(event) = ctx.downloads.try_next_event() {
+        match event {
+            DownloadEvent::WorkerStatus { worker, message } => {
+                app.set_worker_status(worker, &message);
+            }
+            DownloadEvent::Finished { job, outcome } => {
+                ctx.queue.finish(job.id, &outcome);
+                queue_changed = true;
+
+                let entry = match outcome {
+                    DownloadOutcome::Completed(_) => format!("✓ Ep {} complete", job.episode),
+                    DownloadOutcome::Skipped(_) => {
+                        format!("✓ Ep {} skipped (exists)", job.episode)
+                    }
+                    DownloadOutcome::NoSources => format!("✗ Ep {} no sources", job.episode),
+                    DownloadOutcome::Failed(e) => format!("✗ Ep {} failed: {}", job.episode, e),
+                };
+
+                if app.download_batch.contains(&job.id) {
+                    app.add_download_log(&entry);
+                    let done = app.download_current + 1;
+                    app.update_download_progress(done, "Downloading...");
+                }
+            }
+        }
+    }
+
+    if queue_changed {
+        let _ = ctx.queue.save();
+        app.set_queue(&ctx.queue);
+    }
+
+    if app.show_download_modal
+        && app.download_current >= app.download_total
+        && app.download_finished_at.is_none()
+    {
+        let total = app.download_total;
+        app.update_download_progress(total, "Download complete!");
+        app.download_finished_at = Some(Instant::now());
+    }
+
+    // Leave the summary on screen briefly before returning to the episode list
+    if let Some(finished_at) = app.download_finished_at {
+        if finished_at.elapsed() >= Duration::from_secs(2) {
+            app.close_download_modal();
+            app.screen = tui::Screen::EpisodeList;
+        }
+    }
+}
+
+/// Update the history sidebar, history screen and stats from the watch history.
+fn refresh_history(app: &mut App, watch_history: &WatchHistory) {
     let recent = watch_history.get_recent(10);
     let history_records: Vec<(String, String, i64, String)> = recent
         .iter()
@@ -346,374 +953,719 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         })
         .collect();
     app.set_history(history_records);
+    app.set_history_entries(watch_history.records.values().cloned().collect());
+    app.stats = Stats::compute(watch_history, Local::now().date_naive());
+}
 
-    // Main event loop
-    let result = run_app(
-        &mut terminal,
-        &mut app,
-        &mut watch_history,
-        &mode,
-        &quality,
-        download_dir,
-        &player,
-        &player_args,
-    )
-    .await;
+/// Start playing an episode and record it in the watch history.
+///
+/// With `resume`, playback starts from the episode's saved position. When
+/// the player reports its progress the episode is marked watched once
+/// enough of it has played; otherwise it is marked watched right away.
+/// In incognito mode nothing is recorded or synced.
+fn play_episode(
+    app: &mut App,
+    watch_history: &mut WatchHistory,
+    ctx: &mut RunContext,
+    show: &types::Show,
+    episode: i64,
+    url: &str,
+    resume: bool,
+) {
+    let start = resume
+        .then(|| watch_history.get_record(&show.id, &ctx.mode))
+        .flatten()
+        .and_then(|r| r.resume_position(episode));
+
+    match ctx
+        .player
+        .play(url, start, &show.id, &ctx.mode, episode, app.incognito)
+    {
+        Ok(_) if app.incognito => {
+            app.show_playback_menu();
+            app.set_status(&match start {
+                Some(start) => format!(
+                    "Incognito: resuming at {}, this episode won't be recorded",
+                    format_position(start)
+                ),
+                None => "Incognito: this episode won't be recorded".to_string(),
+            });
+            return;
+        }
+        Ok(true) => watch_history.record_play(&show.id, &show.name, episode, &ctx.mode),
+        Ok(false) => {
+            watch_history.update(&show.id, &show.name, episode, &ctx.mode);
+            sync_progress(ctx, watch_history, &show.id, &ctx.mode);
+            let available: Vec<i64> = app.episodes.iter().map(|e| e.number).collect();
+            complete_watchlist(
+                app,
+                ctx,
+                watch_history,
+                &show.id,
+                &ctx.mode.clone(),
+                &available,
+            );
+        }
+        Err(e) => {
+            app.set_error(&format!("Failed to start player: {}", e));
+            app.screen = tui::Screen::EpisodeList;
+            return;
+        }
+    }
+    let _ = watch_history.save();
+    app.set_watched(watch_history.get_record(&show.id, &ctx.mode));
+    refresh_history(app, watch_history);
 
-    // Restore terminal
-    restore_terminal()?;
+    if let Some(start) = start {
+        app.set_status(&format!("Resuming at {}", format_position(start)));
+    }
+    app.show_playback_menu();
+}
 
-    result
+/// Push a show's progress to AniList, if logged in.
+///
+/// The furthest watched episode of the track in `mode` is sent.
+fn sync_progress(ctx: &RunContext, watch_history: &WatchHistory, show_id: &str, mode: &str) {
+    let (Some(sync), Some(record)) = (&ctx.anilist, watch_history.get_record(show_id, mode)) else {
+        return;
+    };
+    if let Some(progress) = record.furthest_watched() {
+        sync.push(show_id, &record.show_name, progress);
+    }
+}
+
+/// Save the score, notes or tags typed for a show.
+fn annotate(app: &mut App, ctx: &mut RunContext, input: &AnnotationInput) {
+    let message = match input.field {
+        AnnotationField::Score => match parse_score(&input.text) {
+            Ok(score) => {
+                ctx.annotations
+                    .update(&input.show_id, &input.show_name, |a| a.score = score);
+                match score {
+                    Some(score) => format!("Scored {} {}/10", input.show_name, score),
+                    None => format!("Cleared the score of {}", input.show_name),
+                }
+            }
+            Err(e) => {
+                app.set_status(&e);
+                return;
+            }
+        },
+        AnnotationField::Notes => {
+            let notes = input.text.trim().to_string();
+            ctx.annotations
+                .update(&input.show_id, &input.show_name, |a| a.notes = notes);
+            format!("Saved notes for {}", input.show_name)
+        }
+        AnnotationField::Tags => {
+            let tags = parse_tags(&input.text);
+            ctx.annotations
+                .update(&input.show_id, &input.show_name, |a| a.tags = tags);
+            format!("Saved tags for {}", input.show_name)
+        }
+    };
+    match ctx.annotations.save() {
+        Ok(()) => app.set_status(&message),
+        Err(e) => app.set_status(&format!("Failed to save scores, notes and tags: {}", e)),
+    }
+    app.set_annotations(&ctx.annotations);
+}
+
+/// Move a show on the watchlist to completed once the last of the
+/// `available` episodes is watched in `mode`.
+fn complete_watchlist(
+    app: &mut App,
+    ctx: &mut RunContext,
+    watch_history: &WatchHistory,
+    show_id: &str,
+    mode: &str,
+    available: &[i64],
+) {
+    let Some(record) = watch_history.get_record(show_id, mode) else {
+        return;
+    };
+    if !ctx.watchlist.complete_if_finished(record, available) {
+        return;
+    }
+    match ctx.watchlist.save() {
+        Ok(()) => app.set_status(&format!("Finished {}; marked completed", record.show_name)),
+        Err(e) => app.set_status(&format!("Failed to save watchlist: {}", e)),
+    }
+    app.set_watchlist(&ctx.watchlist);
+}
+
+/// Show the outcome of background AniList pushes.
+fn process_anilist_messages(app: &mut App, ctx: &mut RunContext) {
+    if let Some(sync) = &mut ctx.anilist {
+        while let Some(message) = sync.try_next_message() {
+            app.set_status(&message);
+        }
+    }
+}
+
+/// Save playback positions reported by the player.
+///
+/// Progress of episodes started in incognito mode is dropped, even if
+/// incognito was turned off while they play.
+fn process_playback_events(app: &mut App, watch_history: &mut WatchHistory, ctx: &mut RunContext) {
+    let mut changed = Vec::new();
+
+    while let Some(event) = ctx.player.try_next_event() {
+        match event {
+            PlaybackEvent::Progress {
+                incognito: true, ..
+            } => {}
+            PlaybackEvent::Progress {
+                show_id,
+                mode,
+                episode,
+                position,
+                duration,
+                incognito: false,
+            } => {
+                if watch_history.record_position(
+                    &show_id,
+                    &mode,
+                    episode,
+                    position,
+                    duration,
+                    ctx.watched_threshold,
+                ) {
+                    debug!("Episode {} of {} ({}) watched", episode, show_id, mode);
+                    sync_progress(ctx, watch_history, &show_id, &mode);
+                    // Only the selected show's episodes are known
+                    let available: Vec<i64> = match &app.selected_show {
+                        Some(show) if show.id == show_id => {
+                            app.episodes.iter().map(|e| e.number).collect()
+                        }
+                        _ => Vec::new(),
+                    };
+                    complete_watchlist(app, ctx, watch_history, &show_id, &mode, &available);
+                }
+                changed.push(record_key(&show_id, &mode));
+            }
+            PlaybackEvent::Closed {
+                show_id,
+                mode,
+                episode,
+            } => {
+                debug!(
+                    "Player closed for episode {} of {} ({})",
+                    episode, show_id, mode
+                );
+            }
+        }
+    }
+
+    if changed.is_empty() {
+        return;
+    }
+    let _ = watch_history.save();
+    refresh_history(app, watch_history);
+    if let Some(show) = &app.selected_show {
+        if changed.contains(&record_key(&show.id, &ctx.mode)) {
+            app.set_watched(watch_history.get_record(&show.id, &ctx.mode));
+        }
+    }
 }
 
 async fn run_app(
     terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
     app: &mut App,
     watch_history: &mut WatchHistory,
-    mode: &str,
-    quality: &str,
-    download_dir: &Path,
-    player: &str,
-    player_args: &[String],
+    ctx: &mut RunContext,
 ) -> Result<(), Box<dyn std::error::Error>> {
     loop {
+        process_download_events(app, ctx);
+        process_playback_events(app, watch_history, ctx);
+        process_anilist_messages(app, ctx);
+        pump_download_queue(app, ctx);
+
         // Draw UI
         terminal.draw(|f| draw(f, app))?;
 
         // Poll for events
-        if let Some(event) = poll_event(Duration::from_millis(100))? {
-            if let Event::Key(key) = event {
-                let action = app.handle_input(key);
-
-                match action {
-                    Action::Quit => break,
-                    Action::Search(ref query) => {
-                        app.set_loading(&format!("Searching for '{}'...", query));
-                        terminal.draw(|f| draw(f, app))?;
-
-                        match search_shows(query, mode).await {
-                            Ok(shows) => {
-                                if shows.is_empty() {
-                                    app.set_error("No results found");
-                                    app.screen = tui::Screen::Search;
-                                } else {
-                                    app.set_shows(shows);
-                                }
-                            }
-                            Err(e) => {
-                                app.set_error(&e.to_string());
+        if let Some(Event::Key(key)) = poll_event(Duration::from_millis(100))? {
+            let action = app.handle_input(key);
+
+            match action {
+                Action::Quit => break,
+                Action::Search(ref query) => {
+                    app.set_loading(&format!("Searching for '{}'...", query));
+                    terminal.draw(|f| draw(f, app))?;
+
+                    match search_shows(query, &ctx.mode).await {
+                        Ok(shows) => {
+                            if shows.is_empty() {
+                                app.set_error("No results found");
                                 app.screen = tui::Screen::Search;
+                            } else {
+                                app.set_shows(shows);
                             }
                         }
+                        Err(e) => {
+                            app.set_error(&e.to_string());
+                            app.screen = tui::Screen::Search;
+                        }
                     }
-                    Action::SelectShow(i) => {
-                        if i < app.shows.len() {
-                            let show = app.shows[i].clone();
-                            app.selected_show = Some(show.clone());
-                            app.set_loading(&format!("Loading episodes for {}...", show.name));
-                            terminal.draw(|f| draw(f, app))?;
+                }
+                Action::SelectShow(i) => {
+                    if i < app.shows.len() {
+                        let show = app.shows[i].clone();
+                        app.selected_show = Some(show.clone());
+                        app.set_loading(&format!("Loading episodes for {}...", show.name));
+                        terminal.draw(|f| draw(f, app))?;
 
-                            match fetch_episodes(&show.id, mode).await {
-                                Ok(mut episodes) => {
-                                    episodes.sort_by_key(|e| e.number);
-                                    app.set_episodes(episodes);
-                                }
-                                Err(e) => {
-                                    app.set_error(&e.to_string());
-                                    app.screen = tui::Screen::ShowList;
-                                }
+                        match fetch_episodes(&show.id, &ctx.mode).await {
+                            Ok(mut episodes) => {
+                                episodes.sort_by_key(|e| e.number);
+                                app.set_episodes(episodes);
+                                app.set_watched(watch_history.get_record(&show.id, &ctx.mode));
+                            }
+                            Err(e) => {
+                                app.set_error(&e.to_string());
+                                app.screen = tui::Screen::ShowList;
                             }
                         }
                     }
-                    Action::SelectEpisode(i) => {
-                        if i < app.episodes.len() {
-                            let episode = app.episodes[i].clone();
-                            app.current_episode = Some(episode.clone());
+                }
+                Action::SelectEpisode(i) => {
+                    if i < app.episodes.len() {
+                        let episode = app.episodes[i].clone();
+                        app.current_episode = Some(episode.clone());
+
+                        if app.download_mode {
+                            app.show_batch_menu();
+                        } else {
+                            // Fetch sources and play
+                            if let Some(show) = app.selected_show.clone() {
+                                app.set_loading("Fetching stream sources...");
+                                terminal.draw(|f| draw(f, app))?;
 
-                            if app.download_mode {
-                                app.show_batch_menu();
-                            } else {
-                                // Fetch sources and play
-                                if let Some(show) = app.selected_show.clone() {
-                                    app.set_loading("Fetching stream sources...");
-                                    terminal.draw(|f| draw(f, app))?;
-
-                                    let episode_str = episode.number.to_string();
-                                    match fetch_stream_sources(&show.id, mode, &episode_str).await {
-                                        Ok(sources) => {
-                                            if sources.is_empty() {
-                                                app.set_error("No sources found");
-                                                app.screen = tui::Screen::EpisodeList;
-                                            } else {
-                                                // Auto-select quality and play
-                                                match choose_stream(&sources, quality) {
-                                                    Ok(source) => {
-                                                        app.selected_source = Some(source.clone());
-
-                                                        // Save history
-                                                        watch_history.update(
-                                                            &show.id,
-                                                            &show.name,
-                                                            episode.number,
-                                                            mode,
-                                                        );
-                                                        let _ = watch_history.save();
-
-                                                        // Spawn player
-                                                        let mut cmd = Command::new("setsid");
-                                                        cmd.arg(player);
-                                                        for arg in player_args {
-                                                            cmd.arg(arg);
-                                                        }
-                                                        cmd.arg(&source.url);
-
-                                                        debug!("Playing: {}", source.url);
-
-                                                        let _ = cmd
-                                                            .stdin(Stdio::null())
-                                                            .stdout(Stdio::null())
-                                                            .stderr(Stdio::null())
-                                                            .spawn();
-
-                                                        app.show_playback_menu();
-                                                    }
-                                                    Err(e) => {
-                                                        app.set_error(&e.to_string());
-                                                        app.screen = tui::Screen::EpisodeList;
-                                                    }
+                                let episode_str = episode.number.to_string();
+                                match fetch_stream_sources(&show.id, &ctx.mode, &episode_str).await
+                                {
+                                    Ok(sources) => {
+                                        if sources.is_empty() {
+                                            app.set_error("No sources found");
+                                            app.screen = tui::Screen::EpisodeList;
+                                        } else {
+                                            // Auto-select quality and play
+                                            match choose_stream(&sources, &ctx.quality) {
+                                                Ok(source) => {
+                                                    app.selected_source = Some(source.clone());
+                                                    play_episode(
+                                                        app,
+                                                        watch_history,
+                                                        ctx,
+                                                        &show,
+                                                        episode.number,
+                                                        &source.url,
+                                                        true,
+                                                    );
+                                                }
+                                                Err(e) => {
+                                                    app.set_error(&e.to_string());
+                                                    app.screen = tui::Screen::EpisodeList;
                                                 }
                                             }
                                         }
-                                        Err(e) => {
-                                            app.set_error(&e.to_string());
-                                            app.screen = tui::Screen::EpisodeList;
-                                        }
+                                    }
+                                    Err(e) => {
+                                        app.set_error(&e.to_string());
+                                        app.screen = tui::Screen::EpisodeList;
                                     }
                                 }
                             }
                         }
                     }
-                    Action::SelectQuality(i) => {
-                        if i < app.sources.len() {
-                            app.selected_source = Some(app.sources[i].clone());
-                            // This would be used for manual quality selection
-                        }
+                }
+                Action::SelectQuality(i) => {
+                    if i < app.sources.len() {
+                        app.selected_source = Some(app.sources[i].clone());
+                        // This would be used for manual quality selection
                     }
-                    Action::Next | Action::Previous | Action::Replay => {
-                        if let Some(current_ep) = &app.current_episode {
-                            let current_idx = app
-                                .episodes
-                                .iter()
-                                .position(|e| e.number == current_ep.number);
-
-                            let new_episode = match action {
-                                Action::Next => {
-                                    current_idx.and_then(|i| app.episodes.get(i + 1).cloned())
+                }
+                Action::Next | Action::Previous | Action::Replay => {
+                    if let Some(current_ep) = &app.current_episode {
+                        let current_idx = app
+                            .episodes
+                            .iter()
+                            .position(|e| e.number == current_ep.number);
+
+                        let new_episode = match action {
+                            Action::Next => {
+                                current_idx.and_then(|i| app.episodes.get(i + 1).cloned())
+                            }
+                            Action::Previous => current_idx.and_then(|i| {
+                                if i > 0 {
+                                    app.episodes.get(i - 1).cloned()
+                                } else {
+                                    None
                                 }
-                                Action::Previous => current_idx.and_then(|i| {
-                                    if i > 0 {
-                                        app.episodes.get(i - 1).cloned()
-                                    } else {
-                                        None
-                                    }
-                                }),
-                                Action::Replay => Some(current_ep.clone()),
-                                _ => None,
-                            };
-
-                            if let Some(episode) = new_episode {
-                                app.current_episode = Some(episode.clone());
-
-                                if let Some(show) = app.selected_show.clone() {
-                                    app.set_loading("Fetching stream sources...");
-                                    terminal.draw(|f| draw(f, app))?;
-
-                                    let episode_str = episode.number.to_string();
-                                    match fetch_stream_sources(&show.id, mode, &episode_str).await {
-                                        Ok(sources) => {
-                                            if let Ok(source) = choose_stream(&sources, quality) {
-                                                // Save history
-                                                watch_history.update(
-                                                    &show.id,
-                                                    &show.name,
-                                                    episode.number,
-                                                    mode,
-                                                );
-                                                let _ = watch_history.save();
-
-                                                // Spawn player
-                                                let mut cmd = Command::new("setsid");
-                                                cmd.arg(player);
-                                                for arg in player_args {
-                                                    cmd.arg(arg);
-                                                }
-                                                cmd.arg(&source.url);
+                            }),
+                            Action::Replay => Some(current_ep.clone()),
+                            _ => None,
+                        };
 
-                                                let _ = cmd
-                                                    .stdin(Stdio::null())
-                                                    .stdout(Stdio::null())
-                                                    .stderr(Stdio::null())
-                                                    .spawn();
+                        if let Some(episode) = new_episode {
+                            app.current_episode = Some(episode.clone());
 
-                                                app.show_playback_menu();
-                                            }
-                                        }
-                                        Err(e) => {
-                                            app.set_error(&e.to_string());
+                            if let Some(show) = app.selected_show.clone() {
+                                app.set_loading("Fetching stream sources...");
+                                terminal.draw(|f| draw(f, app))?;
+
+                                let episode_str = episode.number.to_string();
+                                match fetch_stream_sources(&show.id, &ctx.mode, &episode_str).await
+                                {
+                                    Ok(sources) => {
+                                        if let Ok(source) = choose_stream(&sources, &ctx.quality) {
+                                            // Replay starts over; next/previous resume
+                                            let resume = !matches!(action, Action::Replay);
+                                            play_episode(
+                                                app,
+                                                watch_history,
+                                                ctx,
+                                                &show,
+                                                episode.number,
+                                                &source.url,
+                                                resume,
+                                            );
                                         }
                                     }
+                                    Err(e) => {
+                                        app.set_error(&e.to_string());
+                                    }
                                 }
                             }
                         }
                     }
-                    Action::BackToEpisodes => {
-                        app.screen = tui::Screen::EpisodeList;
-                    }
-                    Action::ContinueFromHistory(i) => {
-                        if i < app.history_records.len() {
-                            let (show_id, show_name, episode_num, record_mode) =
-                                app.history_records[i].clone();
+                }
+                Action::BackToEpisodes => {
+                    app.screen = tui::Screen::EpisodeList;
+                }
+                Action::ContinueFromHistory(ref show_id, ref mode) => {
+                    if let Some(record) = watch_history.get_record(show_id, mode) {
+                        let (show_id, show_name, episode_num, record_mode) = (
+                            record.show_id.clone(),
+                            record.show_name.clone(),
+                            record.episode,
+                            record.mode.clone(),
+                        );
+
+                        app.set_loading(&format!("Loading {}...", show_name));
+                        terminal.draw(|f| draw(f, app))?;
 
-                            app.set_loading(&format!("Loading {}...", show_name));
-                            terminal.draw(|f| draw(f, app))?;
+                        match fetch_episodes(&show_id, &record_mode).await {
+                            Ok(mut episodes) => {
+                                episodes.sort_by_key(|e| e.number);
 
-                            match fetch_episodes(&show_id, &record_mode).await {
-                                Ok(mut episodes) => {
-                                    episodes.sort_by_key(|e| e.number);
-
-                                    // Find episode to resume
-                                    let resume_ep = episodes
-                                        .iter()
-                                        .find(|e| e.number == episode_num + 1)
-                                        .or_else(|| {
-                                            episodes.iter().find(|e| e.number == episode_num)
-                                        })
-                                        .cloned()
-                                        .unwrap_or_else(|| episodes[0].clone());
-
-                                    app.selected_show = Some(types::Show {
-                                        id: show_id,
-                                        name: show_name,
-                                        available_episodes: episodes.len() as i64,
-                                    });
-                                    app.set_episodes(episodes);
-
-                                    // Select the resume episode
-                                    let idx = app
-                                        .episodes
-                                        .iter()
-                                        .position(|e| e.number == resume_ep.number)
-                                        .unwrap_or(0);
-                                    app.episode_list_state.select(Some(idx));
-                                }
-                                Err(e) => {
-                                    app.set_error(&e.to_string());
-                                    app.screen = tui::Screen::Startup;
+                                // Continue in the record's mode; dual-audio
+                                // downloads cover both
+                                if ctx.mode != "dual" {
+                                    ctx.mode = record_mode.clone();
+                                    app.mode = record_mode.clone();
                                 }
+
+                                // Resume at the first unwatched episode after the
+                                // furthest one, or the last played if caught up
+                                let record = watch_history.get_record(&show_id, &record_mode);
+                                let numbers: Vec<i64> = episodes.iter().map(|e| e.number).collect();
+                                let resume_number = record
+                                    .and_then(|r| r.resume_episode(&numbers))
+                                    .unwrap_or(episode_num);
+                                app.set_watched(record);
+
+                                app.selected_show = Some(types::Show {
+                                    id: show_id,
+                                    name: show_name,
+                                    english_name: None,
+                                    native_name: None,
+                                    available_episodes: episodes.len() as i64,
+                                });
+                                app.set_episodes(episodes);
+
+                                // Select the resume episode
+                                let idx = app
+                                    .episodes
+                                    .iter()
+                                    .position(|e| e.number == resume_number)
+                                    .unwrap_or(0);
+                                app.episode_list_state.select(Some(idx));
+                            }
+                            Err(e) => {
+                                app.set_error(&e.to_string());
+                                app.screen = tui::Screen::Startup;
                             }
                         }
                     }
-                    Action::NewSearch => {
-                        app.screen = tui::Screen::Search;
-                    }
-                    Action::BatchAll | Action::BatchSingle | Action::BatchRange(_, _) => {
-                        let show = app.selected_show.clone();
-                        let current_ep = app.current_episode.clone();
-                        if let (Some(show), Some(_)) = (show, current_ep) {
-                            let episodes_to_download: Vec<_> = match &action {
-                                Action::BatchAll => app.episodes.clone(),
-                                Action::BatchRange(start, end) => app
-                                    .episodes
-                                    .iter()
-                                    .filter(|e| e.number >= *start && e.number <= *end)
-                                    .cloned()
-                                    .collect(),
-                                Action::BatchSingle => {
-                                    vec![app.current_episode.clone().unwrap()]
-                                }
-                                _ => vec![],
-                            };
-
-                            // Perform batch download with modal
-                            let total = episodes_to_download.len();
-                            app.start_download_modal(total);
-                            terminal.draw(|f| draw(f, app))?;
-
-                            for (idx, episode) in episodes_to_download.iter().enumerate() {
-                                let output_path =
-                                    get_output_path(download_dir, &show.name, episode.number, mode);
+                }
+                Action::NewSearch => {
+                    app.screen = tui::Screen::Search;
+                }
+                Action::BatchAll | Action::BatchSingle | Action::BatchRange(_, _) => {
+                    let show = app.selected_show.clone();
+                    let current_ep = app.current_episode.clone();
+                    if let (Some(show), Some(_)) = (show, current_ep) {
+                        let episodes_to_download: Vec<_> = match &action {
+                            Action::BatchAll => app.episodes.clone(),
+                            Action::BatchRange(start, end) => app
+                                .episodes
+                                .iter()
+                                .filter(|e| e.number >= *start && e.number <= *end)
+                                .cloned()
+                                .collect(),
+                            Action::BatchSingle => {
+                                vec![app.current_episode.clone().unwrap()]
+                            }
+                            _ => vec![],
+                        };
 
-                                if output_path.exists() {
-                                    app.update_download_progress(
-                                        idx + 1,
-                                        &format!("Skipping Episode {} (exists)", episode.number),
-                                    );
-                                    app.add_download_log(&format!(
-                                        "✓ Ep {} skipped (exists)",
-                                        episode.number
+                        // Make sure the batch fits on disk and within the quota
+                        let numbers: Vec<i64> =
+                            episodes_to_download.iter().map(|e| e.number).collect();
+                        app.set_loading("Checking disk space...");
+                        terminal.draw(|f| draw(f, app))?;
+                        let estimate =
+                            estimate_batch(&show.id, &ctx.mode, &ctx.quality, &numbers).await;
+                        app.screen = tui::Screen::EpisodeList;
+                        // Downloads are shared, so every profile must have
+                        // watched an episode before it is cleaned up
+                        let others = WatchHistory::load_other_profiles();
+                        let histories: Vec<&WatchHistory> =
+                            std::iter::once(&*watch_history).chain(&others).collect();
+                        let fits = match check_space(
+                            &ctx.download_dir,
+                            &ctx.filename_template,
+                            estimate.unwrap_or(0),
+                            ctx.quota,
+                            &mut ctx.queue,
+                            &histories,
+                        ) {
+                            Ok(report) => {
+                                let mut notes = Vec::new();
+                                if !report.deleted.is_empty() {
+                                    notes.push(format!(
+                                        "Freed {} by deleting {} watched episode(s)",
+                                        format_size(report.freed),
+                                        report.deleted.len()
                                     ));
-                                    terminal.draw(|f| draw(f, app))?;
-                                    continue;
                                 }
-
-                                app.update_download_progress(
-                                    idx + 1,
-                                    &format!("Downloading Episode {}...", episode.number),
-                                );
-                                terminal.draw(|f| draw(f, app))?;
-
-                                let episode_str = episode.number.to_string();
-                                match fetch_stream_sources(&show.id, mode, &episode_str).await {
-                                    Ok(sources) if !sources.is_empty() => {
-                                        if let Ok(source) = choose_stream(&sources, quality) {
-                                            match download_file(&source.url, &output_path).await {
-                                                Ok(()) => {
-                                                    watch_history.update(
-                                                        &show.id,
-                                                        &show.name,
-                                                        episode.number,
-                                                        mode,
-                                                    );
-                                                    let _ = watch_history.save();
-                                                    app.add_download_log(&format!(
-                                                        "✓ Ep {} complete",
-                                                        episode.number
-                                                    ));
-                                                }
-                                                Err(e) => {
-                                                    app.add_download_log(&format!(
-                                                        "✗ Ep {} failed: {}",
-                                                        episode.number, e
-                                                    ));
-                                                    terminal.draw(|f| draw(f, app))?;
-                                                }
-                                            }
-                                        }
-                                    }
-                                    _ => {
-                                        app.add_download_log(&format!(
-                                            "✗ Ep {} no sources",
-                                            episode.number
-                                        ));
-                                        terminal.draw(|f| draw(f, app))?;
-                                    }
+                                if estimate.is_none() {
+                                    notes.push(
+                                        "Size unknown: free space and quota weren't checked \
+                                         for this batch"
+                                            .to_string(),
+                                    );
+                                }
+                                if !notes.is_empty() {
+                                    app.set_status(&notes.join("; "));
                                 }
+                                true
                             }
+                            Err(e) => {
+                                app.set_status(&format!("Download cancelled: {}", e));
+                                false
+                            }
+                        };
 
-                            app.update_download_progress(total, "Download complete!");
-                            terminal.draw(|f| draw(f, app))?;
-                            tokio::time::sleep(Duration::from_secs(2)).await;
-                            app.close_download_modal();
-                            app.screen = tui::Screen::EpisodeList;
+                        if fits {
+                            let batch: Vec<u64> = episodes_to_download
+                                .iter()
+                                .map(|episode| {
+                                    ctx.queue.enqueue(
+                                        &show,
+                                        episode.number,
+                                        &ctx.mode,
+                                        &ctx.quality,
+                                        &ctx.download_dir,
+                                    )
+                                })
+                                .collect();
+                            let _ = ctx.queue.save();
+                            app.set_queue(&ctx.queue);
+                            app.start_download_modal(batch, ctx.downloads.workers());
+                        }
+                    }
+                }
+                Action::QueueTogglePause => {
+                    ctx.queue.paused = !ctx.queue.paused;
+                    let _ = ctx.queue.save();
+                    app.set_queue(&ctx.queue);
+                }
+                Action::QueueMove(id, up) => {
+                    ctx.queue.move_item(id, up);
+                    let _ = ctx.queue.save();
+                    app.set_queue(&ctx.queue);
+                }
+                Action::QueueRetryFailed => {
+                    ctx.queue.retry_failed();
+                    let _ = ctx.queue.save();
+                    app.set_queue(&ctx.queue);
+                }
+                Action::QueueRemove(id) => {
+                    ctx.queue.remove(id);
+                    let _ = ctx.queue.save();
+                    app.set_queue(&ctx.queue);
+                }
+                Action::QueueClearCompleted => {
+                    ctx.queue.clear_completed();
+                    let _ = ctx.queue.save();
+                    app.set_queue(&ctx.queue);
+                }
+                Action::ToggleFollow => {
+                    if let Some(show) = app.selected_show.clone() {
+                        if ctx.follows.is_following(&show.id) {
+                            ctx.follows.unfollow(&show.id);
+                            app.set_status(&format!("Unfollowed {}", show.name));
+                        } else {
+                            let known: Vec<i64> = app.episodes.iter().map(|e| e.number).collect();
+                            ctx.follows.follow(&show, &ctx.mode, &ctx.quality, &known);
+                            app.set_status(&format!(
+                                "Following {}; new episodes download on sync",
+                                show.name
+                            ));
+                        }
+                        if let Err(e) = ctx.follows.save() {
+                            app.set_error(&format!("Failed to save follows: {}", e));
+                        }
+                        app.set_follows(&ctx.follows);
+                    }
+                }
+                Action::EditWatchlist(ref edit) => {
+                    let name = match edit {
+                        WatchlistEdit::Add(show) => show.name.clone(),
+                        WatchlistEdit::SetStatus(show_id, _)
+                        | WatchlistEdit::SetPriority(show_id, _)
+                        | WatchlistEdit::Remove(show_id) => ctx
+                            .watchlist
+                            .get(show_id)
+                            .map(|e| e.show_name.clone())
+                            .unwrap_or_default(),
+                    };
+                    let message = match edit {
+                        WatchlistEdit::Add(show) => {
+                            if ctx.watchlist.add(show, WatchStatus::Planned) {
+                                format!("Added {} to the watchlist", name)
+                            } else {
+                                format!(
+                                    "{} is already on the watchlist ({})",
+                                    name,
+                                    ctx.watchlist.get(&show.id).map_or("", |e| e.status.label())
+                                )
+                            }
+                        }
+                        WatchlistEdit::SetStatus(show_id, status) => {
+                            ctx.watchlist.update(show_id, |e| e.status = *status);
+                            format!("{}: {}", name, status.label())
+                        }
+                        WatchlistEdit::SetPriority(show_id, priority) => {
+                            ctx.watchlist.update(show_id, |e| e.priority = *priority);
+                            format!("{}: {} priority", name, priority.label())
+                        }
+                        WatchlistEdit::Remove(show_id) => {
+                            ctx.watchlist.remove(show_id);
+                            format!("Removed {} from the watchlist", name)
+                        }
+                    };
+                    match ctx.watchlist.save() {
+                        Ok(()) => app.set_status(&message),
+                        Err(e) => app.set_status(&format!("Failed to save watchlist: {}", e)),
+                    }
+                    app.set_watchlist(&ctx.watchlist);
+                }
+                Action::Annotate(ref input) => annotate(app, ctx, input),
+                Action::OpenShow(ref show) => {
+                    app.selected_show = Some(show.clone());
+                    app.set_loading(&format!("Loading episodes for {}...", show.name));
+                    terminal.draw(|f| draw(f, app))?;
+
+                    match fetch_episodes(&show.id, &ctx.mode).await {
+                        Ok(mut episodes) => {
+                            episodes.sort_by_key(|e| e.number);
+                            if let Some(selected) = &mut app.selected_show {
+                                selected.available_episodes = episodes.len() as i64;
+                            }
+                            app.set_episodes(episodes);
+                            app.set_watched(watch_history.get_record(&show.id, &ctx.mode));
+                        }
+                        Err(e) => {
+                            app.selected_show = None;
+                            app.set_error(&e.to_string());
+                            app.screen = tui::Screen::Watchlist;
                         }
                     }
-                    Action::Stream | Action::Download | Action::None => {}
                 }
+                Action::EditHistory(_) if app.incognito => {
+                    app.set_status("Turn off incognito mode to edit the history");
+                }
+                Action::EditHistory(ref edit) => {
+                    let name = match edit {
+                        HistoryEdit::Delete(show_id, mode)
+                        | HistoryEdit::SetEpisode(show_id, mode, _)
+                        | HistoryEdit::MarkFinished(show_id, mode) => watch_history
+                            .get_record(show_id, mode)
+                            .map(|r| format!("{} ({})", r.show_name, mode))
+                            .unwrap_or_default(),
+                        HistoryEdit::Clear => String::new(),
+                    };
+                    let message = match edit {
+                        HistoryEdit::Delete(show_id, mode) => {
+                            watch_history.remove(show_id, mode);
+                            format!("Removed {} from the history", name)
+                        }
+                        HistoryEdit::SetEpisode(show_id, mode, episode) => {
+                            watch_history.set_episode(show_id, mode, *episode);
+                            sync_progress(ctx, watch_history, show_id, mode);
+                            format!("{} set to episode {}", name, episode)
+                        }
+                        HistoryEdit::MarkFinished(show_id, mode) => {
+                            app.set_loading("Loading episodes...");
+                            terminal.draw(|f| draw(f, app))?;
+                            app.screen = tui::Screen::History;
+                            match fetch_episodes(show_id, mode).await {
+                                Ok(episodes) => {
+                                    let numbers: Vec<i64> =
+                                        episodes.iter().map(|e| e.number).collect();
+                                    watch_history.mark_finished(show_id, mode, &numbers);
+                                    sync_progress(ctx, watch_history, show_id, mode);
+                                    complete_watchlist(
+                                        app,
+                                        ctx,
+                                        watch_history,
+                                        show_id,
+                                        mode,
+                                        &numbers,
+                                    );
+                                    format!("Marked {} watched", name)
+                                }
+                                Err(e) => format!("Failed to load episodes: {}", e),
+                            }
+                        }
+                        HistoryEdit::Clear if watch_history.is_empty() => {
+                            "The watch history is already empty".to_string()
+                        }
+                        HistoryEdit::Clear => {
+                            watch_history.clear();
+                            "Cleared the watch history".to_string()
+                        }
+                    };
 
-                // Clear error after any input
-                if !matches!(action, Action::None) {
-                    app.clear_error();
+                    match watch_history.save() {
+                        Ok(()) => app.set_status(&message),
+                        Err(e) => app.set_status(&format!("Failed to save history: {}", e)),
+                    }
+                    refresh_history(app, watch_history);
+                    if let Some(show) = &app.selected_show {
+                        app.set_watched(watch_history.get_record(&show.id, &ctx.mode));
+                    }
                 }
+                Action::Stream | Action::Download | Action::None => {}
+            }
+
+            // Clear error after any input
+            if !matches!(action, Action::None) {
+                app.clear_error();
             }
         }
 
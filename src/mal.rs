@@ -0,0 +1,478 @@
+//! MyAnimeList XML import and export.
+//!
+//! MAL exports a user's list as an `animelist` XML file and can import the
+//! same format, which lets history move between MAL and anime-watcher
+//! without an API key. MAL identifies shows by its own IDs; those learned
+//! on import are kept in `mal.json` so exports can refer to them.
+
+use crate::annotations::{Annotation, MAX_SCORE, MIN_SCORE};
+use crate::history::WatchHistory;
+use crate::paths;
+use crate::persist;
+use crate::types::Show;
+use chrono::{DateTime, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::Write;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// A show on a MAL anime list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MalEntry {
+    /// MyAnimeList ID (0 if unknown).
+    pub mal_id: i64,
+    /// Title of the show.
+    pub title: String,
+    /// Number of episodes, if known.
+    pub episodes: Option<i64>,
+    /// Episodes watched.
+    pub watched: i64,
+    /// List status, such as `Watching`, `Completed` or `Plan to Watch`.
+    pub status: String,
+    /// Date the show was finished (`YYYY-MM-DD`), if recorded.
+    pub finished: Option<String>,
+    /// Score from 1 to 10, if scored.
+    pub score: Option<u8>,
+    /// Free-form comments.
+    pub comments: String,
+    /// Custom tags.
+    pub tags: Vec<String>,
+}
+
+impl MalEntry {
+    /// Episodes to mark watched: all of them for a completed show that
+    /// doesn't record a count.
+    pub fn progress(&self) -> i64 {
+        if self.watched == 0 && self.status == "Completed" {
+            self.episodes.unwrap_or(0)
+        } else {
+            self.watched
+        }
+    }
+
+    /// Unix timestamp of noon local time on the day the show was finished,
+    /// if MAL recorded a full date.
+    pub fn finished_at(&self) -> Option<u64> {
+        let day = NaiveDate::parse_from_str(self.finished.as_deref()?, "%Y-%m-%d").ok()?;
+        let noon = day
+            .and_hms_opt(12, 0, 0)?
+            .and_local_timezone(Local)
+            .earliest()?;
+        u64::try_from(noon.timestamp()).ok()
+    }
+}
+
+/// Parse a MAL `animelist` XML export.
+pub fn parse(xml: &str) -> Result<Vec<MalEntry>, Box<dyn Error>> {
+    let doc = roxmltree::Document::parse(xml)?;
+    let root = doc.root_element();
+    if !root.has_tag_name("myanimelist") {
+        return Err("not a MyAnimeList export (expected <myanimelist>)".into());
+    }
+
+    let mut entries = Vec::new();
+    for anime in root.children().filter(|n| n.has_tag_name("anime")) {
+        let field = |name: &str| {
+            anime
+                .children()
+                .find(|n| n.has_tag_name(name))
+                .and_then(|n| n.text())
+                .map(str::trim)
+                .unwrap_or_default()
+        };
+        let number = |name: &str| field(name).parse::<i64>().ok();
+
+        let title = field("series_title").to_string();
+        if title.is_empty() {
+            continue;
+        }
+        entries.push(MalEntry {
+            mal_id: number("series_animedb_id").unwrap_or(0),
+            title,
+            // MAL writes 0 for shows whose length isn't known yet
+            episodes: number("series_episodes").filter(|n| *n > 0),
+            watched: number("my_watched_episodes").unwrap_or(0),
+            status: field("my_status").to_string(),
+            finished: Some(field("my_finish_date"))
+                .filter(|d| !d.is_empty() && !d.starts_with("0000"))
+                .map(str::to_string),
+            // MAL writes 0 for shows that aren't scored
+            score: field("my_score")
+                .parse::<u8>()
+                .ok()
+                .filter(|s| (MIN_SCORE..=MAX_SCORE).contains(s)),
+            comments: field("my_comments").to_string(),
+            tags: field("my_tags")
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Wrap text in a CDATA section.
+fn cdata(text: &str) -> String {
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+/// Write entries as a MAL `animelist` XML file that MAL can import.
+pub fn export(entries: &[MalEntry]) -> String {
+    let count = |status: &str| entries.iter().filter(|e| e.status == status).count();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" ?>\n<myanimelist>\n");
+    let _ = write!(
+        xml,
+        "  <myinfo>\n    <user_export_type>1</user_export_type>\n    \
+         <user_total_anime>{}</user_total_anime>\n    \
+         <user_total_watching>{}</user_total_watching>\n    \
+         <user_total_completed>{}</user_total_completed>\n  </myinfo>\n",
+        entries.len(),
+        count("Watching"),
+        count("Completed")
+    );
+
+    for entry in entries {
+        let _ = write!(
+            xml,
+            "  <anime>\n    <series_animedb_id>{}</series_animedb_id>\n    \
+             <series_title>{}</series_title>\n    \
+             <series_episodes>{}</series_episodes>\n    \
+             <my_watched_episodes>{}</my_watched_episodes>\n    \
+             <my_start_date>0000-00-00</my_start_date>\n    \
+             <my_finish_date>{}</my_finish_date>\n    \
+             <my_score>{}</my_score>\n    \
+             <my_status>{}</my_status>\n    \
+             <my_comments>{}</my_comments>\n    \
+             <my_tags>{}</my_tags>\n    \
+             <my_times_watched>0</my_times_watched>\n    \
+             <update_on_import>1</update_on_import>\n  </anime>\n",
+            entry.mal_id,
+            cdata(&entry.title),
+            entry.episodes.unwrap_or(0),
+            entry.watched,
+            entry.finished.as_deref().unwrap_or("0000-00-00"),
+            entry.score.unwrap_or(0),
+            entry.status,
+            cdata(&entry.comments),
+            cdata(&entry.tags.join(", "))
+        );
+    }
+    xml.push_str("</myanimelist>\n");
+    xml
+}
+
+/// How an imported title matched the AllAnime search results.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TitleMatch {
+    /// Exactly one show has the title.
+    Exact(Show),
+    /// Several shows could be meant; the user has to choose.
+    Ambiguous(Vec<Show>),
+    /// Nothing was found.
+    NotFound,
+}
+
+/// Match a MAL title against AllAnime search results.
+///
+/// A single show with the same name or English title (ignoring case and
+/// punctuation) is a match. Otherwise every result is a candidate.
+pub fn match_title(title: &str, results: Vec<Show>) -> TitleMatch {
+    let same = |show: &Show| {
+        crate::anilist::same_title(&show.name, title)
+            || show
+                .english_name
+                .as_deref()
+                .is_some_and(|e| crate::anilist::same_title(e, title))
+    };
+    let exact: Vec<&Show> = results.iter().filter(|s| same(s)).collect();
+    match exact.as_slice() {
+        [show] => TitleMatch::Exact((*show).clone()),
+        _ if results.is_empty() => TitleMatch::NotFound,
+        _ => TitleMatch::Ambiguous(results),
+    }
+}
+
+/// MAL details of a show.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MalShow {
+    /// MyAnimeList ID.
+    pub mal_id: i64,
+    /// Number of episodes, if known.
+    #[serde(default)]
+    pub episodes: Option<i64>,
+}
+
+/// MyAnimeList IDs of AllAnime shows.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MalIds {
+    /// Map of show_id to MAL details.
+    pub shows: HashMap<String, MalShow>,
+}
+
+impl MalIds {
+    /// Create an empty set of IDs.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the path to the ID file, next to the watch history.
+    pub fn get_path() -> Result<PathBuf, io::Error> {
+        Ok(paths::data_dir()?.join("mal.json"))
+    }
+
+    /// Load the IDs from disk.
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        Self::load_from(&Self::get_path()?)
+    }
+
+    /// Load the IDs from a specific file.
+    pub fn load_from(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Ok(persist::load_json(path)?.unwrap_or_else(Self::new))
+    }
+
+    /// Save the IDs to disk.
+    ///
+    /// IDs saved by other running instances since these were loaded are
+    /// merged in rather than overwritten.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        self.save_to(&Self::get_path()?)
+    }
+
+    /// Save the IDs to a specific file, merging with its contents.
+    pub fn save_to(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        persist::save_json(path, self, Self::merge)
+    }
+
+    /// Merge other IDs into these; the other's win for shows in both.
+    pub fn merge(&mut self, other: &MalIds) {
+        self.shows.extend(
+            other
+                .shows
+                .iter()
+                .map(|(id, show)| (id.clone(), show.clone())),
+        );
+    }
+
+    /// The show with a MAL ID.
+    pub fn show_for(&self, mal_id: i64) -> Option<&str> {
+        self.shows
+            .iter()
+            .find(|(_, s)| s.mal_id == mal_id)
+            .map(|(show_id, _)| show_id.as_str())
+    }
+}
+
+/// A show of the watch history to export.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportShow {
+    /// AllAnime show ID.
+    pub show_id: String,
+    /// Display name.
+    pub name: String,
+    /// Furthest watched episode over the sub and dub tracks.
+    pub progress: i64,
+    /// Unix timestamp the show was last watched.
+    pub timestamp: u64,
+}
+
+/// Shows of the history with watched episodes, by name.
+pub fn export_shows(history: &WatchHistory) -> Vec<ExportShow> {
+    let mut shows: BTreeMap<&str, ExportShow> = BTreeMap::new();
+    for record in history.records.values() {
+        let Some(progress) = record.furthest_watched().filter(|p| *p > 0) else {
+            continue;
+        };
+        let show = shows
+            .entry(record.show_id.as_str())
+            .or_insert_with(|| ExportShow {
+                show_id: record.show_id.clone(),
+                name: record.show_name.clone(),
+                progress: 0,
+                timestamp: 0,
+            });
+        show.progress = show.progress.max(progress);
+        show.timestamp = show.timestamp.max(record.timestamp);
+    }
+    let mut shows: Vec<ExportShow> = shows.into_values().collect();
+    shows.sort_by_key(|show| show.name.to_lowercase());
+    shows
+}
+
+/// MAL entry for an exported show, with its score, notes and tags.
+pub fn to_entry(
+    show: &ExportShow,
+    mal: Option<&MalShow>,
+    annotation: Option<&Annotation>,
+) -> MalEntry {
+    let episodes = mal.and_then(|m| m.episodes);
+    let completed = episodes.is_some_and(|total| show.progress >= total);
+    let finished = completed.then(|| {
+        DateTime::<Local>::from(UNIX_EPOCH + Duration::from_secs(show.timestamp))
+            .format("%Y-%m-%d")
+            .to_string()
+    });
+    MalEntry {
+        mal_id: mal.map(|m| m.mal_id).unwrap_or(0),
+        title: show.name.clone(),
+        episodes,
+        watched: show.progress,
+        status: if completed { "Completed" } else { "Watching" }.to_string(),
+        finished,
+        score: annotation.and_then(|a| a.score),
+        comments: annotation.map(|a| a.notes.clone()).unwrap_or_default(),
+        tags: annotation
+            .map(|a| a.tags.iter().cloned().collect())
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORT: &str = r#"<?xml version="1.0" encoding="UTF-8" ?>
+<myanimelist>
+  <myinfo><user_export_type>1</user_export_type></myinfo>
+  <anime>
+    <series_animedb_id>5114</series_animedb_id>
+    <series_title><![CDATA[Fullmetal Alchemist: Brotherhood]]></series_title>
+    <series_type>TV</series_type>
+    <series_episodes>64</series_episodes>
+    <my_watched_episodes>0</my_watched_episodes>
+    <my_status>Completed</my_status>
+    <my_finish_date>2024-03-22</my_finish_date>
+  </anime>
+  <anime>
+    <series_animedb_id>52991</series_animedb_id>
+    <series_title><![CDATA[Sousou no Frieren]]></series_title>
+    <series_episodes>28</series_episodes>
+    <my_watched_episodes>10</my_watched_episodes>
+    <my_score>9</my_score>
+    <my_status>Watching</my_status>
+    <my_comments><![CDATA[Episode 10!]]></my_comments>
+    <my_tags><![CDATA[rewatch, with friends]]></my_tags>
+  </anime>
+</myanimelist>"#;
+
+    fn show(id: &str, name: &str) -> Show {
+        Show {
+            id: id.to_string(),
+            name: name.to_string(),
+            english_name: None,
+            native_name: None,
+            available_episodes: 12,
+        }
+    }
+
+    #[test]
+    fn test_parse_export() {
+        let entries = parse(EXPORT).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].title, "Fullmetal Alchemist: Brotherhood");
+        assert_eq!(entries[0].progress(), 64);
+        assert_eq!(entries[1].mal_id, 52991);
+        assert_eq!(entries[1].progress(), 10);
+        assert_eq!(entries[0].score, None);
+        assert_eq!(entries[1].score, Some(9));
+        assert_eq!(entries[1].comments, "Episode 10!");
+        assert_eq!(entries[1].tags, vec!["rewatch", "with friends"]);
+        let finished = entries[0].finished_at().unwrap();
+        assert_eq!(
+            DateTime::<Local>::from(UNIX_EPOCH + Duration::from_secs(finished))
+                .format("%Y-%m-%d")
+                .to_string(),
+            "2024-03-22"
+        );
+        assert_eq!(entries[1].finished_at(), None);
+
+        assert!(parse("<html></html>").is_err());
+        assert!(parse("not xml").is_err());
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        let entries = vec![MalEntry {
+            mal_id: 1,
+            title: "Odd ]]> Title & Co".to_string(),
+            episodes: Some(12),
+            watched: 12,
+            status: "Completed".to_string(),
+            finished: Some("2024-03-22".to_string()),
+            score: Some(8),
+            comments: "Watched with <friends>".to_string(),
+            tags: vec!["rewatch".to_string(), "with friends".to_string()],
+        }];
+        let xml = export(&entries);
+        assert_eq!(parse(&xml).unwrap(), entries);
+    }
+
+    #[test]
+    fn test_match_title() {
+        let results = vec![
+            show("a", "Sousou no Frieren"),
+            show("b", "Sousou no Frieren 2nd Season"),
+        ];
+        assert_eq!(
+            match_title("sousou no frieren", results.clone()),
+            TitleMatch::Exact(results[0].clone())
+        );
+        assert!(matches!(
+            match_title("Frieren", results),
+            TitleMatch::Ambiguous(r) if r.len() == 2
+        ));
+        assert_eq!(match_title("Frieren", Vec::new()), TitleMatch::NotFound);
+    }
+
+    #[test]
+    fn test_export_shows_combines_tracks() {
+        let mut history = WatchHistory::new();
+        history.update("show1", "Show", 10, "sub");
+        history.update("show1", "Show", 4, "dub");
+
+        let shows = export_shows(&history);
+        assert_eq!(shows.len(), 1);
+        assert_eq!(shows[0].progress, 10);
+
+        let mal = MalShow {
+            mal_id: 7,
+            episodes: Some(10),
+        };
+        let entry = to_entry(&shows[0], Some(&mal), None);
+        assert_eq!(entry.status, "Completed");
+        assert!(entry.finished.is_some());
+        assert_eq!(entry.score, None);
+        assert_eq!(to_entry(&shows[0], None, None).status, "Watching");
+
+        let mut annotations = crate::annotations::Annotations::new();
+        annotations.update("show1", "Show", |a| {
+            a.score = Some(7);
+            a.tags.insert("rewatch".to_string());
+        });
+        let entry = to_entry(&shows[0], None, annotations.get("show1"));
+        assert_eq!(entry.score, Some(7));
+        assert_eq!(entry.tags, vec!["rewatch"]);
+    }
+
+    #[test]
+    fn test_merge_ids() {
+        let mal = |mal_id| MalShow {
+            mal_id,
+            episodes: None,
+        };
+        let mut saved = MalIds::new();
+        saved.shows.insert("a".to_string(), mal(1));
+        saved.shows.insert("b".to_string(), mal(2));
+        let mut ours = MalIds::new();
+        ours.shows.insert("b".to_string(), mal(3));
+
+        saved.merge(&ours);
+        assert_eq!(saved.show_for(1), Some("a"));
+        assert_eq!(saved.show_for(3), Some("b"));
+    }
+}
@@ -0,0 +1,177 @@
+//! Platform-specific locations for application data.
+//!
+//! Each profile keeps its own watch history, watchlist, annotations and
+//! AniList login under `profiles/<name>` in the data directory, and may
+//! override settings in `profiles/<name>.toml` next to the config file. The
+//! default profile uses the data directory itself, as before profiles
+//! existed. Downloads, followed shows and show mappings are shared, so quota
+//! cleanup only deletes episodes every profile has watched.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Name of the profile whose files live directly in the data directory.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The profile chosen for this run, if not the default one.
+static PROFILE: OnceLock<String> = OnceLock::new();
+
+/// Get the directory where anime-watcher stores its data files.
+///
+/// Returns ~/.local/share/anime-watcher on Linux,
+/// or a platform-appropriate location on other systems.
+pub fn data_dir() -> Result<PathBuf, io::Error> {
+    let base = if cfg!(target_os = "macos") {
+        dirs::data_dir()
+    } else {
+        // Linux, Windows or other
+        dirs::data_local_dir()
+    };
+
+    Ok(base
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Could not find data directory"))?
+        .join("anime-watcher"))
+}
+
+/// Get the directory holding the config file.
+pub fn config_dir() -> Result<PathBuf, io::Error> {
+    Ok(dirs::config_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Could not find config directory"))?
+        .join("anime-watcher"))
+}
+
+/// Check that a profile name is usable as a file name.
+pub fn validate_profile(name: &str) -> Result<(), String> {
+    if !name.is_empty()
+        && name.len() <= 32
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "Invalid profile name '{}': use up to 32 letters, digits, '-' or '_'",
+            name
+        ))
+    }
+}
+
+/// Use a profile for the rest of the run.
+///
+/// Must be called before any data file or the config is loaded; a profile
+/// can only be chosen once.
+pub fn set_profile(name: &str) -> Result<(), String> {
+    validate_profile(name)?;
+    if name == DEFAULT_PROFILE {
+        return Ok(());
+    }
+    PROFILE
+        .set(name.to_string())
+        .map_err(|_| "A profile was already chosen".to_string())
+}
+
+/// The profile in use, or `None` for the default profile.
+pub fn profile() -> Option<&'static str> {
+    PROFILE.get().map(String::as_str)
+}
+
+/// Get the directory holding the data files of the profile in use.
+pub fn profile_data_dir() -> Result<PathBuf, io::Error> {
+    match profile() {
+        Some(name) => data_dir_of(name),
+        None => data_dir(),
+    }
+}
+
+/// Get the directory holding the data files of a profile.
+pub fn data_dir_of(name: &str) -> Result<PathBuf, io::Error> {
+    let dir = data_dir()?;
+    Ok(if name == DEFAULT_PROFILE {
+        dir
+    } else {
+        dir.join("profiles").join(name)
+    })
+}
+
+/// Get the config overlay of a profile.
+pub fn profile_config_path(name: &str) -> Result<PathBuf, io::Error> {
+    Ok(config_dir()?
+        .join("profiles")
+        .join(format!("{}.toml", name)))
+}
+
+/// Names of the profiles that have data or a config overlay, by name.
+///
+/// The default profile isn't included.
+pub fn profiles() -> Result<Vec<String>, io::Error> {
+    Ok(list_profiles(
+        &data_dir()?.join("profiles"),
+        &config_dir()?.join("profiles"),
+    ))
+}
+
+/// Profile names found as directories in `data` and `.toml` files in
+/// `config`.
+fn list_profiles(data: &Path, config: &Path) -> Vec<String> {
+    let names = |dir: &Path| -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .map(|entries| entries.flatten().map(|e| e.path()).collect())
+            .unwrap_or_default()
+    };
+    let mut profiles = BTreeSet::new();
+    for path in names(data).into_iter().filter(|p| p.is_dir()) {
+        profiles.extend(
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .map(str::to_string),
+        );
+    }
+    for path in names(config) {
+        if path.extension().is_some_and(|e| e == "toml") {
+            profiles.extend(
+                path.file_stem()
+                    .and_then(|n| n.to_str())
+                    .map(str::to_string),
+            );
+        }
+    }
+    profiles
+        .into_iter()
+        .filter(|name| name != DEFAULT_PROFILE && validate_profile(name).is_ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_profile() {
+        assert!(validate_profile("alice").is_ok());
+        assert!(validate_profile("kid_2-b").is_ok());
+        assert!(validate_profile("").is_err());
+        assert!(validate_profile("../alice").is_err());
+        assert!(validate_profile("a b").is_err());
+    }
+
+    #[test]
+    fn test_list_profiles() {
+        let dir = std::env::temp_dir().join(format!("aw-profiles-{}", std::process::id()));
+        let (data, config) = (dir.join("data"), dir.join("config"));
+        fs::create_dir_all(data.join("alice")).unwrap();
+        fs::create_dir_all(data.join("default")).unwrap();
+        fs::create_dir_all(&config).unwrap();
+        fs::write(config.join("bob.toml"), "").unwrap();
+        fs::write(config.join("alice.toml"), "").unwrap();
+        fs::write(config.join("notes.txt"), "").unwrap();
+
+        assert_eq!(list_profiles(&data, &config), vec!["alice", "bob"]);
+        assert!(list_profiles(&dir.join("missing"), &dir.join("missing")).is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
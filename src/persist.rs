@@ -0,0 +1,333 @@
+//! Crash-safe storage for the data files.
+//!
+//! Files are written to a temporary file next to the target and renamed over
+//! it, so a crash mid-write leaves the previous version intact. The last few
+//! versions are kept as numbered backups (`history.json.1` is the newest),
+//! and a lock file lets running instances take turns updating a file.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of backups kept of each file.
+pub const BACKUPS: usize = 5;
+
+/// Minimum age of the newest backup before another one is taken.
+///
+/// The history is saved every few seconds during playback; without this the
+/// backups would all be from the last minute.
+const BACKUP_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// A data file that exists but can't be parsed.
+#[derive(Debug)]
+pub struct CorruptFile {
+    /// Path of the file.
+    pub path: PathBuf,
+    /// Why it couldn't be parsed.
+    pub message: String,
+}
+
+impl fmt::Display for CorruptFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is corrupt: {}", self.path.display(), self.message)
+    }
+}
+
+impl Error for CorruptFile {}
+
+/// A data file written by a newer version of anime-watcher, in a format
+/// this one doesn't know. It must be neither recovered nor overwritten.
+#[derive(Debug)]
+pub struct NewerFormat {
+    /// Path of the file.
+    pub path: PathBuf,
+    /// Format version of the file.
+    pub version: u64,
+    /// Newest format version this build reads.
+    pub supported: u64,
+}
+
+impl fmt::Display for NewerFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} was written by a newer version of anime-watcher (format {}, \
+             this one reads up to {}); please upgrade",
+            self.path.display(),
+            self.version,
+            self.supported
+        )
+    }
+}
+
+impl Error for NewerFormat {}
+
+/// Path with `suffix` appended to the file name (`history.json.lock`).
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Path of the `n`th newest backup of a file.
+pub fn backup_path(path: &Path, n: usize) -> PathBuf {
+    with_suffix(path, &format!(".{}", n))
+}
+
+/// Existing backups of a file, newest first.
+pub fn backups(path: &Path) -> Vec<PathBuf> {
+    (1..=BACKUPS)
+        .map(|n| backup_path(path, n))
+        .filter(|p| p.exists())
+        .collect()
+}
+
+/// Find the newest backup of a file that `parse` accepts.
+///
+/// Returns the backup's path with its parsed contents.
+pub fn newest_backup<T, E>(
+    path: &Path,
+    parse: impl Fn(&str) -> Result<T, E>,
+) -> Option<(PathBuf, T)> {
+    backups(path).into_iter().find_map(|backup| {
+        let content = fs::read_to_string(&backup).ok()?;
+        Some((backup, parse(&content).ok()?))
+    })
+}
+
+/// Load a data file with `parse`.
+///
+/// A missing file gives `None`; one that can't be parsed gives a
+/// [`CorruptFile`] error.
+pub fn load<T, E: fmt::Display>(
+    path: &Path,
+    parse: impl Fn(&str) -> Result<T, E>,
+) -> Result<Option<T>, Box<dyn Error>> {
+    match read(path)? {
+        Some(content) => match parse(&content) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => Err(Box::new(CorruptFile {
+                path: path.to_path_buf(),
+                message: e.to_string(),
+            })),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Load a JSON data file, giving `None` if it doesn't exist.
+pub fn load_json<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, Box<dyn Error>> {
+    load(path, |content| serde_json::from_str(content))
+}
+
+/// Find the newest backup of a JSON data file that can be parsed.
+pub fn newest_json_backup<T: DeserializeOwned>(path: &Path) -> Option<(PathBuf, T)> {
+    newest_backup(path, |content| serde_json::from_str(content))
+}
+
+/// Save a JSON data file under its [`lock`].
+///
+/// `value` is merged into the file's current contents with `merge`, so
+/// changes saved by other running instances since it was loaded are kept.
+pub fn save_json<T: Serialize + DeserializeOwned + Default>(
+    path: &Path,
+    value: &T,
+    merge: impl FnOnce(&mut T, &T),
+) -> Result<(), Box<dyn Error>> {
+    let _lock = lock(path)?;
+    // A file that can't be parsed was already offered for recovery at
+    // startup; its backups are kept.
+    let mut merged = read(path)?
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+    merge(&mut merged, value);
+    let content = serde_json::to_string_pretty(&merged)?;
+    write_atomic(path, content.as_bytes())?;
+    Ok(())
+}
+
+/// An exclusive lock on a data file, released when dropped.
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+/// Lock a data file for updating, waiting for other instances to finish.
+///
+/// The lock is taken on a separate `.lock` file, since the data file itself
+/// is replaced on every write.
+pub fn lock(path: &Path) -> io::Result<FileLock> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(with_suffix(path, ".lock"))?;
+    file.lock()?;
+    Ok(FileLock { _file: file })
+}
+
+/// Read a data file, returning `None` if it doesn't exist.
+pub fn read(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Rotate the backups of `path` if the newest one is old enough.
+fn rotate_backups(path: &Path) -> io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let newest = backup_path(path, 1);
+    let recent = fs::metadata(&newest)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age < BACKUP_INTERVAL);
+    if recent {
+        return Ok(());
+    }
+
+    for n in (1..BACKUPS).rev() {
+        let from = backup_path(path, n);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, n + 1))?;
+        }
+    }
+    // Copied rather than renamed so the file never goes missing
+    fs::copy(path, &newest)?;
+    Ok(())
+}
+
+/// Replace the contents of a file atomically, keeping a backup of the
+/// previous version.
+///
+/// Callers updating a shared file should hold its [`lock`].
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temp = with_suffix(path, &format!(".tmp-{}", std::process::id()));
+    let result = (|| {
+        let mut file = File::create(&temp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        rotate_backups(path)?;
+        fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result?;
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+    Ok(())
+}
+
+/// Move a corrupt file out of the way, keeping it for inspection.
+///
+/// Returns the new path (`history.json.corrupt-<unix time>`).
+pub fn quarantine(path: &Path) -> io::Result<PathBuf> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let target = with_suffix(path, &format!(".corrupt-{}", timestamp));
+    fs::rename(path, &target)?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("aw-persist-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("data.json")
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_backup() {
+        let path = temp_file("backup");
+        write_atomic(&path, b"first").unwrap();
+        assert!(backups(&path).is_empty());
+
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(backups(&path), [backup_path(&path, 1)]);
+        assert_eq!(fs::read_to_string(backup_path(&path, 1)).unwrap(), "first");
+
+        // A recent backup isn't replaced on every save
+        write_atomic(&path, b"third").unwrap();
+        assert_eq!(fs::read_to_string(backup_path(&path, 1)).unwrap(), "first");
+
+        let leftovers: Vec<_> = fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().contains(".tmp-"))
+            .collect();
+        assert!(leftovers.is_empty());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_quarantine_and_read() {
+        let path = temp_file("quarantine");
+        assert!(read(&path).unwrap().is_none());
+
+        fs::write(&path, "{not json").unwrap();
+        let moved = quarantine(&path).unwrap();
+        assert!(!path.exists());
+        assert_eq!(fs::read_to_string(&moved).unwrap(), "{not json");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_json_store() {
+        use std::collections::BTreeMap;
+        type Store = BTreeMap<String, u32>;
+        let merge = |saved: &mut Store, ours: &Store| saved.extend(ours.clone());
+
+        let path = temp_file("json");
+        assert!(load_json::<Store>(&path).unwrap().is_none());
+
+        let first = Store::from([("a".to_string(), 1)]);
+        save_json(&path, &first, merge).unwrap();
+        assert_eq!(load_json(&path).unwrap(), Some(first.clone()));
+
+        // Another instance's changes are merged with the saved contents
+        let second = Store::from([("a".to_string(), 3), ("b".to_string(), 2)]);
+        save_json(&path, &second, merge).unwrap();
+        assert_eq!(load_json(&path).unwrap(), Some(second));
+        assert_eq!(
+            newest_json_backup::<Store>(&path),
+            Some((backup_path(&path, 1), first))
+        );
+        save_json(&path, &Store::new(), merge).unwrap();
+        assert_eq!(load_json::<Store>(&path).unwrap().unwrap().len(), 2);
+
+        fs::write(&path, "{not json").unwrap();
+        let err = load_json::<Store>(&path).unwrap_err();
+        assert!(err.downcast_ref::<CorruptFile>().is_some());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,334 @@
+//! Video player launching and playback tracking.
+//!
+//! Players are started detached so they outlive the TUI. mpv is additionally
+//! given a JSON IPC socket (`--input-ipc-server`) that is polled for
+//! `time-pos` and `duration`, so the playback position can be saved and
+//! resumed with `--start=`. Other players can't be tracked.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use log::debug;
+use tokio::sync::mpsc;
+
+/// How often a tracked player is asked for its position.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Playback progress reported by a tracked player.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlaybackEvent {
+    /// The player reported its position.
+    Progress {
+        /// Show being played.
+        show_id: String,
+        /// Translation mode being played.
+        mode: String,
+        /// Episode being played.
+        episode: i64,
+        /// Seconds played.
+        position: f64,
+        /// Length of the episode in seconds.
+        duration: f64,
+        /// Whether playback started in incognito mode, so the progress
+        /// isn't recorded even if incognito was turned off since.
+        incognito: bool,
+    },
+    /// The player exited.
+    Closed {
+        /// Show that was played.
+        show_id: String,
+        /// Translation mode that was played.
+        mode: String,
+        /// Episode that was played.
+        episode: i64,
+    },
+}
+
+/// Whether a player command is mpv.
+fn is_mpv(player: &str) -> bool {
+    Path::new(player)
+        .file_stem()
+        .is_some_and(|stem| stem.eq_ignore_ascii_case("mpv"))
+}
+
+/// Format a position in seconds as `H:MM:SS` or `M:SS`.
+pub fn format_position(seconds: f64) -> String {
+    let total = seconds.max(0.0) as u64;
+    let (hours, minutes, secs) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, secs)
+    } else {
+        format!("{}:{:02}", minutes, secs)
+    }
+}
+
+/// Launches the video player and collects playback events.
+pub struct Player {
+    command: String,
+    args: Vec<String>,
+    event_tx: mpsc::UnboundedSender<PlaybackEvent>,
+    event_rx: mpsc::UnboundedReceiver<PlaybackEvent>,
+    launches: u32,
+}
+
+impl Player {
+    /// Create a player that runs `command` with extra `args`.
+    pub fn new(command: String, args: Vec<String>) -> Self {
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        Self {
+            command,
+            args,
+            event_tx,
+            event_rx,
+            launches: 0,
+        }
+    }
+
+    /// Whether playback with this player can be tracked.
+    pub fn is_tracked(&self) -> bool {
+        cfg!(unix) && is_mpv(&self.command)
+    }
+
+    /// Socket path for the next tracked launch.
+    fn socket_path(&mut self) -> PathBuf {
+        self.launches += 1;
+        std::env::temp_dir().join(format!(
+            "anime-watcher-{}-{}.sock",
+            std::process::id(),
+            self.launches
+        ))
+    }
+
+    /// Start playing `url`, from `start` seconds if given.
+    ///
+    /// Returns whether the playback is tracked; tracked players report
+    /// [`PlaybackEvent`]s until they exit, flagged with `incognito`.
+    pub fn play(
+        &mut self,
+        url: &str,
+        start: Option<f64>,
+        show_id: &str,
+        mode: &str,
+        episode: i64,
+        incognito: bool,
+    ) -> io::Result<bool> {
+        let mut cmd = Command::new("setsid");
+        cmd.arg(&self.command).args(&self.args);
+
+        let socket = if self.is_tracked() {
+            let socket = self.socket_path();
+            let _ = std::fs::remove_file(&socket);
+            cmd.arg(format!("--input-ipc-server={}", socket.display()));
+            if let Some(start) = start {
+                cmd.arg(format!("--start={:.0}", start));
+            }
+            Some(socket)
+        } else {
+            None
+        };
+        cmd.arg(url);
+
+        debug!("Playing: {}", url);
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let Some(socket) = socket else {
+            return Ok(false);
+        };
+        let tx = self.event_tx.clone();
+        let (show_id, mode) = (show_id.to_string(), mode.to_string());
+        tokio::spawn(async move {
+            monitor(&socket, &show_id, &mode, episode, incognito, &tx).await;
+            let _ = std::fs::remove_file(&socket);
+            let _ = tx.send(PlaybackEvent::Closed {
+                show_id,
+                mode,
+                episode,
+            });
+        });
+        Ok(true)
+    }
+
+    /// Get the next playback event without waiting.
+    pub fn try_next_event(&mut self) -> Option<PlaybackEvent> {
+        self.event_rx.try_recv().ok()
+    }
+}
+
+/// Request IDs for the polled properties.
+const TIME_POS_REQUEST: u64 = 1;
+const DURATION_REQUEST: u64 = 2;
+
+/// Parse an mpv IPC reply to one of our `get_property` requests.
+///
+/// Returns the request ID and the numeric value. Events and failed
+/// requests (e.g. `time-pos` before the file is loaded) give `None`.
+fn parse_reply(line: &str) -> Option<(u64, f64)> {
+    let reply: serde_json::Value = serde_json::from_str(line).ok()?;
+    if reply.get("error")?.as_str()? != "success" {
+        return None;
+    }
+    Some((
+        reply.get("request_id")?.as_u64()?,
+        reply.get("data")?.as_f64()?,
+    ))
+}
+
+/// Poll mpv over its IPC socket until it exits.
+#[cfg(unix)]
+async fn monitor(
+    socket: &Path,
+    show_id: &str,
+    mode: &str,
+    episode: i64,
+    incognito: bool,
+    tx: &mpsc::UnboundedSender<PlaybackEvent>,
+) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::UnixStream;
+
+    // mpv creates the socket shortly after starting.
+    let mut stream = None;
+    for _ in 0..50 {
+        if let Ok(connected) = UnixStream::connect(socket).await {
+            stream = Some(connected);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+    let Some(stream) = stream else {
+        debug!("Could not connect to mpv at {}", socket.display());
+        return;
+    };
+
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    let mut duration = None;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let request = format!(
+                    "{{\"command\":[\"get_property\",\"duration\"],\"request_id\":{}}}\n\
+                     {{\"command\":[\"get_property\",\"time-pos\"],\"request_id\":{}}}\n",
+                    DURATION_REQUEST, TIME_POS_REQUEST
+                );
+                if writer.write_all(request.as_bytes()).await.is_err() {
+                    break;
+                }
+            }
+            line = lines.next_line() => {
+                let Ok(Some(line)) = line else {
+                    break;
+                };
+                match parse_reply(&line) {
+                    Some((DURATION_REQUEST, value)) => duration = Some(value),
+                    Some((TIME_POS_REQUEST, position)) => {
+                        if let Some(duration) = duration {
+                            let _ = tx.send(PlaybackEvent::Progress {
+                                show_id: show_id.to_string(),
+                                mode: mode.to_string(),
+                                episode,
+                                position,
+                                duration,
+                                incognito,
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Playback can't be tracked on this platform.
+#[cfg(not(unix))]
+async fn monitor(
+    _socket: &Path,
+    _show_id: &str,
+    _mode: &str,
+    _episode: i64,
+    _incognito: bool,
+    _tx: &mpsc::UnboundedSender<PlaybackEvent>,
+) {
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_mpv() {
+        assert!(is_mpv("mpv"));
+        assert!(is_mpv("/usr/bin/mpv"));
+        assert!(!is_mpv("vlc"));
+        assert!(!is_mpv("mpvpaper"));
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        assert_eq!(
+            parse_reply(r#"{"data":83.25,"request_id":1,"error":"success"}"#),
+            Some((1, 83.25))
+        );
+        assert_eq!(
+            parse_reply(r#"{"request_id":1,"error":"property unavailable"}"#),
+            None
+        );
+        assert_eq!(parse_reply(r#"{"event":"playback-restart"}"#), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_incognito_is_kept_for_the_playback() {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        // A fake mpv that answers one poll and quits
+        let socket = std::env::temp_dir().join(format!("aw-player-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        let listener = tokio::net::UnixListener::bind(&socket).unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            lines.next_line().await.unwrap();
+            lines.next_line().await.unwrap();
+            writer
+                .write_all(
+                    b"{\"data\":1440.0,\"request_id\":2,\"error\":\"success\"}\n\
+                      {\"data\":600.0,\"request_id\":1,\"error\":\"success\"}\n",
+                )
+                .await
+                .unwrap();
+        });
+
+        // The flag given at the start holds for every event, whatever the
+        // TUI's incognito setting is by the time they arrive
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        monitor(&socket, "show1", "sub", 3, true, &tx).await;
+        let _ = std::fs::remove_file(&socket);
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            PlaybackEvent::Progress {
+                show_id: "show1".to_string(),
+                mode: "sub".to_string(),
+                episode: 3,
+                position: 600.0,
+                duration: 1440.0,
+                incognito: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_format_position() {
+        assert_eq!(format_position(83.9), "1:23");
+        assert_eq!(format_position(3725.0), "1:02:05");
+    }
+}
@@ -0,0 +1,324 @@
+//! Watch statistics aggregated from the watch history.
+//!
+//! Each watched episode counts once, on the day it was last played, so a
+//! rewatch moves an episode to a later week instead of counting it twice.
+//! Episodes marked watched without being played, such as imported progress,
+//! count in the totals but not in the charts or streaks.
+
+use crate::history::{WatchHistory, UNKNOWN_TIME};
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate};
+use std::collections::{BTreeMap, HashMap};
+
+/// Assumed length of an episode the player didn't report a length for.
+pub const DEFAULT_EPISODE_MINUTES: f64 = 24.0;
+
+/// Number of weeks in the weekly chart.
+pub const WEEKS: usize = 26;
+
+/// Number of months in the monthly chart.
+pub const MONTHS: usize = 12;
+
+/// Number of shows in the most-watched list.
+const TOP_SHOWS: usize = 5;
+
+/// Episodes watched in a week or month.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Period {
+    /// First day of the period.
+    pub start: NaiveDate,
+    /// Episodes watched in the period.
+    pub episodes: u64,
+}
+
+/// What was watched of one show, in sub and dub together.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShowStats {
+    /// Unique identifier for the show.
+    pub show_id: String,
+    /// Display name of the show.
+    pub name: String,
+    /// Watched episodes.
+    pub episodes: usize,
+    /// Estimated hours watched.
+    pub hours: f64,
+}
+
+/// Statistics of the whole watch history.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    /// Watched episodes.
+    pub episodes: usize,
+    /// Estimated hours watched.
+    pub hours: f64,
+    /// Episodes per week (Monday to Sunday), oldest first, ending with the
+    /// current week.
+    pub weekly: Vec<Period>,
+    /// Episodes per calendar month, oldest first, ending with the current
+    /// month.
+    pub monthly: Vec<Period>,
+    /// Shows with the most watched episodes, most first.
+    pub top_shows: Vec<ShowStats>,
+    /// Days in a row with an episode watched, up to today. Today not having
+    /// one yet doesn't break the streak.
+    pub current_streak: u32,
+    /// Longest run of days in a row with an episode watched.
+    pub longest_streak: u32,
+    /// Watched episodes per translation mode.
+    pub by_mode: BTreeMap<String, usize>,
+}
+
+impl Stats {
+    /// Aggregate the watch history as of `today`, in local time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anime_watcher::history::WatchHistory;
+    /// use anime_watcher::stats::Stats;
+    ///
+    /// let mut history = WatchHistory::new();
+    /// history.update("show1", "Show", 1, "sub");
+    /// history.update("show1", "Show", 2, "sub");
+    ///
+    /// let stats = Stats::compute(&history, chrono::Local::now().date_naive());
+    /// assert_eq!(stats.episodes, 2);
+    /// assert_eq!(stats.current_streak, 1);
+    /// assert_eq!(stats.weekly.last().unwrap().episodes, 2);
+    /// ```
+    pub fn compute(history: &WatchHistory, today: NaiveDate) -> Self {
+        let mut stats = Stats::default();
+        let mut days: BTreeMap<NaiveDate, u64> = BTreeMap::new();
+        // Shows with the timestamp of the record their name was taken from
+        let mut shows: HashMap<&str, (ShowStats, u64)> = HashMap::new();
+
+        for record in history.records.values() {
+            let (show, named_at) = shows.entry(&record.show_id).or_insert_with(|| {
+                let show = ShowStats {
+                    show_id: record.show_id.clone(),
+                    name: String::new(),
+                    episodes: 0,
+                    hours: 0.0,
+                };
+                (show, 0)
+            });
+            if show.name.is_empty() || record.timestamp > *named_at {
+                show.name = record.show_name.clone();
+                *named_at = record.timestamp;
+            }
+
+            for (episode, timestamp) in &record.watched {
+                let hours = record
+                    .durations
+                    .get(episode)
+                    .map_or(DEFAULT_EPISODE_MINUTES / 60.0, |seconds| seconds / 3600.0);
+                stats.episodes += 1;
+                stats.hours += hours;
+                show.episodes += 1;
+                show.hours += hours;
+                *stats.by_mode.entry(record.mode.clone()).or_default() += 1;
+                if let Some(day) = local_date(*timestamp) {
+                    *days.entry(day).or_default() += 1;
+                }
+            }
+        }
+
+        let watched_between =
+            |start: NaiveDate, end: NaiveDate| days.range(start..end).map(|(_, n)| n).sum();
+        let this_week = today - Days::new(u64::from(today.weekday().num_days_from_monday()));
+        stats.weekly = (0..WEEKS as u64)
+            .rev()
+            .map(|i| {
+                let start = this_week - Days::new(7 * i);
+                Period {
+                    start,
+                    episodes: watched_between(start, start + Days::new(7)),
+                }
+            })
+            .collect();
+        let this_month = today.with_day(1).unwrap_or(today);
+        stats.monthly = (0..MONTHS as u32)
+            .rev()
+            .map(|i| {
+                let start = this_month - Months::new(i);
+                Period {
+                    start,
+                    episodes: watched_between(start, start + Months::new(1)),
+                }
+            })
+            .collect();
+
+        let mut run = 0;
+        let mut previous: Option<NaiveDate> = None;
+        for day in days.keys() {
+            run = match previous {
+                Some(previous) if previous.succ_opt() == Some(*day) => run + 1,
+                _ => 1,
+            };
+            stats.longest_streak = stats.longest_streak.max(run);
+            previous = Some(*day);
+        }
+        let mut day = today;
+        if !days.contains_key(&day) {
+            day = day.pred_opt().unwrap_or(day);
+        }
+        while days.contains_key(&day) {
+            stats.current_streak += 1;
+            match day.pred_opt() {
+                Some(pred) => day = pred,
+                None => break,
+            }
+        }
+
+        let mut top: Vec<ShowStats> = shows
+            .into_values()
+            .map(|(show, _)| show)
+            .filter(|show| show.episodes > 0)
+            .collect();
+        top.sort_by(|a, b| {
+            b.episodes
+                .cmp(&a.episodes)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        top.truncate(TOP_SHOWS);
+        stats.top_shows = top;
+
+        stats
+    }
+}
+
+/// Local calendar date of a Unix timestamp, unless it is [`UNKNOWN_TIME`].
+fn local_date(timestamp: u64) -> Option<NaiveDate> {
+    if timestamp == UNKNOWN_TIME {
+        return None;
+    }
+    DateTime::from_timestamp(timestamp as i64, 0).map(|t| t.with_timezone(&Local).date_naive())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    /// Unix timestamp of noon local time on a day.
+    fn noon(day: NaiveDate) -> u64 {
+        day.and_hms_opt(12, 0, 0)
+            .unwrap()
+            .and_local_timezone(Local)
+            .unwrap()
+            .timestamp() as u64
+    }
+
+    fn watch(history: &mut WatchHistory, show_id: &str, mode: &str, episode: i64, day: NaiveDate) {
+        history.record_play(show_id, show_id, episode, mode);
+        history
+            .records
+            .get_mut(&crate::history::record_key(show_id, mode))
+            .unwrap()
+            .watched
+            .insert(episode, noon(day));
+    }
+
+    #[test]
+    fn test_periods() {
+        // A Wednesday
+        let today = date(2026, 10, 14);
+        let mut history = WatchHistory::new();
+        watch(&mut history, "a", "sub", 1, date(2026, 10, 12));
+        watch(&mut history, "a", "sub", 2, date(2026, 10, 14));
+        watch(&mut history, "a", "sub", 3, date(2026, 10, 11));
+        watch(&mut history, "b", "dub", 1, date(2026, 9, 30));
+        watch(&mut history, "b", "dub", 2, date(2025, 1, 1));
+
+        let stats = Stats::compute(&history, today);
+        assert_eq!(stats.episodes, 5);
+        assert_eq!(stats.weekly.len(), WEEKS);
+        let this_week = stats.weekly.last().unwrap();
+        assert_eq!(this_week.start, date(2026, 10, 12));
+        assert_eq!(this_week.episodes, 2);
+        assert_eq!(stats.weekly[WEEKS - 2].episodes, 1);
+        assert_eq!(stats.weekly[WEEKS - 3].episodes, 1);
+
+        assert_eq!(stats.monthly.len(), MONTHS);
+        assert_eq!(stats.monthly[0].start, date(2025, 11, 1));
+        assert_eq!(stats.monthly[MONTHS - 1].episodes, 3);
+        assert_eq!(stats.monthly[MONTHS - 2].episodes, 1);
+
+        assert_eq!(stats.by_mode.get("sub"), Some(&3));
+        assert_eq!(stats.by_mode.get("dub"), Some(&2));
+    }
+
+    #[test]
+    fn test_streaks() {
+        let today = date(2026, 10, 14);
+        let mut history = WatchHistory::new();
+        for (episode, day) in [(1, 1), (2, 2), (3, 3), (4, 3), (5, 10), (6, 12), (7, 13)] {
+            watch(&mut history, "a", "sub", episode, date(2026, 10, day));
+        }
+
+        // Nothing watched yet today, so the streak runs up to yesterday
+        let stats = Stats::compute(&history, today);
+        assert_eq!(stats.current_streak, 2);
+        assert_eq!(stats.longest_streak, 3);
+
+        let stats = Stats::compute(&history, date(2026, 10, 15));
+        assert_eq!(stats.current_streak, 0);
+    }
+
+    #[test]
+    fn test_hours_and_top_shows() {
+        let today = date(2026, 10, 14);
+        let mut history = WatchHistory::new();
+        watch(&mut history, "a", "sub", 1, today);
+        watch(&mut history, "a", "dub", 2, today);
+        watch(&mut history, "b", "sub", 1, today);
+        history
+            .records
+            .get_mut("b:sub")
+            .unwrap()
+            .durations
+            .insert(1, 3600.0);
+
+        let stats = Stats::compute(&history, today);
+        let expected = 2.0 * DEFAULT_EPISODE_MINUTES / 60.0 + 1.0;
+        assert!((stats.hours - expected).abs() < 1e-9);
+        assert_eq!(stats.top_shows.len(), 2);
+        assert_eq!(stats.top_shows[0].show_id, "a");
+        assert_eq!(stats.top_shows[0].episodes, 2);
+        assert!((stats.top_shows[1].hours - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_imports_stay_out_of_the_timeline() {
+        let today = chrono::Local::now().date_naive();
+        let mut history = WatchHistory::new();
+        watch(&mut history, "a", "sub", 1, today - Days::new(1));
+        history.import_progress("b", "Backlog", "sub", 100, None);
+        history.record_play("c", "Finished", 1, "sub");
+        history.mark_finished("c", "sub", &[1, 2, 3]);
+
+        let stats = Stats::compute(&history, today);
+        assert_eq!(stats.episodes, 104);
+        assert_eq!(stats.current_streak, 1);
+        assert_eq!(stats.longest_streak, 1);
+        assert_eq!(stats.weekly.iter().map(|p| p.episodes).sum::<u64>(), 1);
+    }
+
+    #[test]
+    fn test_dated_imports_stay_out_of_the_timeline() {
+        let today = chrono::Local::now().date_naive();
+        let mut history = WatchHistory::new();
+        watch(&mut history, "a", "sub", 1, today - Days::new(3));
+        // Finished on MAL two days ago
+        let finished = noon(today - Days::new(2));
+        history.import_progress("b", "Backlog", "sub", 64, Some(finished));
+
+        let stats = Stats::compute(&history, today);
+        assert_eq!(stats.episodes, 65);
+        assert_eq!(stats.longest_streak, 1);
+        assert_eq!(stats.weekly.iter().map(|p| p.episodes).sum::<u64>(), 1);
+        assert_eq!(stats.monthly.iter().map(|p| p.episodes).sum::<u64>(), 1);
+    }
+}
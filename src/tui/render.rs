@@ -4,12 +4,16 @@ use ratatui::{
     layout::{Constraint, Direction, Layout, Rect},
     style::{Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap},
+    widgets::{BarChart, Block, Borders, Clear, Gauge, List, ListItem, Paragraph, Sparkline, Wrap},
     Frame,
 };
 
 use super::state::App;
-use super::types::{Focus, Screen};
+use super::types::{AnnotationInput, Focus, Screen};
+use crate::annotations::Annotation;
+use crate::download::QueueStatus;
+use crate::player::format_position;
+use crate::watchlist::Priority;
 
 /// Draw the UI.
 pub fn draw(frame: &mut Frame, app: &mut App) {
@@ -54,6 +58,10 @@ pub fn draw(frame: &mut Frame, app: &mut App) {
         Screen::QualitySelect => draw_quality_select(frame, app, content_chunks[1]),
         Screen::Playback => draw_playback(frame, app, content_chunks[1]),
         Screen::BatchSelect => draw_batch_select(frame, app, content_chunks[1]),
+        Screen::Queue => draw_queue(frame, app, content_chunks[1]),
+        Screen::History => draw_history(frame, app, content_chunks[1]),
+        Screen::Stats => draw_stats(frame, app, content_chunks[1]),
+        Screen::Watchlist => draw_watchlist(frame, app, content_chunks[1]),
     }
 
     // Draw footer
@@ -74,6 +82,17 @@ pub fn draw(frame: &mut Frame, app: &mut App) {
         draw_batch_confirm_popup(frame, app);
     }
 
+    // Draw history screen popups
+    if let Some(input) = &app.history_episode_input {
+        draw_episode_input_popup(frame, input);
+    }
+    if app.pending_history_edit.is_some() {
+        draw_history_confirm_popup(frame, app);
+    }
+    if let Some(input) = &app.annotation_input {
+        draw_annotation_input_popup(frame, input);
+    }
+
     // Draw help modal if shown
     if app.show_help {
         draw_help_modal(frame, app);
@@ -99,6 +118,25 @@ fn draw_header(frame: &mut Frame, app: &App, area: Rect) {
                 .fg(app.colors.mode_indicator())
                 .add_modifier(Modifier::BOLD),
         ),
+        match &app.profile {
+            Some(profile) => Span::styled(
+                format!("  [profile: {}]", profile),
+                Style::default()
+                    .fg(app.colors.highlight())
+                    .add_modifier(Modifier::BOLD),
+            ),
+            None => Span::raw(""),
+        },
+        if app.incognito {
+            Span::styled(
+                "  [incognito]",
+                Style::default()
+                    .fg(app.colors.error())
+                    .add_modifier(Modifier::BOLD),
+            )
+        } else {
+            Span::raw("")
+        },
         Span::raw("  "),
         Span::styled(format!("[{}]", app.mode), mode_style),
         Span::raw("  "),
@@ -111,6 +149,21 @@ fn draw_header(frame: &mut Frame, app: &App, area: Rect) {
         } else {
             Span::raw("")
         },
+        match (app.queue_remaining, &app.download_window_opens) {
+            (0, _) => Span::raw(""),
+            (n, _) if app.queue_paused => Span::styled(
+                format!("  [queue: {} paused]", n),
+                Style::default().fg(app.colors.text_dim()),
+            ),
+            (n, Some(opens)) => Span::styled(
+                format!("  [queue: {} from {}]", n, opens),
+                Style::default().fg(app.colors.text_dim()),
+            ),
+            (n, None) => Span::styled(
+                format!("  [queue: {}]", n),
+                Style::default().fg(app.colors.download()),
+            ),
+        },
     ]))
     .block(Block::default().borders(Borders::ALL));
 
@@ -172,14 +225,15 @@ fn draw_sidebar(frame: &mut Frame, app: &mut App, area: Rect) {
         let items: Vec<ListItem> = app
             .history_records
             .iter()
-            .map(|(_, name, ep, _)| {
+            .map(|(_, name, ep, mode)| {
                 // Truncate name if too long (use chars to avoid UTF-8 panics)
-                let display_name = if name.chars().count() > 20 {
-                    format!("{}...", name.chars().take(17).collect::<String>())
+                let display_name = if name.chars().count() > 16 {
+                    format!("{}...", name.chars().take(13).collect::<String>())
                 } else {
                     name.clone()
                 };
-                ListItem::new(format!("{} [{}]", display_name, ep))
+                // Sub and dub of a show are listed separately
+                ListItem::new(format!("{} [{} {}]", display_name, ep, mode))
             })
             .collect();
 
@@ -260,9 +314,17 @@ fn draw_show_list_main(frame: &mut Frame, app: &mut App, area: Rect) {
     let details = if let Some(i) = app.show_list_state.selected() {
         if i < app.shows.len() {
             let show = &app.shows[i];
+            let watchlist = app
+                .watchlist
+                .get(&show.id)
+                .map(|entry| format!("\nWatchlist: {}", entry.status.label()))
+                .unwrap_or_default();
             format!(
-                "Name: {}\nEpisodes: {}\n\nPress Enter to view episodes",
-                show.name, show.available_episodes
+                "Name: {}\nEpisodes: {}{}{}\n\nPress Enter to view episodes",
+                show.name,
+                show.available_episodes,
+                watchlist,
+                annotation_details(app.annotations.get(&show.id))
             )
         } else {
             String::new()
@@ -335,14 +397,29 @@ fn draw_episode_list_main(frame: &mut Frame, app: &mut App, area: Rect) {
     // Episode list using filtered episodes
     let items: Vec<ListItem> = filtered_episodes
         .iter()
-        .map(|e| ListItem::new(e.to_display()))
+        .map(|e| {
+            if app.watched_episodes.contains(&e.number) {
+                ListItem::new(format!("✓ {}", e.to_display()))
+                    .style(Style::default().fg(app.colors.text_dim()))
+            } else {
+                ListItem::new(format!("○ {}", e.to_display()))
+            }
+        })
         .collect();
 
     let title = if let Some(show) = &app.selected_show {
-        if !app.episode_filter.is_empty() {
-            format!("{} (filtered)", show.name)
+        let mut name = if app.is_following_selected() {
+            format!("★ {}", show.name)
         } else {
             show.name.clone()
+        };
+        if let Some(entry) = app.watchlist.get(&show.id) {
+            name.push_str(&format!(" [{}]", entry.status.label()));
+        }
+        if !app.episode_filter.is_empty() {
+            format!("{} (filtered)", name)
+        } else {
+            name
         }
     } else {
         "Episodes".to_string()
@@ -369,7 +446,22 @@ fn draw_episode_list_main(frame: &mut Frame, app: &mut App, area: Rect) {
             } else {
                 "stream"
             };
-            format!("Episode {}\n\nPress Enter to {}", episode.number, action)
+            let watched = if app.watched_episodes.contains(&episode.number) {
+                "Watched".to_string()
+            } else if let Some(position) = app.resume_positions.get(&episode.number) {
+                format!("Stopped at {}", format_position(*position))
+            } else {
+                "Not watched".to_string()
+            };
+            let annotation = app
+                .selected_show
+                .as_ref()
+                .map(|show| annotation_details(app.annotations.get(&show.id)))
+                .unwrap_or_default();
+            format!(
+                "Episode {}\n{}{}\n\nPress Enter to {}",
+                episode.number, watched, annotation, action
+            )
         } else {
             String::new()
         }
@@ -391,18 +483,26 @@ fn draw_footer(frame: &mut Frame, app: &App, area: Rect) {
         match app.screen {
             Screen::Startup => "[/] search  [Tab] switch  [↑↓] navigate  [Enter] select  [?] help  [q] quit",
             Screen::Search => "[/] search  [Tab] switch  [?] help  [q] quit",
-            Screen::ShowList => "[/] search  [Tab] switch  [↑↓] navigate  [Enter] select  [?] help  [q] quit",
-            Screen::EpisodeList => "[/] search  [Tab] switch  [↑↓] navigate  [f] filter  [Enter] select  [?] help  [q] quit",
+            Screen::ShowList => "[/] search  [Tab] switch  [↑↓] navigate  [Enter] select  [a] watchlist  [r] score  [?] help  [q] quit",
+            Screen::EpisodeList => "[/] search  [Tab] switch  [↑↓] navigate  [f] filter  [+] follow  [a] watchlist  [r] score  [Enter] select  [?] help  [q] quit",
             Screen::QualitySelect => "[↑↓] navigate  [Enter] select  [Bksp] back  [?] help  [q] quit",
             Screen::Playback => "[/] search  [Tab] switch  [n] next  [p] prev  [r] replay  [?] help  [q] quit",
             Screen::BatchSelect => "[↑↓] navigate  [Enter] select  [Bksp] back  [?] help  [q] quit",
+            Screen::Queue => "[↑↓] navigate  [Space] pause  [[/]] reorder  [r] retry  [x] remove  [c] clear done  [Bksp] back",
+            Screen::History => "[↑↓] navigate  [Enter] continue  [o] sort  [f] filter  [e] set episode  [m] finished  [r/n/#] score/notes/tags  [x] remove  [c] clear all  [Bksp] back",
+            Screen::Stats => "[Bksp] back  [?] help  [q] quit",
+            Screen::Watchlist => "[↑↓] navigate  [Enter] open  [Space] status  [p] priority  [r/n/#] score/notes/tags  [f] filter  [v] status filter  [x] remove  [Bksp] back",
             Screen::Loading => "[?] help  [q] quit",
         }
     };
 
-    let footer = Paragraph::new(help_text)
-        .style(Style::default().fg(app.colors.text_dim()))
-        .block(Block::default().borders(Borders::ALL));
+    let footer = match &app.status_message {
+        Some(status) => {
+            Paragraph::new(status.as_str()).style(Style::default().fg(app.colors.status()))
+        }
+        None => Paragraph::new(help_text).style(Style::default().fg(app.colors.text_dim())),
+    }
+    .block(Block::default().borders(Borders::ALL));
 
     frame.render_widget(footer, area);
 }
@@ -483,6 +583,312 @@ fn draw_batch_select(frame: &mut Frame, app: &mut App, area: Rect) {
     frame.render_stateful_widget(list, area, &mut app.batch_list_state);
 }
 
+fn draw_queue(frame: &mut Frame, app: &mut App, area: Rect) {
+    let title = if app.queue_paused {
+        "Download Queue (paused)".to_string()
+    } else if let Some(opens) = &app.download_window_opens {
+        format!(
+            "Download Queue ({} remaining, next window opens at {})",
+            app.queue_remaining, opens
+        )
+    } else {
+        format!("Download Queue ({} remaining)", app.queue_remaining)
+    };
+
+    if app.queue_items.is_empty() {
+        let empty = Paragraph::new("The download queue is empty")
+            .style(Style::default().fg(app.colors.text_dim()))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(empty, area);
+        return;
+    }
+
+    let items: Vec<ListItem> = app
+        .queue_items
+        .iter()
+        .map(|item| {
+            let color = match item.status {
+                QueueStatus::Pending => app.colors.text(),
+                QueueStatus::Active => app.colors.download(),
+                QueueStatus::Failed => app.colors.error(),
+                QueueStatus::Completed => app.colors.text_dim(),
+            };
+            ListItem::new(item.to_display()).style(Style::default().fg(color))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(
+            Style::default()
+                .bg(app.colors.selection_bg())
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+
+    frame.render_stateful_widget(list, area, &mut app.queue_list_state);
+}
+
+fn draw_history(frame: &mut Frame, app: &mut App, area: Rect) {
+    let mut title = format!("Watch History (by {})", app.history_sort.label());
+    if app.history_filter_active || !app.history_filter.is_empty() {
+        title.push_str(&format!(" - filter: {}", app.history_filter));
+        if app.history_filter_active {
+            title.push('_');
+        }
+    }
+
+    let view = app.get_history_view();
+    if view.is_empty() {
+        let message = if app.history_entries.is_empty() {
+            "No watch history"
+        } else {
+            "No shows match the filter"
+        };
+        let empty = Paragraph::new(message)
+            .style(Style::default().fg(app.colors.text_dim()))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(empty, area);
+        return;
+    }
+
+    let items: Vec<ListItem> = view
+        .iter()
+        .map(|r| {
+            let watched_on = chrono::DateTime::from_timestamp(r.timestamp as i64, 0)
+                .map(|t| {
+                    t.with_timezone(&chrono::Local)
+                        .format("%Y-%m-%d")
+                        .to_string()
+                })
+                .unwrap_or_default();
+            let mut details = format!(
+                "  ep {}  ·  {} watched  ·  {}  ·  {}",
+                r.episode,
+                r.watched.len(),
+                r.mode,
+                watched_on
+            );
+            if let Some(score) = app.annotations.get(&r.show_id).and_then(|a| a.score) {
+                details.push_str(&format!("  ·  {}/10", score));
+            }
+            ListItem::new(Line::from(vec![
+                Span::raw(r.show_name.clone()),
+                Span::styled(details, Style::default().fg(app.colors.text_dim())),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(
+            Style::default()
+                .bg(app.colors.selection_bg())
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+
+    frame.render_stateful_widget(list, area, &mut app.history_screen_state);
+}
+
+fn draw_watchlist(frame: &mut Frame, app: &mut App, area: Rect) {
+    let mut title = match app.watchlist_status_filter {
+        Some(status) => format!("Watchlist - {}", status.label()),
+        None => "Watchlist".to_string(),
+    };
+    if app.watchlist_filter_active || !app.watchlist_filter.is_empty() {
+        title.push_str(&format!(" - filter: {}", app.watchlist_filter));
+        if app.watchlist_filter_active {
+            title.push('_');
+        }
+    }
+
+    let view = app.get_watchlist_view();
+    if view.is_empty() {
+        let message = if app.watchlist.shows.is_empty() {
+            "The watchlist is empty. Press a on a show to add it."
+        } else {
+            "No shows match the filter"
+        };
+        let empty = Paragraph::new(message)
+            .style(Style::default().fg(app.colors.text_dim()))
+            .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(empty, area);
+        return;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(70), Constraint::Percentage(30)])
+        .split(area);
+
+    let items: Vec<ListItem> = view
+        .iter()
+        .map(|entry| {
+            let mut details = format!("  {}", entry.status.label());
+            if entry.priority != Priority::Normal {
+                details.push_str(&format!("  ·  {} priority", entry.priority.label()));
+            }
+            if let Some(score) = app.annotations.get(&entry.show_id).and_then(|a| a.score) {
+                details.push_str(&format!("  ·  {}/10", score));
+            }
+            ListItem::new(Line::from(vec![
+                Span::raw(entry.show_name.clone()),
+                Span::styled(details, Style::default().fg(app.colors.text_dim())),
+            ]))
+        })
+        .collect();
+    let details = app
+        .watchlist_state
+        .selected()
+        .and_then(|i| view.get(i))
+        .map(|entry| match app.annotations.get(&entry.show_id) {
+            Some(annotation) => annotation_details(Some(annotation))
+                .trim_start()
+                .to_string(),
+            None => "No score, notes or tags".to_string(),
+        })
+        .unwrap_or_default();
+
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(
+            Style::default()
+                .bg(app.colors.selection_bg())
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("> ");
+    frame.render_stateful_widget(list, chunks[0], &mut app.watchlist_state);
+
+    let details = Paragraph::new(details)
+        .block(Block::default().borders(Borders::ALL).title("Details"))
+        .wrap(Wrap { trim: true });
+    frame.render_widget(details, chunks[1]);
+}
+
+/// Score, tags and notes of a show, each on its own line after a newline.
+fn annotation_details(annotation: Option<&Annotation>) -> String {
+    let Some(annotation) = annotation else {
+        return String::new();
+    };
+    let mut details = String::new();
+    if let Some(score) = annotation.score {
+        details.push_str(&format!("\nScore: {}/10", score));
+    }
+    if !annotation.tags.is_empty() {
+        details.push_str(&format!("\nTags: {}", annotation.tags_text()));
+    }
+    if !annotation.notes.is_empty() {
+        details.push_str(&format!("\nNotes: {}", annotation.notes));
+    }
+    details
+}
+
+fn draw_stats(frame: &mut Frame, app: &App, area: Rect) {
+    let stats = &app.stats;
+    if stats.episodes == 0 {
+        let empty = Paragraph::new("No watched episodes yet")
+            .style(Style::default().fg(app.colors.text_dim()))
+            .block(Block::default().borders(Borders::ALL).title("Watch Stats"));
+        frame.render_widget(empty, area);
+        return;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(4),                                // Totals
+            Constraint::Length(6),                                // Weekly sparkline
+            Constraint::Min(8),                                   // Monthly chart
+            Constraint::Length(stats.top_shows.len() as u16 + 2), // Top shows
+        ])
+        .split(area);
+
+    let days = |n: u32| format!("{} day{}", n, if n == 1 { "" } else { "s" });
+    let totals = Paragraph::new(vec![
+        Line::from(format!(
+            "{} episodes  ·  ~{:.0} hours",
+            stats.episodes, stats.hours
+        )),
+        Line::from(format!(
+            "Streak: {} (longest {})",
+            days(stats.current_streak),
+            days(stats.longest_streak)
+        )),
+    ])
+    .block(Block::default().borders(Borders::ALL).title("Watch Stats"));
+    frame.render_widget(totals, chunks[0]);
+
+    let weekly: Vec<u64> = stats.weekly.iter().map(|p| p.episodes).collect();
+    let sparkline = Sparkline::default()
+        .data(&weekly)
+        .style(Style::default().fg(app.colors.border_focused()))
+        .block(Block::default().borders(Borders::ALL).title(format!(
+            "Episodes per week (last {} weeks, {} this week)",
+            weekly.len(),
+            weekly.last().copied().unwrap_or(0)
+        )));
+    frame.render_widget(sparkline, chunks[1]);
+
+    let labels: Vec<String> = stats
+        .monthly
+        .iter()
+        .map(|p| p.start.format("%b").to_string())
+        .collect();
+    let monthly: Vec<(&str, u64)> = labels
+        .iter()
+        .zip(&stats.monthly)
+        .map(|(label, p)| (label.as_str(), p.episodes))
+        .collect();
+    let chart = BarChart::default()
+        .data(&monthly)
+        .bar_width(4)
+        .bar_gap(1)
+        .bar_style(Style::default().fg(app.colors.highlight()))
+        .value_style(Style::default().add_modifier(Modifier::BOLD))
+        .label_style(Style::default().fg(app.colors.text_dim()))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Episodes per month"),
+        );
+    frame.render_widget(chart, chunks[2]);
+
+    let bottom = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(65), Constraint::Percentage(35)])
+        .split(chunks[3]);
+
+    let top: Vec<ListItem> = stats
+        .top_shows
+        .iter()
+        .map(|show| {
+            ListItem::new(Line::from(vec![
+                Span::raw(show.name.clone()),
+                Span::styled(
+                    format!("  {} eps  ·  ~{:.1} h", show.episodes, show.hours),
+                    Style::default().fg(app.colors.text_dim()),
+                ),
+            ]))
+        })
+        .collect();
+    let top = List::new(top).block(Block::default().borders(Borders::ALL).title("Most watched"));
+    frame.render_widget(top, bottom[0]);
+
+    let sub = stats.by_mode.get("sub").copied().unwrap_or(0);
+    let dub = stats.by_mode.get("dub").copied().unwrap_or(0);
+    let split = Gauge::default()
+        .ratio(sub as f64 / (sub + dub).max(1) as f64)
+        .label(format!("sub {}  ·  dub {}", sub, dub))
+        .gauge_style(
+            Style::default()
+                .fg(app.colors.border_focused())
+                .bg(app.colors.highlight()),
+        )
+        .block(Block::default().borders(Borders::ALL).title("Sub / dub"));
+    frame.render_widget(split, bottom[1]);
+}
+
 fn draw_error_popup(frame: &mut Frame, app: &App, error: &str) {
     let area = centered_rect(60, 20, frame.area());
     frame.render_widget(Clear, area);
@@ -540,11 +946,62 @@ fn draw_batch_confirm_popup(frame: &mut Frame, app: &App) {
     frame.render_widget(popup, area);
 }
 
+fn draw_episode_input_popup(frame: &mut Frame, input: &str) {
+    let area = centered_rect(50, 15, frame.area());
+    frame.render_widget(Clear, area);
+
+    let popup = Paragraph::new(format!("Last watched episode: {}", input))
+        .block(Block::default().borders(Borders::ALL).title("Set Episode"));
+
+    frame.render_widget(popup, area);
+
+    // Show cursor
+    frame.set_cursor_position((area.x + 23 + input.len() as u16, area.y + 1));
+}
+
+fn draw_annotation_input_popup(frame: &mut Frame, input: &AnnotationInput) {
+    let area = centered_rect(60, 15, frame.area());
+    frame.render_widget(Clear, area);
+
+    let title = format!("{} - {}", input.field.title(), input.show_name);
+    let popup = Paragraph::new(format!("> {}", input.text))
+        .block(Block::default().borders(Borders::ALL).title(title));
+
+    frame.render_widget(popup, area);
+
+    // Show cursor
+    frame.set_cursor_position((area.x + 3 + input.text.chars().count() as u16, area.y + 1));
+}
+
+fn draw_history_confirm_popup(frame: &mut Frame, app: &App) {
+    let area = centered_rect(50, 20, frame.area());
+    frame.render_widget(Clear, area);
+
+    let message = format!(
+        "{}\n\n[Y/Enter] Yes  [N/Esc] No",
+        app.get_pending_history_message()
+    );
+
+    let popup = Paragraph::new(message)
+        .style(Style::default().fg(app.colors.status()))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Confirm")
+                .border_style(Style::default().fg(app.colors.status())),
+        )
+        .wrap(Wrap { trim: true });
+
+    frame.render_widget(popup, area);
+}
+
 /// Draw the download progress modal overlay.
 ///
 /// Displays a centered modal showing:
 /// - Current progress (e.g., "[3/10]")
 /// - Current action message
+/// - When the next download window opens, if outside one
+/// - Status of each download worker
 /// - Activity log of recent download results
 fn draw_download_modal(frame: &mut Frame, app: &App) {
     let area = centered_rect(60, 50, frame.area());
@@ -569,6 +1026,20 @@ fn draw_download_modal(frame: &mut Frame, app: &App) {
         lines.push(String::new());
     }
 
+    // Outside the download windows nothing new starts
+    if let (Some(opens), None) = (&app.download_window_opens, app.download_finished_at) {
+        lines.push(format!("Waiting for the download window at {}", opens));
+        lines.push(String::new());
+    }
+
+    // Worker status
+    if !app.download_workers.is_empty() && app.download_finished_at.is_none() {
+        for (i, status) in app.download_workers.iter().enumerate() {
+            lines.push(format!("Worker {}: {}", i + 1, status));
+        }
+        lines.push(String::new());
+    }
+
     // Activity log
     if !app.download_log.is_empty() {
         lines.push("─── Activity Log ───".to_string());
@@ -620,6 +1091,11 @@ Global Commands
   Ctrl+Q      Force quit
   /           Focus search bar
   Tab         Switch panel focus
+  d           Open download queue
+  h           Open watch history
+  t           Open watch stats
+  w           Open watchlist
+  i           Incognito on/off
   q           Quit
 
 ";
@@ -671,6 +1147,75 @@ Batch Download
   Range       Download range (e.g., 1-12)
   Single      Download selected episode only
 
+";
+
+    let queue_keys = "\
+Download Queue
+──────────────
+  d           Open the queue
+  Space       Pause / resume downloads
+  [ / ]       Move item earlier / later
+  r           Retry failed items
+  x           Remove item
+  c           Clear completed items
+  Backspace   Go back
+
+";
+
+    let history_keys = "\
+Watch History
+─────────────
+  h           Open the history
+  Enter       Continue the show
+  o           Sort by recent / name / progress
+  f           Filter (name, #tag, score:N)
+  e           Set the last watched episode
+  m           Mark every episode watched
+  x           Remove the show
+  c           Clear the whole history
+  Backspace   Clear filter / go back
+
+";
+
+    let watchlist_keys = "\
+Watchlist
+─────────
+  a           Add the show (search results, episode list)
+  w           Open the watchlist
+  Enter       Open the show
+  Space       Cycle status
+  p           Cycle priority
+  f           Filter (name, #tag, score:N)
+  v           Show one status at a time
+  x           Remove the show
+  Backspace   Clear filter / go back
+
+";
+
+    let annotation_keys = "\
+Scores, Notes and Tags
+──────────────────────
+  r           Score the show (1-10)
+  n           Edit notes
+  #           Edit tags (separated by commas)
+
+";
+
+    let stats_keys = "\
+Watch Stats
+───────────
+  t           Open the stats
+  Backspace   Go back
+              (`anime-watcher stats` prints them)
+
+";
+
+    let follow_keys = "\
+Following
+─────────
+  +           Follow / unfollow this show
+              (`anime-watcher sync` downloads new episodes)
+
 ";
 
     let filter_keys = "\
@@ -698,15 +1243,21 @@ Episode Filter
         }
         Screen::ShowList => {
             let content = format!(
-                "{}{}{}{}Press ? to close",
-                global_keys, navigation_keys, sidebar_keys, search_keys
+                "{}{}{}{}{}Press ? to close",
+                global_keys, navigation_keys, annotation_keys, sidebar_keys, search_keys
             );
             ("Show List", content)
         }
         Screen::EpisodeList => {
             let content = format!(
-                "{}{}{}{}{}Press ? to close",
-                global_keys, navigation_keys, filter_keys, sidebar_keys, search_keys
+                "{}{}{}{}{}{}{}Press ? to close",
+                global_keys,
+                navigation_keys,
+                filter_keys,
+                follow_keys,
+                annotation_keys,
+                sidebar_keys,
+                search_keys
             );
             ("Episode List", content)
         }
@@ -728,6 +1279,28 @@ Episode Filter
             );
             ("Batch Download", content)
         }
+        Screen::Queue => {
+            let content = format!("{}{}Press ? to close", global_keys, queue_keys);
+            ("Download Queue", content)
+        }
+        Screen::History => {
+            let content = format!(
+                "{}{}{}Press ? to close",
+                global_keys, history_keys, annotation_keys
+            );
+            ("Watch History", content)
+        }
+        Screen::Watchlist => {
+            let content = format!(
+                "{}{}{}Press ? to close",
+                global_keys, watchlist_keys, annotation_keys
+            );
+            ("Watchlist", content)
+        }
+        Screen::Stats => {
+            let content = format!("{}{}Press ? to close", global_keys, stats_keys);
+            ("Watch Stats", content)
+        }
         Screen::Loading => {
             let content = format!("{}Press ? to close", global_keys);
             ("Loading", content)
@@ -1,16 +1,31 @@
 //! Application state management and input handling.
 
+use crate::annotations::{Annotations, Filter};
 use crate::config::{ColorScheme, Keybindings};
+use crate::download::{DownloadQueue, QueueItem};
+use crate::follow::FollowList;
+use crate::history::WatchRecord;
+use crate::stats::Stats;
 use crate::types::{Episode, Show, StreamSource};
+use crate::watchlist::{WatchStatus, Watchlist, WatchlistEntry};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::widgets::ListState;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 
-use super::types::{Action, Focus, Screen};
+use super::types::{
+    Action, AnnotationField, AnnotationInput, Focus, HistoryEdit, HistorySort, Screen,
+    WatchlistEdit,
+};
 
 /// Application state for the TUI.
 pub struct App {
     /// Current screen being displayed
     pub screen: Screen,
+    /// Profile in use, unless it is the default one
+    pub profile: Option<String>,
+    /// Whether watching is kept out of the history and trackers
+    pub incognito: bool,
     /// Current focus (sidebar or main)
     pub focus: Focus,
     /// Whether the app should quit
@@ -88,6 +103,58 @@ pub struct App {
     pub download_message: String,
     /// Download activity log
     pub download_log: Vec<String>,
+    /// Current status of each download worker
+    pub download_workers: Vec<String>,
+    /// Queue item ids belonging to the batch shown in the modal
+    pub download_batch: Vec<u64>,
+    /// When the current batch finished (modal closes shortly after)
+    pub download_finished_at: Option<Instant>,
+    /// Snapshot of the download queue
+    pub queue_items: Vec<QueueItem>,
+    /// Whether the download queue is paused
+    pub queue_paused: bool,
+    /// Number of queued episodes still waiting or downloading
+    pub queue_remaining: usize,
+    /// When the next download window opens ("HH:MM"), if outside one
+    pub download_window_opens: Option<String>,
+    /// IDs of followed shows
+    pub followed_shows: HashSet<String>,
+    /// Watched episode numbers of the selected show
+    pub watched_episodes: HashSet<i64>,
+    /// Saved playback positions (seconds) of the selected show's episodes
+    pub resume_positions: HashMap<i64, f64>,
+    /// Download queue list state
+    pub queue_list_state: ListState,
+    /// Every show in the watch history, for the history screen
+    pub history_entries: Vec<WatchRecord>,
+    /// Sort order of the history screen
+    pub history_sort: HistorySort,
+    /// History screen filter input
+    pub history_filter: String,
+    /// Whether the history filter is being typed
+    pub history_filter_active: bool,
+    /// History screen list state
+    pub history_screen_state: ListState,
+    /// Episode number being typed for "set episode", if active
+    pub history_episode_input: Option<String>,
+    /// History change waiting for confirmation
+    pub pending_history_edit: Option<HistoryEdit>,
+    /// Watch statistics, for the stats screen
+    pub stats: Stats,
+    /// Shows on the watchlist
+    pub watchlist: Watchlist,
+    /// Status shown in the watchlist screen, or all
+    pub watchlist_status_filter: Option<WatchStatus>,
+    /// Watchlist screen filter input
+    pub watchlist_filter: String,
+    /// Whether the watchlist filter is being typed
+    pub watchlist_filter_active: bool,
+    /// Watchlist screen list state
+    pub watchlist_state: ListState,
+    /// Scores, notes and tags of shows
+    pub annotations: Annotations,
+    /// Score, notes or tags being typed for a show, if active
+    pub annotation_input: Option<AnnotationInput>,
 }
 
 impl App {
@@ -104,6 +171,8 @@ impl App {
 
         Self {
             screen: Screen::Startup,
+            profile: None,
+            incognito: false,
             focus: Focus::Main,
             should_quit: false,
             search_input: String::new(),
@@ -142,6 +211,32 @@ impl App {
             download_total: 0,
             download_message: String::new(),
             download_log: Vec::new(),
+            download_workers: Vec::new(),
+            download_batch: Vec::new(),
+            download_finished_at: None,
+            queue_items: Vec::new(),
+            queue_paused: false,
+            queue_remaining: 0,
+            download_window_opens: None,
+            followed_shows: HashSet::new(),
+            watched_episodes: HashSet::new(),
+            resume_positions: HashMap::new(),
+            queue_list_state: ListState::default(),
+            history_entries: Vec::new(),
+            history_sort: HistorySort::default(),
+            history_filter: String::new(),
+            history_filter_active: false,
+            history_screen_state: ListState::default(),
+            history_episode_input: None,
+            pending_history_edit: None,
+            stats: Stats::default(),
+            watchlist: Watchlist::new(),
+            watchlist_status_filter: None,
+            watchlist_filter: String::new(),
+            watchlist_filter_active: false,
+            watchlist_state: ListState::default(),
+            annotations: Annotations::new(),
+            annotation_input: None,
         }
     }
 
@@ -151,20 +246,24 @@ impl App {
     ///
     /// # Arguments
     ///
-    /// * `total` - Total number of episodes to download
-    pub fn start_download_modal(&mut self, total: usize) {
+    /// * `batch` - Queue item ids of the episodes in the batch
+    /// * `workers` - Number of parallel download workers
+    pub fn start_download_modal(&mut self, batch: Vec<u64>, workers: usize) {
         self.show_download_modal = true;
         self.download_current = 0;
-        self.download_total = total;
+        self.download_total = batch.len();
+        self.download_batch = batch;
         self.download_message = String::new();
         self.download_log.clear();
+        self.download_workers = vec!["idle".to_string(); workers];
+        self.download_finished_at = None;
     }
 
     /// Update the current download progress displayed in the modal.
     ///
     /// # Arguments
     ///
-    /// * `current` - Current episode index (1-based)
+    /// * `current` - Number of episodes finished so far
     /// * `message` - Status message to display (e.g., "Downloading Episode 5...")
     pub fn update_download_progress(&mut self, current: usize, message: &str) {
         self.download_current = current;
@@ -186,9 +285,93 @@ impl App {
         }
     }
 
+    /// Update the status line shown for a download worker.
+    ///
+    /// # Arguments
+    ///
+    /// * `worker` - Worker index (0-based)
+    /// * `message` - Status to display (e.g., "Ep 5: downloading 1080p")
+    pub fn set_worker_status(&mut self, worker: usize, message: &str) {
+        if let Some(status) = self.download_workers.get_mut(worker) {
+            *status = message.to_string();
+        }
+    }
+
     /// Close the download modal and return to normal view.
     pub fn close_download_modal(&mut self) {
         self.show_download_modal = false;
+        self.download_finished_at = None;
+        self.download_batch.clear();
+    }
+
+    /// Update the set of followed shows.
+    pub fn set_follows(&mut self, follows: &FollowList) {
+        self.followed_shows = follows.shows.keys().cloned().collect();
+    }
+
+    /// Update the watchlist shown in the watchlist screen.
+    pub fn set_watchlist(&mut self, watchlist: &Watchlist) {
+        self.watchlist = watchlist.clone();
+        self.clamp_watchlist_selection();
+    }
+
+    /// Update the scores, notes and tags shown for shows.
+    pub fn set_annotations(&mut self, annotations: &Annotations) {
+        self.annotations = annotations.clone();
+        self.clamp_history_selection();
+        self.clamp_watchlist_selection();
+    }
+
+    /// Watchlist entries with the chosen status that match the filter.
+    pub fn get_watchlist_view(&self) -> Vec<&WatchlistEntry> {
+        let filter = Filter::parse(&self.watchlist_filter);
+        self.watchlist
+            .sorted(self.watchlist_status_filter)
+            .into_iter()
+            .filter(|e| filter.matches(&e.show_name, self.annotations.get(&e.show_id)))
+            .collect()
+    }
+
+    /// Keep the watchlist selection within the visible entries.
+    fn clamp_watchlist_selection(&mut self) {
+        let len = self.get_watchlist_view().len();
+        if len == 0 {
+            self.watchlist_state.select(None);
+        } else {
+            let selected = self.watchlist_state.selected().unwrap_or(0);
+            self.watchlist_state.select(Some(selected.min(len - 1)));
+        }
+    }
+
+    /// Update the watched markers from the selected show's history record.
+    pub fn set_watched(&mut self, record: Option<&WatchRecord>) {
+        self.watched_episodes = record
+            .map(|r| r.watched.keys().copied().collect())
+            .unwrap_or_default();
+        self.resume_positions = record
+            .map(|r| r.positions.clone().into_iter().collect())
+            .unwrap_or_default();
+    }
+
+    /// Whether the selected show is followed.
+    pub fn is_following_selected(&self) -> bool {
+        self.selected_show
+            .as_ref()
+            .is_some_and(|show| self.followed_shows.contains(&show.id))
+    }
+
+    /// Update the queue snapshot shown in the queue view and header.
+    pub fn set_queue(&mut self, queue: &DownloadQueue) {
+        self.queue_items = queue.items.clone();
+        self.queue_paused = queue.paused;
+        self.queue_remaining = queue.remaining();
+        let selected = self.queue_list_state.selected().unwrap_or(0);
+        if self.queue_items.is_empty() {
+            self.queue_list_state.select(None);
+        } else {
+            self.queue_list_state
+                .select(Some(selected.min(self.queue_items.len() - 1)));
+        }
     }
 
     /// Get filtered episodes based on current filter.
@@ -247,10 +430,92 @@ impl App {
 
     /// Set history records for the continue menu.
     pub fn set_history(&mut self, records: Vec<(String, String, i64, String)>) {
-        let has_records = !records.is_empty();
         self.history_records = records;
-        if has_records {
-            self.history_list_state.select(Some(0));
+        if self.history_records.is_empty() {
+            self.history_list_state.select(None);
+        } else {
+            let selected = self.history_list_state.selected().unwrap_or(0);
+            self.history_list_state
+                .select(Some(selected.min(self.history_records.len() - 1)));
+        }
+    }
+
+    /// Update the records shown in the history screen.
+    pub fn set_history_entries(&mut self, records: Vec<WatchRecord>) {
+        self.history_entries = records;
+        self.clamp_history_selection();
+    }
+
+    /// Keep the history screen selection within the visible records.
+    fn clamp_history_selection(&mut self) {
+        let len = self.get_history_view().len();
+        if len == 0 {
+            self.history_screen_state.select(None);
+        } else {
+            let selected = self.history_screen_state.selected().unwrap_or(0);
+            self.history_screen_state
+                .select(Some(selected.min(len - 1)));
+        }
+    }
+
+    /// History records matching the filter, in the chosen sort order.
+    pub fn get_history_view(&self) -> Vec<&WatchRecord> {
+        let filter = Filter::parse(&self.history_filter);
+        let mut records: Vec<&WatchRecord> = self
+            .history_entries
+            .iter()
+            .filter(|r| filter.matches(&r.show_name, self.annotations.get(&r.show_id)))
+            .collect();
+
+        match self.history_sort {
+            HistorySort::Recent => records.sort_by_key(|r| std::cmp::Reverse(r.timestamp)),
+            HistorySort::Name => {
+                records.sort_by_cached_key(|r| r.show_name.to_lowercase());
+            }
+            HistorySort::Progress => records.sort_by_key(|r| {
+                (
+                    std::cmp::Reverse(r.watched.len()),
+                    std::cmp::Reverse(r.timestamp),
+                )
+            }),
+        }
+        records
+    }
+
+    /// The record selected in the history screen.
+    fn selected_history_record(&self) -> Option<&WatchRecord> {
+        let i = self.history_screen_state.selected()?;
+        self.get_history_view().get(i).copied()
+    }
+
+    /// Describe the pending history change for the confirmation popup.
+    pub fn get_pending_history_message(&self) -> String {
+        let name = |show_id: &str, mode: &str| {
+            let name = self
+                .history_entries
+                .iter()
+                .find(|r| r.show_id == show_id && r.mode == mode)
+                .map(|r| r.show_name.as_str())
+                .unwrap_or(show_id);
+            format!("{} ({})", name, mode)
+        };
+        match &self.pending_history_edit {
+            Some(HistoryEdit::Delete(id, mode)) => {
+                format!("Remove {} from the history?", name(id, mode))
+            }
+            Some(HistoryEdit::SetEpisode(id, mode, ep)) => format!(
+                "Set {} to episode {}? Later episodes are marked unwatched.",
+                name(id, mode),
+                ep
+            ),
+            Some(HistoryEdit::MarkFinished(id, mode)) => {
+                format!("Mark every episode of {} watched?", name(id, mode))
+            }
+            Some(HistoryEdit::Clear) => format!(
+                "Clear the whole watch history ({} shows)?",
+                self.history_entries.len()
+            ),
+            None => String::new(),
         }
     }
 
@@ -276,14 +541,16 @@ impl App {
         self.error_message = None;
     }
 
-    /// Set status message (reserved for future use).
-    #[allow(dead_code)]
+    /// Set status message.
     pub fn set_status(&mut self, message: &str) {
         self.status_message = Some(message.to_string());
     }
 
     /// Handle keyboard input and return an action.
     pub fn handle_input(&mut self, key: KeyEvent) -> Action {
+        // Status messages last until the next key press
+        self.status_message = None;
+
         // Global quit with Ctrl+C or Ctrl+Q
         if key.modifiers.contains(KeyModifiers::CONTROL) {
             match key.code {
@@ -295,6 +562,14 @@ impl App {
             }
         }
 
+        // Downloads run in the background; the modal can be hidden with back
+        if self.show_download_modal {
+            if self.keybindings.matches(&self.keybindings.back, &key) {
+                self.close_download_modal();
+            }
+            return Action::None;
+        }
+
         // Handle help modal
         if self.show_help {
             if key.code == KeyCode::Esc
@@ -322,6 +597,23 @@ impl App {
             return self.handle_batch_confirm(key);
         }
 
+        // History screen popups
+        if self.history_episode_input.is_some() {
+            return self.handle_history_episode_input(key);
+        }
+        if self.pending_history_edit.is_some() {
+            return self.handle_history_confirm(key);
+        }
+        if self.history_filter_active {
+            return self.handle_history_filter_input(key);
+        }
+        if self.watchlist_filter_active {
+            return self.handle_watchlist_filter_input(key);
+        }
+        if self.annotation_input.is_some() {
+            return self.handle_annotation_input(key);
+        }
+
         // Handle search bar input when focused
         if self.search_focused {
             return self.handle_search_bar_input(key);
@@ -358,6 +650,61 @@ impl App {
             return Action::None;
         }
 
+        // Open the download queue from anywhere but the search screen,
+        // where keys are typed into the query
+        if !matches!(self.screen, Screen::Queue | Screen::Search)
+            && self.keybindings.matches(&self.keybindings.queue, &key)
+        {
+            self.focus = Focus::Main;
+            self.screen = Screen::Queue;
+            if self.queue_list_state.selected().is_none() && !self.queue_items.is_empty() {
+                self.queue_list_state.select(Some(0));
+            }
+            return Action::None;
+        }
+
+        // Open the watch history from anywhere but the search screen
+        if !matches!(self.screen, Screen::History | Screen::Search)
+            && self.keybindings.matches(&self.keybindings.history, &key)
+        {
+            self.focus = Focus::Main;
+            self.screen = Screen::History;
+            self.clamp_history_selection();
+            return Action::None;
+        }
+
+        // Open the watchlist from anywhere but the search screen
+        if !matches!(self.screen, Screen::Watchlist | Screen::Search)
+            && self.keybindings.matches(&self.keybindings.watchlist, &key)
+        {
+            self.focus = Focus::Main;
+            self.screen = Screen::Watchlist;
+            self.clamp_watchlist_selection();
+            return Action::None;
+        }
+
+        // Toggle incognito mode from anywhere but the search screen
+        if !matches!(self.screen, Screen::Search)
+            && self.keybindings.matches(&self.keybindings.incognito, &key)
+        {
+            self.incognito = !self.incognito;
+            self.set_status(if self.incognito {
+                "Incognito: episodes you watch won't be recorded or synced"
+            } else {
+                "Incognito off: watching is recorded again"
+            });
+            return Action::None;
+        }
+
+        // Open the watch statistics from anywhere but the search screen
+        if !matches!(self.screen, Screen::Stats | Screen::Search)
+            && self.keybindings.matches(&self.keybindings.stats, &key)
+        {
+            self.focus = Focus::Main;
+            self.screen = Screen::Stats;
+            return Action::None;
+        }
+
         // Handle sidebar input when focused
         if self.focus == Focus::Sidebar {
             return self.handle_sidebar_input(key);
@@ -371,6 +718,10 @@ impl App {
             Screen::QualitySelect => self.handle_quality_input(key),
             Screen::Playback => self.handle_playback_input(key),
             Screen::BatchSelect => self.handle_batch_input(key),
+            Screen::Queue => self.handle_queue_input(key),
+            Screen::History => self.handle_history_input(key),
+            Screen::Stats => self.handle_stats_input(key),
+            Screen::Watchlist => self.handle_watchlist_input(key),
             Screen::Loading => {
                 // Allow quit during loading
                 if self.keybindings.matches(&self.keybindings.quit, &key) {
@@ -428,9 +779,9 @@ impl App {
             Action::None
         } else if self.keybindings.matches(&self.keybindings.select, &key) {
             if let Some(i) = self.history_list_state.selected() {
-                if i < self.history_records.len() {
+                if let Some((show_id, _, _, mode)) = self.history_records.get(i) {
                     self.focus = Focus::Main;
-                    return Action::ContinueFromHistory(i);
+                    return Action::ContinueFromHistory(show_id.clone(), mode.clone());
                 }
             }
             Action::None
@@ -475,8 +826,12 @@ impl App {
                     Some(0) => Action::NewSearch,
                     _ => Action::NewSearch,
                 }
-            } else if let Some(i) = self.history_list_state.selected() {
-                Action::ContinueFromHistory(i)
+            } else if let Some((show_id, _, _, mode)) = self
+                .history_list_state
+                .selected()
+                .and_then(|i| self.history_records.get(i))
+            {
+                Action::ContinueFromHistory(show_id.clone(), mode.clone())
             } else {
                 Action::NewSearch
             }
@@ -542,6 +897,25 @@ impl App {
             } else {
                 Action::None
             }
+        } else if self
+            .keybindings
+            .matches(&self.keybindings.add_to_watchlist, &key)
+        {
+            match self
+                .show_list_state
+                .selected()
+                .and_then(|i| self.shows.get(i))
+            {
+                Some(show) => Action::EditWatchlist(WatchlistEdit::Add(show.clone())),
+                None => Action::None,
+            }
+        } else if self.start_annotation_input(&key, |app| {
+            app.show_list_state
+                .selected()
+                .and_then(|i| app.shows.get(i))
+                .map(|show| (show.id.clone(), show.name.clone()))
+        }) {
+            Action::None
         } else if self.keybindings.matches(&self.keybindings.search, &key) {
             self.screen = Screen::Search;
             Action::None
@@ -586,6 +960,22 @@ impl App {
         } else if self.keybindings.matches(&self.keybindings.filter, &key) {
             self.episode_filter_active = true;
             Action::None
+        } else if self.keybindings.matches(&self.keybindings.follow, &key) {
+            Action::ToggleFollow
+        } else if self
+            .keybindings
+            .matches(&self.keybindings.add_to_watchlist, &key)
+        {
+            match &self.selected_show {
+                Some(show) => Action::EditWatchlist(WatchlistEdit::Add(show.clone())),
+                None => Action::None,
+            }
+        } else if self.start_annotation_input(&key, |app| {
+            app.selected_show
+                .as_ref()
+                .map(|show| (show.id.clone(), show.name.clone()))
+        }) {
+            Action::None
         } else if self.keybindings.matches(&self.keybindings.search, &key) {
             self.screen = Screen::Search;
             Action::None
@@ -752,6 +1142,394 @@ impl App {
         }
     }
 
+    fn handle_queue_input(&mut self, key: KeyEvent) -> Action {
+        let selected = self
+            .queue_list_state
+            .selected()
+            .and_then(|i| self.queue_items.get(i).map(|item| (i, item.id)));
+
+        if self.keybindings.matches(&self.keybindings.up, &key) {
+            if let Some((i, _)) = selected {
+                if i > 0 {
+                    self.queue_list_state.select(Some(i - 1));
+                }
+            }
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.down, &key) {
+            if let Some((i, _)) = selected {
+                if i + 1 < self.queue_items.len() {
+                    self.queue_list_state.select(Some(i + 1));
+                }
+            }
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.pause, &key) {
+            Action::QueueTogglePause
+        } else if self.keybindings.matches(&self.keybindings.move_up, &key) {
+            match selected {
+                Some((i, id)) if i > 0 => {
+                    self.queue_list_state.select(Some(i - 1));
+                    Action::QueueMove(id, true)
+                }
+                _ => Action::None,
+            }
+        } else if self.keybindings.matches(&self.keybindings.move_down, &key) {
+            match selected {
+                Some((i, id)) if i + 1 < self.queue_items.len() => {
+                    self.queue_list_state.select(Some(i + 1));
+                    Action::QueueMove(id, false)
+                }
+                _ => Action::None,
+            }
+        } else if self.keybindings.matches(&self.keybindings.retry, &key) {
+            Action::QueueRetryFailed
+        } else if self.keybindings.matches(&self.keybindings.delete, &key) {
+            match selected {
+                Some((_, id)) => Action::QueueRemove(id),
+                None => Action::None,
+            }
+        } else if self.keybindings.matches(&self.keybindings.clear, &key) {
+            Action::QueueClearCompleted
+        } else if self.keybindings.matches(&self.keybindings.back, &key) {
+            self.screen = if self.selected_show.is_some() {
+                Screen::EpisodeList
+            } else {
+                Screen::Startup
+            };
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.quit, &key) {
+            self.should_quit = true;
+            Action::Quit
+        } else {
+            Action::None
+        }
+    }
+
+    fn handle_history_input(&mut self, key: KeyEvent) -> Action {
+        let len = self.get_history_view().len();
+        let selected = self
+            .selected_history_record()
+            .map(|r| (r.show_id.clone(), r.mode.clone()));
+
+        if self.keybindings.matches(&self.keybindings.up, &key) {
+            let i = self.history_screen_state.selected().unwrap_or(0);
+            if i > 0 {
+                self.history_screen_state.select(Some(i - 1));
+            }
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.down, &key) {
+            let i = self.history_screen_state.selected().unwrap_or(0);
+            if i + 1 < len {
+                self.history_screen_state.select(Some(i + 1));
+            }
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.select, &key) {
+            match selected {
+                Some((show_id, mode)) => Action::ContinueFromHistory(show_id, mode),
+                None => Action::None,
+            }
+        } else if self.keybindings.matches(&self.keybindings.sort, &key) {
+            self.history_sort = self.history_sort.next();
+            self.history_screen_state
+                .select(if len > 0 { Some(0) } else { None });
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.filter, &key) {
+            self.history_filter_active = true;
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.delete, &key) {
+            self.pending_history_edit = selected.map(|(id, mode)| HistoryEdit::Delete(id, mode));
+            Action::None
+        } else if self
+            .keybindings
+            .matches(&self.keybindings.set_episode, &key)
+        {
+            if selected.is_some() {
+                self.history_episode_input = Some(String::new());
+            }
+            Action::None
+        } else if self
+            .keybindings
+            .matches(&self.keybindings.mark_finished, &key)
+        {
+            self.pending_history_edit =
+                selected.map(|(id, mode)| HistoryEdit::MarkFinished(id, mode));
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.clear, &key) {
+            if !self.history_entries.is_empty() {
+                self.pending_history_edit = Some(HistoryEdit::Clear);
+            }
+            Action::None
+        } else if self.start_annotation_input(&key, |app| {
+            app.selected_history_record()
+                .map(|r| (r.show_id.clone(), r.show_name.clone()))
+        }) {
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.back, &key) {
+            if !self.history_filter.is_empty() {
+                self.history_filter.clear();
+                self.clamp_history_selection();
+            } else {
+                self.screen = if self.selected_show.is_some() {
+                    Screen::EpisodeList
+                } else {
+                    Screen::Startup
+                };
+            }
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.quit, &key) {
+            self.should_quit = true;
+            Action::Quit
+        } else {
+            Action::None
+        }
+    }
+
+    fn handle_stats_input(&mut self, key: KeyEvent) -> Action {
+        if self.keybindings.matches(&self.keybindings.back, &key) {
+            self.screen = if self.selected_show.is_some() {
+                Screen::EpisodeList
+            } else {
+                Screen::Startup
+            };
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.quit, &key) {
+            self.should_quit = true;
+            Action::Quit
+        } else {
+            Action::None
+        }
+    }
+
+    fn handle_watchlist_input(&mut self, key: KeyEvent) -> Action {
+        let len = self.get_watchlist_view().len();
+        let selected = self
+            .watchlist_state
+            .selected()
+            .and_then(|i| self.get_watchlist_view().get(i).map(|e| (*e).clone()));
+
+        if self.keybindings.matches(&self.keybindings.up, &key) {
+            let i = self.watchlist_state.selected().unwrap_or(0);
+            if i > 0 {
+                self.watchlist_state.select(Some(i - 1));
+            }
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.down, &key) {
+            let i = self.watchlist_state.selected().unwrap_or(0);
+            if i + 1 < len {
+                self.watchlist_state.select(Some(i + 1));
+            }
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.select, &key) {
+            match selected {
+                Some(entry) => Action::OpenShow(entry.to_show()),
+                None => Action::None,
+            }
+        } else if self.keybindings.matches(&self.keybindings.set_status, &key) {
+            match selected {
+                Some(entry) => Action::EditWatchlist(WatchlistEdit::SetStatus(
+                    entry.show_id,
+                    entry.status.next(),
+                )),
+                None => Action::None,
+            }
+        } else if self
+            .keybindings
+            .matches(&self.keybindings.set_priority, &key)
+        {
+            match selected {
+                Some(entry) => Action::EditWatchlist(WatchlistEdit::SetPriority(
+                    entry.show_id,
+                    entry.priority.next(),
+                )),
+                None => Action::None,
+            }
+        } else if self.start_annotation_input(&key, |_| {
+            selected
+                .as_ref()
+                .map(|e| (e.show_id.clone(), e.show_name.clone()))
+        }) {
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.filter, &key) {
+            self.watchlist_filter_active = true;
+            Action::None
+        } else if self
+            .keybindings
+            .matches(&self.keybindings.status_filter, &key)
+        {
+            // Cycle through each status, then back to all of them
+            self.watchlist_status_filter = match self.watchlist_status_filter {
+                None => Some(WatchStatus::ALL[0]),
+                Some(status) if status.next() == WatchStatus::ALL[0] => None,
+                Some(status) => Some(status.next()),
+            };
+            self.watchlist_state.select(None);
+            self.clamp_watchlist_selection();
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.delete, &key) {
+            match selected {
+                Some(entry) => Action::EditWatchlist(WatchlistEdit::Remove(entry.show_id)),
+                None => Action::None,
+            }
+        } else if self.keybindings.matches(&self.keybindings.back, &key) {
+            if !self.watchlist_filter.is_empty() {
+                self.watchlist_filter.clear();
+                self.clamp_watchlist_selection();
+            } else {
+                self.screen = if self.selected_show.is_some() {
+                    Screen::EpisodeList
+                } else {
+                    Screen::Startup
+                };
+            }
+            Action::None
+        } else if self.keybindings.matches(&self.keybindings.quit, &key) {
+            self.should_quit = true;
+            Action::Quit
+        } else {
+            Action::None
+        }
+    }
+
+    /// Open the input for the score, notes or tags of a show if the key
+    /// is bound to one. `target` gives the ID and name of the show the
+    /// current screen has selected.
+    ///
+    /// Returns whether the key was an annotation key.
+    fn start_annotation_input(
+        &mut self,
+        key: &KeyEvent,
+        target: impl FnOnce(&Self) -> Option<(String, String)>,
+    ) -> bool {
+        let field = if self.keybindings.matches(&self.keybindings.rate, key) {
+            AnnotationField::Score
+        } else if self.keybindings.matches(&self.keybindings.edit_notes, key) {
+            AnnotationField::Notes
+        } else if self.keybindings.matches(&self.keybindings.edit_tags, key) {
+            AnnotationField::Tags
+        } else {
+            return false;
+        };
+        if let Some((show_id, show_name)) = target(self) {
+            let annotation = self.annotations.get(&show_id);
+            let text = match field {
+                AnnotationField::Score => annotation
+                    .and_then(|a| a.score)
+                    .map(|score| score.to_string())
+                    .unwrap_or_default(),
+                AnnotationField::Notes => annotation.map(|a| a.notes.clone()).unwrap_or_default(),
+                AnnotationField::Tags => annotation.map(|a| a.tags_text()).unwrap_or_default(),
+            };
+            self.annotation_input = Some(AnnotationInput {
+                show_id,
+                show_name,
+                field,
+                text,
+            });
+        }
+        true
+    }
+
+    fn handle_annotation_input(&mut self, key: KeyEvent) -> Action {
+        let Some(input) = &mut self.annotation_input else {
+            return Action::None;
+        };
+        match key.code {
+            KeyCode::Enter => match self.annotation_input.take() {
+                Some(input) => Action::Annotate(input),
+                None => Action::None,
+            },
+            KeyCode::Esc => {
+                self.annotation_input = None;
+                Action::None
+            }
+            KeyCode::Backspace => {
+                input.text.pop();
+                Action::None
+            }
+            KeyCode::Char(c) => {
+                input.text.push(c);
+                Action::None
+            }
+            _ => Action::None,
+        }
+    }
+
+    fn handle_watchlist_filter_input(&mut self, key: KeyEvent) -> Action {
+        match key.code {
+            KeyCode::Enter | KeyCode::Esc => {
+                self.watchlist_filter_active = false;
+            }
+            KeyCode::Char(c) => {
+                self.watchlist_filter.push(c);
+                self.watchlist_state.select(Some(0));
+            }
+            KeyCode::Backspace => {
+                self.watchlist_filter.pop();
+                self.watchlist_state.select(Some(0));
+            }
+            _ => {}
+        }
+        self.clamp_watchlist_selection();
+        Action::None
+    }
+
+    fn handle_history_filter_input(&mut self, key: KeyEvent) -> Action {
+        match key.code {
+            KeyCode::Enter | KeyCode::Esc => {
+                self.history_filter_active = false;
+            }
+            KeyCode::Char(c) => {
+                self.history_filter.push(c);
+                self.history_screen_state.select(Some(0));
+            }
+            KeyCode::Backspace => {
+                self.history_filter.pop();
+                self.history_screen_state.select(Some(0));
+            }
+            _ => {}
+        }
+        self.clamp_history_selection();
+        Action::None
+    }
+
+    fn handle_history_episode_input(&mut self, key: KeyEvent) -> Action {
+        let Some(input) = self.history_episode_input.as_mut() else {
+            return Action::None;
+        };
+        match key.code {
+            KeyCode::Enter => match input.parse::<i64>() {
+                Ok(episode) if episode >= 0 => {
+                    self.history_episode_input = None;
+                    self.pending_history_edit = self.selected_history_record().map(|r| {
+                        HistoryEdit::SetEpisode(r.show_id.clone(), r.mode.clone(), episode)
+                    });
+                }
+                _ => self.set_error("Enter an episode number"),
+            },
+            KeyCode::Char(c) if c.is_ascii_digit() => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Esc => self.history_episode_input = None,
+            _ => {}
+        }
+        Action::None
+    }
+
+    fn handle_history_confirm(&mut self, key: KeyEvent) -> Action {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => self
+                .pending_history_edit
+                .take()
+                .map(Action::EditHistory)
+                .unwrap_or(Action::None),
+            KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                self.pending_history_edit = None;
+                Action::None
+            }
+            _ => Action::None,
+        }
+    }
+
     fn handle_range_input(&mut self, key: KeyEvent) -> Action {
         match key.code {
             KeyCode::Enter => {
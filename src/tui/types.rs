@@ -1,5 +1,8 @@
 //! TUI type definitions for screens, focus, and actions.
 
+use crate::types::Show;
+use crate::watchlist::{Priority, WatchStatus};
+
 /// The current screen/view of the application.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Screen {
@@ -17,6 +20,14 @@ pub enum Screen {
     Playback,
     /// Batch download options
     BatchSelect,
+    /// Persistent download queue
+    Queue,
+    /// Watch history management
+    History,
+    /// Watch statistics
+    Stats,
+    /// Watchlist of planned and ongoing shows
+    Watchlist,
     /// Loading/waiting for API response
     Loading,
 }
@@ -56,8 +67,8 @@ pub enum Action {
     Replay,
     /// Go back to episode selection
     BackToEpisodes,
-    /// Continue from history
-    ContinueFromHistory(usize),
+    /// Continue a show from history, by show ID and translation mode
+    ContinueFromHistory(String, String),
     /// Start new search
     NewSearch,
     /// Batch download all
@@ -66,4 +77,119 @@ pub enum Action {
     BatchRange(i64, i64),
     /// Single download
     BatchSingle,
+    /// Pause or resume the download queue
+    QueueTogglePause,
+    /// Move a queue item earlier (true) or later (false)
+    QueueMove(u64, bool),
+    /// Retry all failed queue items
+    QueueRetryFailed,
+    /// Remove a queue item
+    QueueRemove(u64),
+    /// Remove completed queue items
+    QueueClearCompleted,
+    /// Follow or unfollow the selected show
+    ToggleFollow,
+    /// Apply a confirmed change to the watch history
+    EditHistory(HistoryEdit),
+    /// Apply a change to the watchlist
+    EditWatchlist(WatchlistEdit),
+    /// Load the episodes of a show from the watchlist
+    OpenShow(Show),
+    /// Save a score, notes or tags typed for a show
+    Annotate(AnnotationInput),
+}
+
+/// A change to the watch history made from the history screen.
+///
+/// Shows are identified by show ID and translation mode.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HistoryEdit {
+    /// Remove a show
+    Delete(String, String),
+    /// Set the last watched episode of a show
+    SetEpisode(String, String, i64),
+    /// Mark every episode of a show watched
+    MarkFinished(String, String),
+    /// Remove every show
+    Clear,
+}
+
+/// A change to the watchlist, with shows identified by show ID.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatchlistEdit {
+    /// Add a show as planned
+    Add(Show),
+    /// Change the status of a show
+    SetStatus(String, WatchStatus),
+    /// Change the priority of a show
+    SetPriority(String, Priority),
+    /// Remove a show
+    Remove(String),
+}
+
+/// Which part of a show's annotation is being edited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnnotationField {
+    /// The personal score from 1 to 10
+    Score,
+    /// Free-form notes
+    Notes,
+    /// Custom tags
+    Tags,
+}
+
+impl AnnotationField {
+    /// Title of the input popup.
+    pub fn title(self) -> &'static str {
+        match self {
+            AnnotationField::Score => "Score (1-10, empty to clear)",
+            AnnotationField::Notes => "Notes",
+            AnnotationField::Tags => "Tags (separated by commas)",
+        }
+    }
+}
+
+/// A score, notes or tags being typed for a show.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnnotationInput {
+    /// Unique identifier for the show.
+    pub show_id: String,
+    /// Display name of the show.
+    pub show_name: String,
+    /// Field being edited.
+    pub field: AnnotationField,
+    /// Text typed so far.
+    pub text: String,
+}
+
+/// Sort order of the history screen.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum HistorySort {
+    /// Most recently watched first
+    #[default]
+    Recent,
+    /// Alphabetically by name
+    Name,
+    /// Most watched episodes first
+    Progress,
+}
+
+impl HistorySort {
+    /// The next sort order in the cycle.
+    pub fn next(self) -> Self {
+        match self {
+            HistorySort::Recent => HistorySort::Name,
+            HistorySort::Name => HistorySort::Progress,
+            HistorySort::Progress => HistorySort::Recent,
+        }
+    }
+
+    /// Label shown in the history screen title.
+    pub fn label(self) -> &'static str {
+        match self {
+            HistorySort::Recent => "recent",
+            HistorySort::Name => "name",
+            HistorySort::Progress => "progress",
+        }
+    }
 }
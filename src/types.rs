@@ -19,6 +19,14 @@ pub struct RawShow {
     /// Display name of the show.
     pub name: String,
 
+    /// English title, if different from the display name.
+    #[serde(default, rename = "englishName")]
+    pub english_name: Option<String>,
+
+    /// Title in the original language.
+    #[serde(default, rename = "nativeName")]
+    pub native_name: Option<String>,
+
     /// Map of translation type to episode count (e.g., "sub" -> 24, "dub" -> 12).
     #[serde(rename = "availableEpisodes")]
     pub available_episodes: HashMap<String, i64>,
@@ -34,6 +42,14 @@ pub struct Show {
     /// Display name of the show.
     pub name: String,
 
+    /// English title, if different from the display name.
+    #[serde(default, rename = "englishName")]
+    pub english_name: Option<String>,
+
+    /// Title in the original language.
+    #[serde(default, rename = "nativeName")]
+    pub native_name: Option<String>,
+
     /// Number of available episodes for the selected translation mode.
     #[serde(rename = "availableEpisodes")]
     pub available_episodes: i64,
@@ -50,6 +66,8 @@ impl Show {
     /// let show = Show {
     ///     id: "abc123".to_string(),
     ///     name: "My Anime".to_string(),
+    ///     english_name: None,
+    ///     native_name: None,
     ///     available_episodes: 24,
     /// };
     /// assert_eq!(show.to_display(), "My Anime (24 eps)");
@@ -110,6 +128,28 @@ pub struct StreamSource {
 
     /// URL to the video stream or embed page.
     pub url: String,
+
+    /// Name of the provider the source came from (e.g., "Default", "S-mp4").
+    pub provider: String,
+
+    /// Soft subtitle tracks the provider serves alongside the video.
+    pub subtitles: Vec<SubtitleTrack>,
+}
+
+/// A soft subtitle track for a stream source.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct SubtitleTrack {
+    /// Language code as given by the provider (e.g., "en").
+    #[serde(default)]
+    pub lang: String,
+
+    /// Human-readable track name (e.g., "English").
+    #[serde(default)]
+    pub label: String,
+
+    /// URL of the subtitle file (WebVTT, SRT or ASS).
+    #[serde(rename = "src")]
+    pub url: String,
 }
 
 impl StreamSource {
@@ -123,12 +163,16 @@ impl StreamSource {
     /// let source = StreamSource {
     ///     quality: 1080,
     ///     url: "https://example.com/video.mp4".to_string(),
+    ///     provider: "Default".to_string(),
+    ///     subtitles: Vec::new(),
     /// };
     /// assert_eq!(source.to_display(), "1080p");
     ///
     /// let unknown = StreamSource {
     ///     quality: 0,
     ///     url: "https://example.com/video.mp4".to_string(),
+    ///     provider: "Default".to_string(),
+    ///     subtitles: Vec::new(),
     /// };
     /// assert_eq!(unknown.to_display(), "Unknown quality");
     /// ```
@@ -141,6 +185,76 @@ impl StreamSource {
     }
 }
 
+/// Descriptive metadata for a show, used for media-server sidecar files.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct ShowDetails {
+    /// Unique identifier for the show.
+    #[serde(rename = "_id")]
+    pub id: String,
+
+    /// Display name of the show.
+    pub name: String,
+
+    /// English title, if known.
+    #[serde(default, rename = "englishName")]
+    pub english_name: Option<String>,
+
+    /// Title in the original language.
+    #[serde(default, rename = "nativeName")]
+    pub native_name: Option<String>,
+
+    /// Synopsis; may contain HTML markup.
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// Poster image URL.
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+
+    /// Date the show started airing.
+    #[serde(default, rename = "airedStart")]
+    pub aired_start: Option<AiredDate>,
+
+    /// Genre names.
+    #[serde(default)]
+    pub genres: Vec<String>,
+}
+
+/// A possibly partial air date as returned by the AllAnime API.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct AiredDate {
+    /// Year, if known.
+    #[serde(default)]
+    pub year: Option<i32>,
+
+    /// Month (1-12), if known.
+    #[serde(default)]
+    pub month: Option<u32>,
+
+    /// Day of the month, if known.
+    #[serde(default)]
+    pub date: Option<u32>,
+}
+
+impl AiredDate {
+    /// Format as `YYYY-MM-DD`, or `None` if any part is missing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use anime_watcher::types::AiredDate;
+    ///
+    /// let date = AiredDate { year: Some(2023), month: Some(9), date: Some(29) };
+    /// assert_eq!(date.to_iso().as_deref(), Some("2023-09-29"));
+    /// ```
+    pub fn to_iso(&self) -> Option<String> {
+        Some(format!(
+            "{:04}-{:02}-{:02}",
+            self.year?, self.month?, self.date?
+        ))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -150,6 +264,8 @@ mod tests {
         let show = Show {
             id: "abc123".to_string(),
             name: "Test Anime".to_string(),
+            english_name: None,
+            native_name: None,
             available_episodes: 12,
         };
         assert_eq!(show.to_display(), "Test Anime (12 eps)");
@@ -160,6 +276,8 @@ mod tests {
         let show = Show {
             id: "xyz".to_string(),
             name: "New Show".to_string(),
+            english_name: None,
+            native_name: None,
             available_episodes: 0,
         };
         assert_eq!(show.to_display(), "New Show (0 eps)");
@@ -200,6 +318,8 @@ mod tests {
         let source = StreamSource {
             quality: 1080,
             url: "https://example.com/video.mp4".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         };
         assert_eq!(source.quality, 1080);
         assert_eq!(source.url, "https://example.com/video.mp4");
@@ -210,6 +330,8 @@ mod tests {
         let source = StreamSource {
             quality: 1080,
             url: "https://example.com/video.mp4".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         };
         assert_eq!(source.to_display(), "1080p");
     }
@@ -219,6 +341,8 @@ mod tests {
         let source = StreamSource {
             quality: 720,
             url: "https://example.com/video.mp4".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         };
         assert_eq!(source.to_display(), "720p");
     }
@@ -228,6 +352,8 @@ mod tests {
         let source = StreamSource {
             quality: 0,
             url: "https://example.com/video.mp4".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         };
         assert_eq!(source.to_display(), "Unknown quality");
     }
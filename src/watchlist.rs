@@ -0,0 +1,489 @@
+//! Watchlist of shows being watched or planned.
+//!
+//! Unlike the watch history, which only records what was played, the
+//! watchlist holds any show with a status and a priority. It is kept in
+//! `watchlist.json` next to the history; notes live with the show's score
+//! and tags in the annotations.
+
+use crate::annotations::Annotations;
+use crate::history::WatchRecord;
+use crate::paths;
+use crate::persist;
+use crate::types::Show;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where a show stands on the watchlist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchStatus {
+    Watching,
+    Planned,
+    Completed,
+    OnHold,
+    Dropped,
+}
+
+impl WatchStatus {
+    /// Every status, in display order.
+    pub const ALL: [WatchStatus; 5] = [
+        WatchStatus::Watching,
+        WatchStatus::Planned,
+        WatchStatus::Completed,
+        WatchStatus::OnHold,
+        WatchStatus::Dropped,
+    ];
+
+    /// The next status in the cycle.
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|s| *s == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// Label shown in the TUI.
+    pub fn label(self) -> &'static str {
+        match self {
+            WatchStatus::Watching => "watching",
+            WatchStatus::Planned => "planned",
+            WatchStatus::Completed => "completed",
+            WatchStatus::OnHold => "on hold",
+            WatchStatus::Dropped => "dropped",
+        }
+    }
+
+    /// The status matching an AniList list status, such as `PLANNING`.
+    pub fn from_anilist(status: &str) -> Option<Self> {
+        match status {
+            "CURRENT" | "REPEATING" => Some(WatchStatus::Watching),
+            "PLANNING" => Some(WatchStatus::Planned),
+            "COMPLETED" => Some(WatchStatus::Completed),
+            "PAUSED" => Some(WatchStatus::OnHold),
+            "DROPPED" => Some(WatchStatus::Dropped),
+            _ => None,
+        }
+    }
+}
+
+/// How soon a show should be watched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    /// The next priority in the cycle.
+    pub fn next(self) -> Self {
+        match self {
+            Priority::Low => Priority::Normal,
+            Priority::Normal => Priority::High,
+            Priority::High => Priority::Low,
+        }
+    }
+
+    /// Label shown in the TUI.
+    pub fn label(self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+        }
+    }
+}
+
+/// A show on the watchlist.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WatchlistEntry {
+    /// Unique identifier for the show.
+    pub show_id: String,
+    /// Display name of the show.
+    pub show_name: String,
+    /// English title, if known.
+    #[serde(default)]
+    pub english_name: Option<String>,
+    /// Original-language title, if known.
+    #[serde(default)]
+    pub native_name: Option<String>,
+    /// Where the show stands.
+    pub status: WatchStatus,
+    /// How soon it should be watched.
+    #[serde(default)]
+    pub priority: Priority,
+    /// Notes saved here by earlier versions, kept until
+    /// [`Watchlist::move_notes`] moves them to the annotations.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub notes: String,
+    /// Unix timestamp of when the show was added.
+    pub added_at: u64,
+    /// Unix timestamp of the last change.
+    pub updated_at: u64,
+}
+
+impl WatchlistEntry {
+    /// The show, for loading its episodes.
+    pub fn to_show(&self) -> Show {
+        Show {
+            id: self.show_id.clone(),
+            name: self.show_name.clone(),
+            english_name: self.english_name.clone(),
+            native_name: self.native_name.clone(),
+            available_episodes: 0,
+        }
+    }
+}
+
+/// Current Unix timestamp.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// All shows on the watchlist.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Watchlist {
+    /// Map of show_id to watchlist entry.
+    pub shows: HashMap<String, WatchlistEntry>,
+    /// Shows removed from the watchlist, with the Unix timestamp of removal.
+    ///
+    /// Kept so other instances don't save removed shows back.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub removed: HashMap<String, u64>,
+}
+
+impl Watchlist {
+    /// Create a new empty watchlist.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the path to the watchlist, next to the watch history.
+    pub fn get_watchlist_path() -> Result<PathBuf, io::Error> {
+        Ok(paths::profile_data_dir()?.join("watchlist.json"))
+    }
+
+    /// Load the watchlist from disk.
+    ///
+    /// Returns an empty watchlist if the file doesn't exist.
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        Self::load_from(&Self::get_watchlist_path()?)
+    }
+
+    /// Load the watchlist from a specific file.
+    pub fn load_from(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(persist::load_json(path)?.unwrap_or_else(Self::new))
+    }
+
+    /// Find the newest backup of a watchlist that can be parsed.
+    pub fn newest_backup(path: &Path) -> Option<(PathBuf, Self)> {
+        persist::newest_json_backup(path)
+    }
+
+    /// Save the watchlist to disk.
+    ///
+    /// Changes saved by other running instances since this watchlist was
+    /// loaded are merged in rather than overwritten.
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        self.save_to(&Self::get_watchlist_path()?)
+    }
+
+    /// Save the watchlist to a specific file, merging with its contents.
+    pub fn save_to(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        persist::save_json(path, self, Self::merge)
+    }
+
+    /// Merge another watchlist into this one.
+    ///
+    /// For shows in both, the more recently changed entry wins, and shows
+    /// removed since they were last changed are left out.
+    pub fn merge(&mut self, other: &Watchlist) {
+        for (show_id, theirs) in &other.shows {
+            match self.shows.get(show_id) {
+                Some(ours) if ours.updated_at > theirs.updated_at => {}
+                _ => {
+                    self.shows.insert(show_id.clone(), theirs.clone());
+                }
+            }
+        }
+
+        for (show_id, removed_at) in &other.removed {
+            let entry = self.removed.entry(show_id.clone()).or_insert(*removed_at);
+            *entry = (*entry).max(*removed_at);
+        }
+        let removed = &self.removed;
+        self.shows.retain(|show_id, entry| {
+            removed
+                .get(show_id)
+                .is_none_or(|removed_at| entry.updated_at > *removed_at)
+        });
+    }
+
+    /// Move notes saved on the watchlist by earlier versions to the
+    /// annotations, after any notes already there.
+    ///
+    /// Returns whether any notes were moved.
+    pub fn move_notes(&mut self, annotations: &mut Annotations) -> bool {
+        let mut moved = false;
+        for entry in self.shows.values_mut() {
+            if entry.notes.is_empty() {
+                continue;
+            }
+            let notes = std::mem::take(&mut entry.notes);
+            annotations.update(&entry.show_id, &entry.show_name, |a| {
+                if a.notes.is_empty() {
+                    a.notes = notes;
+                } else if !a.notes.contains(&notes) {
+                    a.notes = format!("{} {}", a.notes, notes);
+                }
+            });
+            moved = true;
+        }
+        moved
+    }
+
+    /// The entry of a show, if it is on the watchlist.
+    pub fn get(&self, show_id: &str) -> Option<&WatchlistEntry> {
+        self.shows.get(show_id)
+    }
+
+    /// Add a show with a status.
+    ///
+    /// Returns false, leaving the entry alone, if the show is already on
+    /// the watchlist.
+    pub fn add(&mut self, show: &Show, status: WatchStatus) -> bool {
+        if self.shows.contains_key(&show.id) {
+            return false;
+        }
+        let timestamp = now();
+        self.shows.insert(
+            show.id.clone(),
+            WatchlistEntry {
+                show_id: show.id.clone(),
+                show_name: show.name.clone(),
+                english_name: show.english_name.clone(),
+                native_name: show.native_name.clone(),
+                status,
+                priority: Priority::default(),
+                notes: String::new(),
+                added_at: timestamp,
+                updated_at: timestamp,
+            },
+        );
+        true
+    }
+
+    /// Remove a show. Returns whether it was on the watchlist.
+    pub fn remove(&mut self, show_id: &str) -> bool {
+        if self.shows.remove(show_id).is_none() {
+            return false;
+        }
+        self.removed.insert(show_id.to_string(), now());
+        true
+    }
+
+    /// Change an entry, updating its timestamp. Returns whether the show
+    /// is on the watchlist.
+    pub fn update(&mut self, show_id: &str, change: impl FnOnce(&mut WatchlistEntry)) -> bool {
+        let Some(entry) = self.shows.get_mut(show_id) else {
+            return false;
+        };
+        change(entry);
+        entry.updated_at = now();
+        true
+    }
+
+    /// Mark a show completed once the last of the `available` episodes has
+    /// been watched in `record`.
+    ///
+    /// Returns whether the status changed.
+    pub fn complete_if_finished(&mut self, record: &WatchRecord, available: &[i64]) -> bool {
+        let Some(last) = available.iter().copied().max() else {
+            return false;
+        };
+        let finished = record.is_watched(last);
+        let show_id = &record.show_id;
+        match self.get(show_id) {
+            Some(entry) if finished && entry.status != WatchStatus::Completed => {
+                self.update(show_id, |entry| entry.status = WatchStatus::Completed)
+            }
+            _ => false,
+        }
+    }
+
+    /// Entries with a status (or all of them), by status, then highest
+    /// priority, then name.
+    pub fn sorted(&self, status: Option<WatchStatus>) -> Vec<&WatchlistEntry> {
+        let mut entries: Vec<&WatchlistEntry> = self
+            .shows
+            .values()
+            .filter(|e| status.is_none_or(|s| e.status == s))
+            .collect();
+        entries.sort_by_key(|e| {
+            (
+                WatchStatus::ALL.iter().position(|s| *s == e.status),
+                std::cmp::Reverse(e.priority),
+                e.show_name.to_lowercase(),
+            )
+        });
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::WatchHistory;
+    use std::fs;
+
+    fn show(id: &str, name: &str) -> Show {
+        Show {
+            id: id.to_string(),
+            name: name.to_string(),
+            english_name: None,
+            native_name: None,
+            available_episodes: 3,
+        }
+    }
+
+    #[test]
+    fn test_add_and_update() {
+        let mut watchlist = Watchlist::new();
+        assert!(watchlist.add(&show("a", "Frieren"), WatchStatus::Planned));
+        assert!(!watchlist.add(&show("a", "Frieren"), WatchStatus::Watching));
+        assert_eq!(watchlist.get("a").unwrap().status, WatchStatus::Planned);
+
+        assert!(watchlist.update("a", |e| e.priority = Priority::High));
+        assert!(!watchlist.update("b", |e| e.status = WatchStatus::Dropped));
+        let entry = watchlist.get("a").unwrap();
+        assert_eq!(entry.priority, Priority::High);
+
+        assert!(watchlist.remove("a"));
+        assert!(watchlist.get("a").is_none());
+    }
+
+    #[test]
+    fn test_sorted() {
+        let mut watchlist = Watchlist::new();
+        watchlist.add(&show("a", "Bocchi"), WatchStatus::Planned);
+        watchlist.add(&show("b", "Apothecary"), WatchStatus::Planned);
+        watchlist.add(&show("c", "Frieren"), WatchStatus::Watching);
+        watchlist.add(&show("d", "Mushishi"), WatchStatus::Dropped);
+        watchlist.update("a", |e| e.priority = Priority::High);
+
+        let ids = |entries: Vec<&WatchlistEntry>| -> Vec<String> {
+            entries.iter().map(|e| e.show_id.clone()).collect()
+        };
+        assert_eq!(ids(watchlist.sorted(None)), vec!["c", "a", "b", "d"]);
+        assert_eq!(
+            ids(watchlist.sorted(Some(WatchStatus::Planned))),
+            vec!["a", "b"]
+        );
+        assert!(watchlist.sorted(Some(WatchStatus::OnHold)).is_empty());
+    }
+
+    #[test]
+    fn test_complete_if_finished() {
+        let mut watchlist = Watchlist::new();
+        watchlist.add(&show("a", "Frieren"), WatchStatus::Watching);
+        let mut history = WatchHistory::new();
+        history.update("a", "Frieren", 2, "sub");
+
+        let record = history.get_record("a", "sub").unwrap();
+        assert!(!watchlist.complete_if_finished(record, &[1, 2, 3]));
+        assert!(watchlist.complete_if_finished(record, &[1, 2]));
+        assert_eq!(watchlist.get("a").unwrap().status, WatchStatus::Completed);
+        assert!(!watchlist.complete_if_finished(record, &[1, 2]));
+    }
+
+    #[test]
+    fn test_status_cycle_and_anilist() {
+        assert_eq!(WatchStatus::Dropped.next(), WatchStatus::Watching);
+        assert_eq!(
+            WatchStatus::from_anilist("PLANNING"),
+            Some(WatchStatus::Planned)
+        );
+        assert_eq!(
+            WatchStatus::from_anilist("PAUSED"),
+            Some(WatchStatus::OnHold)
+        );
+        assert_eq!(WatchStatus::from_anilist("UNKNOWN"), None);
+    }
+
+    #[test]
+    fn test_move_notes() {
+        let path =
+            std::env::temp_dir().join(format!("aw-watchlist-notes-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{"shows": {
+                "a": {"show_id": "a", "show_name": "Frieren", "status": "watching",
+                      "priority": "high", "notes": "with friends",
+                      "added_at": 1000, "updated_at": 1000},
+                "b": {"show_id": "b", "show_name": "Bocchi", "status": "planned",
+                      "added_at": 1000, "updated_at": 1000}
+            }}"#,
+        )
+        .unwrap();
+        let mut watchlist = Watchlist::load_from(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let mut annotations = Annotations::new();
+        annotations.update("a", "Frieren", |a| a.notes = "Episode 10!".to_string());
+        assert!(watchlist.move_notes(&mut annotations));
+        assert_eq!(
+            annotations.get("a").unwrap().notes,
+            "Episode 10! with friends"
+        );
+        assert!(annotations.get("b").is_none());
+        assert!(watchlist.get("a").unwrap().notes.is_empty());
+        assert!(!watchlist.move_notes(&mut annotations));
+    }
+
+    #[test]
+    fn test_status_format() {
+        let mut watchlist = Watchlist::new();
+        watchlist.add(&show("a", "Frieren"), WatchStatus::OnHold);
+
+        let content = serde_json::to_string(&watchlist).unwrap();
+        assert!(content.contains("\"on_hold\""));
+        let loaded: Watchlist = serde_json::from_str(&content).unwrap();
+        assert_eq!(loaded, watchlist);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut saved = Watchlist::new();
+        saved.add(&show("a", "Frieren"), WatchStatus::Planned);
+        saved.add(&show("b", "Bocchi"), WatchStatus::Watching);
+        saved.add(&show("c", "Dandadan"), WatchStatus::Planned);
+
+        // Another instance changed one show and removed another
+        let mut ours = saved.clone();
+        let entry = ours.shows.get_mut("a").unwrap();
+        entry.status = WatchStatus::Watching;
+        entry.updated_at += 10;
+        ours.remove("b");
+        // while this one was loaded before a show was added
+        saved.add(&show("d", "Mushishi"), WatchStatus::Planned);
+
+        saved.merge(&ours);
+        assert_eq!(saved.get("a").unwrap().status, WatchStatus::Watching);
+        assert!(saved.get("b").is_none());
+        assert!(saved.get("c").is_some());
+        assert!(saved.get("d").is_some());
+
+        // Merging an older copy doesn't bring the removed show back
+        let mut older = Watchlist::new();
+        older.add(&show("b", "Bocchi"), WatchStatus::Watching);
+        older.shows.get_mut("b").unwrap().updated_at -= 10;
+        saved.merge(&older);
+        assert!(saved.get("b").is_none());
+    }
+}
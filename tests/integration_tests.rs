@@ -5,7 +5,8 @@
 
 use anime_watcher::api::Provider;
 use anime_watcher::config::Config;
-use anime_watcher::history::WatchHistory;
+use anime_watcher::history::{WatchHistory, HISTORY_VERSION};
+use anime_watcher::persist::NewerFormat;
 use anime_watcher::types::{Episode, Show, StreamSource};
 
 /// Test that shows can be created and displayed correctly.
@@ -14,6 +15,8 @@ fn test_show_display_integration() {
     let show = Show {
         id: "test-123".to_string(),
         name: "Test Anime".to_string(),
+        english_name: None,
+        native_name: None,
         available_episodes: 24,
     };
 
@@ -37,18 +40,24 @@ fn test_episode_display_integration() {
 /// Test stream source quality display.
 #[test]
 fn test_stream_source_quality_integration() {
-    let sources = vec![
+    let sources = [
         StreamSource {
             quality: 1080,
             url: "http://example.com/1080p".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         },
         StreamSource {
             quality: 720,
             url: "http://example.com/720p".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         },
         StreamSource {
             quality: 0,
             url: "http://example.com/unknown".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         },
     ];
 
@@ -82,7 +91,7 @@ fn test_provider_priority_ordering() {
 /// Test that provider sorting works correctly.
 #[test]
 fn test_provider_sorting() {
-    let mut providers = vec![
+    let mut providers = [
         Provider::from_name("Unknown"),
         Provider::from_name("Fm-Hls"),
         Provider::from_name("Mp4"),
@@ -146,7 +155,7 @@ fn test_watch_history_sorting() {
 /// Test episode filtering logic.
 #[test]
 fn test_episode_number_matching() {
-    let episodes = vec![
+    let episodes = [
         Episode {
             id: "1".to_string(),
             number: 1,
@@ -188,23 +197,29 @@ fn test_episode_number_matching() {
 /// Test quality selection logic (best quality).
 #[test]
 fn test_quality_selection_best() {
-    let sources = vec![
+    let sources = [
         StreamSource {
             quality: 480,
             url: "480p".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         },
         StreamSource {
             quality: 1080,
             url: "1080p".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         },
         StreamSource {
             quality: 720,
             url: "720p".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         },
     ];
 
     let mut known: Vec<_> = sources.iter().filter(|s| s.quality > 0).collect();
-    known.sort_by(|a, b| b.quality.cmp(&a.quality));
+    known.sort_by_key(|s| std::cmp::Reverse(s.quality));
 
     // Best should be 1080p
     assert_eq!(known[0].quality, 1080);
@@ -213,23 +228,29 @@ fn test_quality_selection_best() {
 /// Test quality selection logic (worst quality).
 #[test]
 fn test_quality_selection_worst() {
-    let sources = vec![
+    let sources = [
         StreamSource {
             quality: 480,
             url: "480p".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         },
         StreamSource {
             quality: 1080,
             url: "1080p".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         },
         StreamSource {
             quality: 720,
             url: "720p".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         },
     ];
 
     let mut known: Vec<_> = sources.iter().filter(|s| s.quality > 0).collect();
-    known.sort_by(|a, b| b.quality.cmp(&a.quality));
+    known.sort_by_key(|s| std::cmp::Reverse(s.quality));
 
     // Worst should be 480p
     assert_eq!(known.last().unwrap().quality, 480);
@@ -238,18 +259,24 @@ fn test_quality_selection_worst() {
 /// Test specific quality selection.
 #[test]
 fn test_quality_selection_specific() {
-    let sources = vec![
+    let sources = [
         StreamSource {
             quality: 480,
             url: "480p".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         },
         StreamSource {
             quality: 1080,
             url: "1080p".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         },
         StreamSource {
             quality: 720,
             url: "720p".to_string(),
+            provider: String::new(),
+            subtitles: Vec::new(),
         },
     ];
 
@@ -260,6 +287,118 @@ fn test_quality_selection_specific() {
     assert_eq!(found.unwrap().quality, 720);
 }
 
+/// Path of a history file saved by an earlier version, in `tests/fixtures/history`.
+fn history_fixture(name: &str) -> std::path::PathBuf {
+    std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/history")
+        .join(name)
+}
+
+/// Test that history files in every earlier format are migrated on load.
+#[test]
+fn test_history_migrates_every_version() {
+    for name in [
+        "v0-original.json",
+        "v0-watched.json",
+        "v0-positions.json",
+        "v0-edits.json",
+        "v0-sub-dub.json",
+        "v0-durations.json",
+        "v1.json",
+    ] {
+        let history = WatchHistory::load_from(&history_fixture(name))
+            .unwrap_or_else(|e| panic!("{}: {}", name, e));
+        let record = history
+            .get_record("frieren", "sub")
+            .unwrap_or_else(|| panic!("{}: record not rekeyed", name));
+        assert_eq!(record.show_name, "Sousou no Frieren", "{}", name);
+        assert_eq!(record.timestamp, 1700000000, "{}", name);
+        assert!(record.is_watched(7), "{}: watched episodes lost", name);
+        assert!(
+            history.records.keys().all(|key| key.contains(':')),
+            "{}",
+            name
+        );
+        assert!(
+            history.removed.keys().all(|key| key.contains(':')),
+            "{}",
+            name
+        );
+    }
+
+    // Fields are kept from the version that introduced them on
+    let original = WatchHistory::load_from(&history_fixture("v0-original.json")).unwrap();
+    assert_eq!(
+        original.get_record("frieren", "sub").unwrap().watched.len(),
+        1
+    );
+
+    let positions = WatchHistory::load_from(&history_fixture("v0-positions.json")).unwrap();
+    let record = positions.get_record("frieren", "sub").unwrap();
+    assert_eq!(record.watched.len(), 3);
+    assert_eq!(record.resume_position(8), Some(612.5));
+
+    let edits = WatchHistory::load_from(&history_fixture("v0-edits.json")).unwrap();
+    assert_eq!(
+        edits.get_record("frieren", "sub").unwrap().edited,
+        1699990000
+    );
+    assert!(edits.removed.contains_key("bocchi:sub"));
+    assert!(edits.removed.contains_key("bocchi:dub"));
+
+    let sub_dub = WatchHistory::load_from(&history_fixture("v0-sub-dub.json")).unwrap();
+    assert_eq!(sub_dub.get_record("frieren", "dub").unwrap().episode, 2);
+
+    let current = WatchHistory::load_from(&history_fixture("v1.json")).unwrap();
+    let record = current.get_record("frieren", "sub").unwrap();
+    assert_eq!(record.durations.get(&8), Some(&1436.2));
+}
+
+/// Test that a migrated history is saved in the current format.
+#[test]
+fn test_history_saves_current_version() {
+    let path = std::env::temp_dir().join(format!("aw-migrate-{}.json", std::process::id()));
+    std::fs::copy(history_fixture("v0-original.json"), &path).unwrap();
+
+    let history = WatchHistory::load_from(&path).unwrap();
+    history.save_to(&path).unwrap();
+
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved["version"], HISTORY_VERSION);
+    assert!(saved["records"]["frieren:sub"]["watched"]["7"].is_u64());
+
+    let mut leftovers = anime_watcher::persist::backups(&path);
+    leftovers.extend([path.clone(), path.with_extension("json.lock")]);
+    for leftover in leftovers {
+        let _ = std::fs::remove_file(leftover);
+    }
+}
+
+/// Test that a history from a newer version is neither read nor overwritten.
+#[test]
+fn test_history_from_newer_version() {
+    let fixture = history_fixture("v99-future.json");
+    let err = WatchHistory::load_from(&fixture).unwrap_err();
+    let newer = err.downcast_ref::<NewerFormat>().unwrap();
+    assert_eq!(newer.version, 99);
+    assert_eq!(newer.supported, HISTORY_VERSION);
+
+    let path = std::env::temp_dir().join(format!("aw-future-{}.json", std::process::id()));
+    std::fs::copy(&fixture, &path).unwrap();
+    assert!(WatchHistory::new().save_to(&path).is_err());
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        std::fs::read_to_string(&fixture).unwrap()
+    );
+
+    let mut leftovers = anime_watcher::persist::backups(&path);
+    leftovers.extend([path.clone(), path.with_extension("json.lock")]);
+    for leftover in leftovers {
+        let _ = std::fs::remove_file(leftover);
+    }
+}
+
 /// Test that URL decoding handles various cases.
 #[test]
 fn test_url_decoding_integration() {